@@ -4,11 +4,18 @@
  */
 
 use async_trait::async_trait;
+use int_math::UVec2;
 use log::info;
 use std::fmt::Debug;
+#[cfg(feature = "hot-reload")]
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Instant;
+use swamp_render::post_process::PostProcess;
 use swamp_render::Render;
-use swamp_wgpu_window::WgpuWindow;
+use swamp_wgpu::render_graph::{ColorTarget, RenderGraph, RenderPassDesc, TransientTextureDesc};
+use swamp_wgpu_sprites::{SPRITE_FRAGMENT_SHADER_SOURCE, SPRITE_VERTEX_SHADER_SOURCE};
+use swamp_wgpu_window::{WgpuWindow, DEFAULT_CLEAR_COLOR};
 use swamp_window::AppHandler;
 use winit::dpi;
 use winit::window::Window;
@@ -17,15 +24,25 @@ pub trait Application: Debug {
     fn init(&mut self, render: &mut Render);
     fn tick(&mut self);
     fn render(&mut self, render: &mut Render);
+
+    /// Called once after `init`, add post process effects here.
+    fn init_post_process(&mut self, _post_process: &mut PostProcess) {}
+
+    /// Called every frame before rendering, e.g. to animate effect parameters.
+    fn update_post_process(&mut self, _post_process: &mut PostProcess) {}
 }
 
 #[derive(Debug)]
 pub struct App<'a> {
     main_render: Option<Render>,
+    post_process: Option<PostProcess>,
+    start_time: Instant,
     wgpu_window: Option<WgpuWindow<'a>>,
     app: &'a mut dyn Application,
     #[allow(unused)]
     title: String,
+    #[cfg(feature = "hot-reload")]
+    shader_paths: Option<(PathBuf, PathBuf)>,
 }
 
 #[async_trait(?Send)]
@@ -34,76 +51,125 @@ impl<'a> AppHandler for App<'a> {
         info!("create window!");
         let wgpu_window = pollster::block_on(WgpuWindow::new(window)).expect("REASON");
 
-        let vertex_shader_source = "
-// Define a structure to hold the vertex output
-struct VertexOutput {
-    @builtin(position) position: vec4<f32>, // Clip space position
-    @location(1) tex_coords: vec2<f32>,      // Texture coordinates
-};
-
-@vertex
-fn vs_main(
-    @location(0) position: vec2<f32>,  // Input vertex position
-    @location(1) tex_coords: vec2<f32> // Input texture coordinates
-) -> VertexOutput {
-    // Create the output structure
-    var output: VertexOutput;
-
-    // Set the clip space position
-    output.position = vec4<f32>(position, 0.0, 1.0); // Convert 2D position to 4D
-    // Pass through the texture coordinates
-    output.tex_coords = tex_coords;
-
-    return output; // Return the output structure
-}
-        ";
-
-        let fragment_shader_source = "
-@group(0) @binding(0) var texture: texture_2d<f32>;
-@group(0) @binding(1) var texture_sampler: sampler;
-
-@fragment
-fn fs_main(@location(1) tex_coords: vec2<f32>) -> @location(0) vec4<f32> {
-    // Sample the texture with nearest filtering for hard pixel edges
-    return textureSample(texture, texture_sampler, tex_coords);
-}
+        self.main_render = Some(self.create_render(&wgpu_window));
+        let surface_config = wgpu_window.surface_config();
+        self.main_render.as_mut().unwrap().set_viewport(UVec2::new(
+            surface_config.width as u16,
+            surface_config.height as u16,
+        ));
 
-";
-        self.main_render = Some(Render::new(
+        self.post_process = Some(PostProcess::new(
             Arc::clone(wgpu_window.device()),
             Arc::clone(wgpu_window.queue()),
             wgpu_window.surface_config().format,
-            vertex_shader_source,
-            fragment_shader_source,
         ));
 
         self.wgpu_window = Some(wgpu_window);
 
-        self.app.init(self.main_render.as_mut().unwrap())
+        self.app.init(self.main_render.as_mut().unwrap());
+        self.app
+            .init_post_process(self.post_process.as_mut().unwrap());
     }
 
     fn resized(&mut self, physical_size: dpi::PhysicalSize<u32>) {
         info!("resized!");
         self.wgpu_window.as_mut().unwrap().resize(physical_size);
+        if let Some(main_render) = self.main_render.as_mut() {
+            main_render.set_viewport(UVec2::new(
+                physical_size.width as u16,
+                physical_size.height as u16,
+            ));
+        }
     }
     fn redraw(&mut self) {
         let main_render = self.main_render.as_mut().expect("REASON");
         self.app.render(main_render);
+
+        let post_process = self.post_process.as_mut().expect("REASON");
+        post_process.set_time(self.start_time.elapsed().as_secs_f32());
+        self.app.update_post_process(post_process);
+
+        let mut graph = RenderGraph::new();
+        let sprites_target = if post_process.has_enabled_effects() {
+            ColorTarget::Texture(graph.create_texture(TransientTextureDesc::surface_sized()))
+        } else {
+            ColorTarget::Surface
+        };
+
+        let mut sprites_pass = RenderPassDesc::new("sprites", sprites_target);
+        sprites_pass.clear = Some(DEFAULT_CLEAR_COLOR);
+        graph.add_render_pass(sprites_pass, |render_pass, _| {
+            main_render.render(render_pass)
+        });
+
+        if let ColorTarget::Texture(scene) = sprites_target {
+            post_process.add_to_graph(&mut graph, scene, ColorTarget::Surface);
+        }
+
         self.wgpu_window
             .as_mut()
             .unwrap()
-            .render(|render_pass, _, _| main_render.render(render_pass))
+            .render_graph(graph)
             .expect("TODO: panic message");
     }
 }
 
 impl<'a> App<'a> {
+    #[cfg(not(feature = "hot-reload"))]
+    fn create_render(&self, wgpu_window: &WgpuWindow) -> Render {
+        Render::new(
+            Arc::clone(wgpu_window.device()),
+            Arc::clone(wgpu_window.queue()),
+            wgpu_window.surface_config().format,
+            SPRITE_VERTEX_SHADER_SOURCE,
+            SPRITE_FRAGMENT_SHADER_SOURCE,
+        )
+        .unwrap_or_else(|err| panic!("built-in sprite shaders are invalid: {err}"))
+    }
+
+    #[cfg(feature = "hot-reload")]
+    fn create_render(&self, wgpu_window: &WgpuWindow) -> Render {
+        let device = Arc::clone(wgpu_window.device());
+        let queue = Arc::clone(wgpu_window.queue());
+        let format = wgpu_window.surface_config().format;
+        match &self.shader_paths {
+            Some((vertex_path, fragment_path)) => {
+                Render::new_from_shader_paths(device, queue, format, vertex_path, fragment_path)
+                    .unwrap_or_else(|err| panic!("could not load sprite shaders: {err}"))
+            }
+            None => Render::new(
+                device,
+                queue,
+                format,
+                SPRITE_VERTEX_SHADER_SOURCE,
+                SPRITE_FRAGMENT_SHADER_SOURCE,
+            )
+            .unwrap_or_else(|err| panic!("built-in sprite shaders are invalid: {err}")),
+        }
+    }
+
+    /// Loads the sprite shaders from these files instead of the built-in ones,
+    /// and reloads them when they change on disk.
+    #[cfg(feature = "hot-reload")]
+    pub fn with_shader_paths(
+        mut self,
+        vertex_shader_path: impl Into<PathBuf>,
+        fragment_shader_path: impl Into<PathBuf>,
+    ) -> Self {
+        self.shader_paths = Some((vertex_shader_path.into(), fragment_shader_path.into()));
+        self
+    }
+
     pub fn new(title: &str, app: &'a mut impl Application) -> Self {
         Self {
             main_render: None,
+            post_process: None,
+            start_time: Instant::now(),
             title: title.into(),
             app,
             wgpu_window: None,
+            #[cfg(feature = "hot-reload")]
+            shader_paths: None,
         }
     }
 
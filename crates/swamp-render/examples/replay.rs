@@ -0,0 +1,104 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+//! Replays a recording from `Render::start_recording` without a window, and saves every
+//! frame as a PNG.
+//!
+//! ```text
+//! cargo run -p swamp-render --example replay -- <recording> [output directory] [asset directory]
+//! ```
+//!
+//! The asset directory is searched for textures that are not found at their recorded path.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use swamp_render::recording::Recording;
+use swamp_render::Render;
+use swamp_wgpu::headless::{self, HeadlessTarget};
+use swamp_wgpu_sprites::{SPRITE_FRAGMENT_SHADER_SOURCE, SPRITE_VERTEX_SHADER_SOURCE};
+
+const TARGET_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args_os().skip(1);
+    let Some(recording_path) = args.next().map(PathBuf::from) else {
+        eprintln!("usage: replay <recording> [output directory] [asset directory]");
+        std::process::exit(2);
+    };
+    let output_dir = args
+        .next()
+        .map_or_else(|| PathBuf::from("."), PathBuf::from);
+    let asset_dir = args.next().map(PathBuf::from);
+
+    let recording = Recording::load(&recording_path)?;
+    let (device, queue) = pollster::block_on(headless::request_device())?;
+    let device = Arc::new(device);
+    let queue = Arc::new(queue);
+    let mut render = Render::new(
+        Arc::clone(&device),
+        Arc::clone(&queue),
+        TARGET_FORMAT,
+        SPRITE_VERTEX_SHADER_SOURCE,
+        SPRITE_FRAGMENT_SHADER_SOURCE,
+    )?;
+    let materials = recording.create_materials(&mut render, asset_dir.as_deref());
+
+    std::fs::create_dir_all(&output_dir)?;
+    let mut target: Option<HeadlessTarget> = None;
+
+    for (index, frame) in recording.frames.iter().enumerate() {
+        let width = u32::from(frame.viewport.x.max(1));
+        let height = u32::from(frame.viewport.y.max(1));
+        // The viewport can change between frames, e.g. when the window was resized
+        if target
+            .as_ref()
+            .is_none_or(|target| target.width() != width || target.height() != height)
+        {
+            target = Some(HeadlessTarget::new(
+                &device,
+                "replay target",
+                width,
+                height,
+                TARGET_FORMAT,
+            ));
+        }
+        let target = target.as_ref().expect("created above");
+
+        frame.submit(&mut render, &materials);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("replay encoder"),
+        });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("replay pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target.view(),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render.render(&mut render_pass);
+        }
+        target.copy_to_readback(&mut encoder);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let pixels = target.read_pixels(&device)?;
+        let image = image::RgbaImage::from_raw(width, height, pixels)
+            .expect("read back pixels match the target size");
+        let path = output_dir.join(format!("frame_{index:05}.png"));
+        image.save(&path)?;
+
+        println!("{}: {:?}", path.display(), render.frame_stats());
+    }
+
+    Ok(())
+}
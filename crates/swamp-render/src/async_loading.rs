@@ -0,0 +1,175 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+use crate::MaterialHandle;
+use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use swamp_wgpu_sprites::texture_formats::{ImageFileFormat, TextureLoadError};
+
+/// What a material shows until its texture has been loaded.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Placeholder {
+    #[default]
+    Checkerboard,
+    Transparent,
+}
+
+impl Placeholder {
+    pub(crate) fn image(self) -> image::RgbaImage {
+        const SIZE: u32 = 8;
+        image::RgbaImage::from_fn(SIZE, SIZE, |x, y| match self {
+            Self::Checkerboard if (x / 4 + y / 4) % 2 == 0 => image::Rgba([255, 0, 255, 255]),
+            Self::Checkerboard => image::Rgba([32, 32, 32, 255]),
+            Self::Transparent => image::Rgba([0, 0, 0, 0]),
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MaterialLoadState {
+    /// The placeholder is shown
+    Loading,
+    Loaded,
+    /// The placeholder is kept
+    Failed,
+}
+
+/// Counts of all [`crate::Render::load_material_async`] requests.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct LoadingProgress {
+    pub requested: usize,
+    pub loaded: usize,
+    pub failed: usize,
+}
+
+impl LoadingProgress {
+    pub fn pending(&self) -> usize {
+        self.requested - self.loaded - self.failed
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pending() == 0
+    }
+
+    /// From 0.0 to 1.0, failed loads count as finished.
+    pub fn fraction(&self) -> f32 {
+        if self.requested == 0 {
+            return 1.0;
+        }
+        (self.loaded + self.failed) as f32 / self.requested as f32
+    }
+}
+
+/// Decoded on a worker thread, ready to be uploaded on the render thread.
+pub(crate) enum DecodedTexture {
+    Rgba(image::RgbaImage),
+    /// Block compressed containers are only parsed when uploaded
+    Encoded(Vec<u8>, ImageFileFormat),
+}
+
+pub(crate) struct LoadedTexture {
+    pub handle: MaterialHandle,
+    /// Canonical path, used for hot reloading
+    pub path: PathBuf,
+    pub result: Result<DecodedTexture, String>,
+}
+
+struct LoadJob {
+    handle: MaterialHandle,
+    path: PathBuf,
+}
+
+/// A small pool of threads that read and decode image files.
+pub(crate) struct TextureLoader {
+    job_sender: Sender<LoadJob>,
+    result_receiver: Receiver<LoadedTexture>,
+    pub progress: LoadingProgress,
+}
+
+impl Debug for TextureLoader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TextureLoader")
+            .field("progress", &self.progress)
+            .finish()
+    }
+}
+
+impl TextureLoader {
+    pub fn new() -> Self {
+        let (job_sender, job_receiver) = channel::<LoadJob>();
+        let (result_sender, result_receiver) = channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let thread_count = std::thread::available_parallelism()
+            .map_or(2, |count| count.get())
+            .clamp(1, 4);
+        for index in 0..thread_count {
+            let job_receiver = Arc::clone(&job_receiver);
+            let result_sender = result_sender.clone();
+            // The workers exit when the loader, and with it the job sender, is dropped
+            std::thread::Builder::new()
+                .name(format!("texture loader {index}"))
+                .spawn(move || loop {
+                    let job = match job_receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => return,
+                    };
+                    let Ok(job) = job else {
+                        return;
+                    };
+                    if result_sender.send(decode_job(job)).is_err() {
+                        return;
+                    }
+                })
+                .expect("could not spawn texture loader thread");
+        }
+
+        Self {
+            job_sender,
+            result_receiver,
+            progress: LoadingProgress::default(),
+        }
+    }
+
+    pub fn request(&mut self, handle: MaterialHandle, path: PathBuf) {
+        self.progress.requested += 1;
+        self.job_sender
+            .send(LoadJob { handle, path })
+            .expect("texture loader threads are running");
+    }
+
+    /// Textures that finished decoding since the last call.
+    pub fn finished(&self) -> Vec<LoadedTexture> {
+        self.result_receiver.try_iter().collect()
+    }
+}
+
+fn decode_job(job: LoadJob) -> LoadedTexture {
+    let path = job.path.canonicalize().unwrap_or(job.path);
+    let result = std::fs::read(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|octets| decode(octets, ImageFileFormat::from_path(&path)));
+
+    LoadedTexture {
+        handle: job.handle,
+        path,
+        result,
+    }
+}
+
+fn decode(octets: Vec<u8>, format: Option<ImageFileFormat>) -> Result<DecodedTexture, String> {
+    let format = format
+        .or_else(|| ImageFileFormat::detect(&octets))
+        .ok_or_else(|| TextureLoadError::UnknownFormat.to_string())?;
+
+    match format {
+        ImageFileFormat::Dds | ImageFileFormat::Ktx2 => Ok(DecodedTexture::Encoded(octets, format)),
+        _ => swamp_wgpu_sprites::texture_formats::decode_image(&octets, Some(format))
+            .map(DecodedTexture::Rgba)
+            .map_err(|err| err.to_string()),
+    }
+}
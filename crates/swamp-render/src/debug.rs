@@ -0,0 +1,323 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+//! Debug overlay that [`crate::Render::render`] draws on top of everything,
+//! see [`crate::Render::set_debug`].
+
+use crate::{FrameStats, MaterialHandle};
+use int_math::{URect, Vec2};
+use swamp_wgpu_sprites::SpriteVertex;
+
+/// Pixels per font pixel of the debug text.
+pub const TEXT_SCALE: f32 = 2.0;
+/// Advance between two lines of debug text, in pixels.
+pub const LINE_HEIGHT: f32 = (GLYPH_HEIGHT + 2) as f32 * TEXT_SCALE;
+
+const GLYPH_WIDTH: u16 = 3;
+const GLYPH_HEIGHT: u16 = 5;
+const GLYPH_ADVANCE: f32 = (GLYPH_WIDTH + 1) as f32 * TEXT_SCALE;
+
+/// Four vertices per quad must fit in `u16` indices.
+const MAX_MESH_VERTICES: usize = u16::MAX as usize - 3;
+
+const BOUNDS_COLOR: [u8; 4] = [64, 255, 64, 255];
+const PIVOT_COLOR: [u8; 4] = [255, 64, 255, 255];
+const ATLAS_RECT_COLOR: [u8; 4] = [255, 255, 0, 255];
+const BACKDROP_COLOR: [u8; 4] = [0, 0, 0, 192];
+const HUD_TEXT_COLOR: [u8; 4] = [255, 255, 255, 255];
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct DebugSettings {
+    /// Outlines each drawn sprite, including its outline and drop shadow
+    pub sprite_bounds: bool,
+    /// Marks the pivot of each drawn sprite, or its lower left corner without a pivot
+    pub pivots: bool,
+    /// Replaces the tint with a color per draw call, so a change of color is a batch break
+    pub batch_colors: bool,
+    pub atlas: Option<AtlasView>,
+    /// The [`FrameStats`] of the previous frame in the upper left corner
+    pub stats_hud: bool,
+}
+
+impl DebugSettings {
+    pub fn is_enabled(&self) -> bool {
+        self.sprite_bounds
+            || self.pivots
+            || self.batch_colors
+            || self.atlas.is_some()
+            || self.stats_hud
+    }
+}
+
+/// Shows the whole texture of a material, with atlas rects outlined.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtlasView {
+    pub material: MaterialHandle,
+    /// Lower left corner in pixels
+    pub position: Vec2,
+    /// Pixels per texel
+    pub scale: f32,
+    /// Outlined, e.g. the frames of a sprite sheet
+    pub rects: Vec<URect>,
+}
+
+impl AtlasView {
+    pub fn new(material: MaterialHandle) -> Self {
+        Self {
+            material,
+            position: Vec2::default(),
+            scale: 1.0,
+            rects: Vec::new(),
+        }
+    }
+}
+
+/// Solid quads that are drawn with a white texture. A new mesh is started before the
+/// vertices no longer fit in `u16` indices.
+#[derive(Debug, Default)]
+pub(crate) struct DebugMesh {
+    meshes: Vec<(Vec<SpriteVertex>, Vec<u16>)>,
+}
+
+impl DebugMesh {
+    pub fn into_meshes(self) -> Vec<(Vec<SpriteVertex>, Vec<u16>)> {
+        self.meshes
+    }
+
+    /// `min` is the lower left and `max` the upper right corner, in pixels.
+    pub fn rect(&mut self, min: [f32; 2], max: [f32; 2], color: [u8; 4]) {
+        if self
+            .meshes
+            .last()
+            .is_none_or(|(vertices, _)| vertices.len() > MAX_MESH_VERTICES)
+        {
+            self.meshes.push((Vec::new(), Vec::new()));
+        }
+        let (vertices, indices) = self.meshes.last_mut().expect("pushed above");
+
+        let first = vertices.len() as u16;
+        for position in [min, [max[0], min[1]], max, [min[0], max[1]]] {
+            vertices.push(SpriteVertex {
+                position,
+                tex_coords: [0.5, 0.5],
+                color,
+            });
+        }
+        indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+
+    /// Lines of `thickness` pixels on the inside of the rect.
+    pub fn outline(&mut self, min: [f32; 2], max: [f32; 2], thickness: f32, color: [u8; 4]) {
+        let inner_min = [min[0] + thickness, min[1] + thickness];
+        let inner_max = [max[0] - thickness, max[1] - thickness];
+        self.rect(min, [max[0], inner_min[1]], color);
+        self.rect([min[0], inner_max[1]], max, color);
+        self.rect([min[0], inner_min[1]], [inner_min[0], inner_max[1]], color);
+        self.rect([inner_max[0], inner_min[1]], [max[0], inner_max[1]], color);
+    }
+
+    pub fn cross(&mut self, center: [f32; 2], radius: f32, color: [u8; 4]) {
+        let [x, y] = center;
+        self.rect([x - radius, y - 0.5], [x + radius, y + 0.5], color);
+        self.rect([x - 0.5, y - radius], [x + 0.5, y - 0.5], color);
+        self.rect([x - 0.5, y + 0.5], [x + 0.5, y + radius], color);
+    }
+
+    /// `position` is the upper left corner of the first line, lines are separated by `\n`.
+    /// Letters are shown in upper case.
+    pub fn text(&mut self, position: [f32; 2], text: &str, color: [u8; 4]) {
+        for (line_index, line) in text.lines().enumerate() {
+            let top = position[1] - line_index as f32 * LINE_HEIGHT;
+            for (char_index, ch) in line.chars().enumerate() {
+                let left = position[0] + char_index as f32 * GLYPH_ADVANCE;
+                self.glyph([left, top], glyph_bits(ch), color);
+            }
+        }
+    }
+
+    fn glyph(&mut self, upper_left: [f32; 2], bits: u16, color: [u8; 4]) {
+        for row in 0..GLYPH_HEIGHT {
+            let row_bits = (bits >> ((GLYPH_HEIGHT - 1 - row) * GLYPH_WIDTH)) & 0b111;
+            let top = upper_left[1] - f32::from(row) * TEXT_SCALE;
+            // Pixels next to each other in a row share a quad
+            let mut column = 0;
+            while column < GLYPH_WIDTH {
+                if row_bits & (0b100 >> column) == 0 {
+                    column += 1;
+                    continue;
+                }
+                let start = column;
+                while column < GLYPH_WIDTH && row_bits & (0b100 >> column) != 0 {
+                    column += 1;
+                }
+                self.rect(
+                    [
+                        upper_left[0] + f32::from(start) * TEXT_SCALE,
+                        top - TEXT_SCALE,
+                    ],
+                    [upper_left[0] + f32::from(column) * TEXT_SCALE, top],
+                    color,
+                );
+            }
+        }
+    }
+}
+
+/// Width and height in pixels of text drawn with [`DebugMesh::text`].
+pub(crate) fn text_size(text: &str) -> [f32; 2] {
+    let columns = text.lines().map(|line| line.chars().count()).max();
+    let line_count = text.lines().count();
+
+    [
+        columns.unwrap_or(0) as f32 * GLYPH_ADVANCE,
+        line_count as f32 * LINE_HEIGHT,
+    ]
+}
+
+/// Draws the stats in the upper left corner of the viewport.
+pub(crate) fn stats_hud(mesh: &mut DebugMesh, stats: &FrameStats, viewport: [f32; 2]) {
+    let text = format!(
+        "SPRITES {} CULLED {}\nBATCHES {} DRAWS {}\nPIPELINES {} MATERIALS {}\nINSTANCES {} MESHES {}\nTEXTURES {}",
+        stats.sprites.drawn,
+        stats.sprites.culled,
+        stats.batches,
+        stats.draw_calls,
+        stats.pipeline_switches,
+        stats.material_switches,
+        kilo_octets(stats.instance_octets),
+        kilo_octets(stats.vertex_octets),
+        kilo_octets(stats.texture_octets),
+    );
+
+    const MARGIN: f32 = 4.0;
+    let [width, height] = text_size(&text);
+    let upper_left = [MARGIN * 2.0, viewport[1] - MARGIN * 2.0];
+    mesh.rect(
+        [MARGIN, viewport[1] - height - MARGIN * 3.0],
+        [width + MARGIN * 3.0, viewport[1] - MARGIN],
+        BACKDROP_COLOR,
+    );
+    mesh.text(upper_left, &text, HUD_TEXT_COLOR);
+}
+
+/// Outlines `rects` of an atlas texture of `texture_size` texels that is shown at `view`.
+pub(crate) fn atlas_outlines(mesh: &mut DebugMesh, view: &AtlasView, texture_size: [f32; 2]) {
+    let [x, y] = [f32::from(view.position.x), f32::from(view.position.y)];
+    let scale = view.scale;
+    // Texture rows go down from the top of the texture
+    let top = y + texture_size[1] * scale;
+
+    mesh.outline(
+        [x - 1.0, y - 1.0],
+        [x + texture_size[0] * scale + 1.0, top + 1.0],
+        1.0,
+        ATLAS_RECT_COLOR,
+    );
+    for rect in &view.rects {
+        let left = x + f32::from(rect.position.x) * scale;
+        let rect_top = top - f32::from(rect.position.y) * scale;
+        mesh.outline(
+            [left, rect_top - f32::from(rect.size.y) * scale],
+            [left + f32::from(rect.size.x) * scale, rect_top],
+            1.0,
+            ATLAS_RECT_COLOR,
+        );
+    }
+}
+
+/// Dark rect behind an atlas texture, so transparent texels can be told apart.
+pub(crate) fn atlas_backdrop(mesh: &mut DebugMesh, view: &AtlasView, texture_size: [f32; 2]) {
+    let [x, y] = [f32::from(view.position.x), f32::from(view.position.y)];
+    mesh.rect(
+        [x, y],
+        [
+            x + texture_size[0] * view.scale,
+            y + texture_size[1] * view.scale,
+        ],
+        BACKDROP_COLOR,
+    );
+}
+
+pub(crate) fn sprite_bounds(mesh: &mut DebugMesh, min: [f32; 2], max: [f32; 2]) {
+    mesh.outline(min, max, 1.0, BOUNDS_COLOR);
+}
+
+pub(crate) fn pivot(mesh: &mut DebugMesh, position: [f32; 2]) {
+    mesh.cross(position, 3.0, PIVOT_COLOR);
+}
+
+/// Colors that are far apart for neighbouring indices.
+pub(crate) fn batch_color(index: usize) -> [u8; 4] {
+    // Steps around the hue circle by the golden ratio
+    let hue = (index as f32 * 0.618_034).fract() * 6.0;
+    let ramp = |offset: f32| {
+        let distance = ((hue - offset).rem_euclid(6.0) - 3.0).abs();
+        ((distance - 1.0).clamp(0.0, 1.0) * 255.0) as u8
+    };
+
+    [ramp(0.0), ramp(2.0), ramp(4.0), 255]
+}
+
+fn kilo_octets(octets: u64) -> String {
+    format!("{} KB", octets.div_ceil(1024))
+}
+
+/// Rows from the top, three bits per row with the leftmost pixel in the highest bit.
+const fn glyph(rows: [u16; 5]) -> u16 {
+    (rows[0] << 12) | (rows[1] << 9) | (rows[2] << 6) | (rows[3] << 3) | rows[4]
+}
+
+fn glyph_bits(ch: char) -> u16 {
+    match ch.to_ascii_uppercase() {
+        ' ' => 0,
+        '0' => glyph([0b111, 0b101, 0b101, 0b101, 0b111]),
+        '1' => glyph([0b010, 0b110, 0b010, 0b010, 0b111]),
+        '2' => glyph([0b111, 0b001, 0b111, 0b100, 0b111]),
+        '3' => glyph([0b111, 0b001, 0b111, 0b001, 0b111]),
+        '4' => glyph([0b101, 0b101, 0b111, 0b001, 0b001]),
+        '5' => glyph([0b111, 0b100, 0b111, 0b001, 0b111]),
+        '6' => glyph([0b111, 0b100, 0b111, 0b101, 0b111]),
+        '7' => glyph([0b111, 0b001, 0b001, 0b001, 0b001]),
+        '8' => glyph([0b111, 0b101, 0b111, 0b101, 0b111]),
+        '9' => glyph([0b111, 0b101, 0b111, 0b001, 0b111]),
+        'A' => glyph([0b010, 0b101, 0b111, 0b101, 0b101]),
+        'B' => glyph([0b110, 0b101, 0b110, 0b101, 0b110]),
+        'C' => glyph([0b011, 0b100, 0b100, 0b100, 0b011]),
+        'D' => glyph([0b110, 0b101, 0b101, 0b101, 0b110]),
+        'E' => glyph([0b111, 0b100, 0b110, 0b100, 0b111]),
+        'F' => glyph([0b111, 0b100, 0b110, 0b100, 0b100]),
+        'G' => glyph([0b011, 0b100, 0b101, 0b101, 0b011]),
+        'H' => glyph([0b101, 0b101, 0b111, 0b101, 0b101]),
+        'I' => glyph([0b111, 0b010, 0b010, 0b010, 0b111]),
+        'J' => glyph([0b001, 0b001, 0b001, 0b101, 0b010]),
+        'K' => glyph([0b101, 0b101, 0b110, 0b101, 0b101]),
+        'L' => glyph([0b100, 0b100, 0b100, 0b100, 0b111]),
+        'M' => glyph([0b101, 0b111, 0b111, 0b101, 0b101]),
+        'N' => glyph([0b110, 0b101, 0b101, 0b101, 0b101]),
+        'O' => glyph([0b010, 0b101, 0b101, 0b101, 0b010]),
+        'P' => glyph([0b110, 0b101, 0b110, 0b100, 0b100]),
+        'Q' => glyph([0b010, 0b101, 0b101, 0b110, 0b011]),
+        'R' => glyph([0b110, 0b101, 0b110, 0b101, 0b101]),
+        'S' => glyph([0b011, 0b100, 0b010, 0b001, 0b110]),
+        'T' => glyph([0b111, 0b010, 0b010, 0b010, 0b010]),
+        'U' => glyph([0b101, 0b101, 0b101, 0b101, 0b111]),
+        'V' => glyph([0b101, 0b101, 0b101, 0b101, 0b010]),
+        'W' => glyph([0b101, 0b101, 0b111, 0b111, 0b101]),
+        'X' => glyph([0b101, 0b101, 0b010, 0b101, 0b101]),
+        'Y' => glyph([0b101, 0b101, 0b010, 0b010, 0b010]),
+        'Z' => glyph([0b111, 0b001, 0b010, 0b100, 0b111]),
+        ':' => glyph([0b000, 0b010, 0b000, 0b010, 0b000]),
+        '.' => glyph([0b000, 0b000, 0b000, 0b000, 0b010]),
+        ',' => glyph([0b000, 0b000, 0b000, 0b010, 0b100]),
+        '/' => glyph([0b001, 0b001, 0b010, 0b100, 0b100]),
+        '-' => glyph([0b000, 0b000, 0b111, 0b000, 0b000]),
+        '+' => glyph([0b000, 0b010, 0b111, 0b010, 0b000]),
+        '=' => glyph([0b000, 0b111, 0b000, 0b111, 0b000]),
+        '%' => glyph([0b101, 0b001, 0b010, 0b100, 0b101]),
+        '(' => glyph([0b010, 0b100, 0b100, 0b100, 0b010]),
+        ')' => glyph([0b010, 0b001, 0b001, 0b001, 0b010]),
+        _ => glyph([0b111, 0b001, 0b010, 0b000, 0b010]),
+    }
+}
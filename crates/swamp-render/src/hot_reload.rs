@@ -0,0 +1,154 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::fmt::{Debug, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use swamp_wgpu::shader_validation::ShaderError;
+
+#[derive(Debug)]
+pub enum HotReloadError {
+    Io(PathBuf, std::io::Error),
+    Watch(notify::Error),
+    Shader(ShaderError),
+}
+
+impl Display for HotReloadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(path, err) => write!(f, "could not read {}: {err}", path.display()),
+            Self::Watch(err) => write!(f, "could not watch file: {err}"),
+            Self::Shader(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for HotReloadError {}
+
+impl From<notify::Error> for HotReloadError {
+    fn from(err: notify::Error) -> Self {
+        Self::Watch(err)
+    }
+}
+
+impl From<ShaderError> for HotReloadError {
+    fn from(err: ShaderError) -> Self {
+        Self::Shader(err)
+    }
+}
+
+pub fn read_source(path: &Path) -> Result<String, HotReloadError> {
+    std::fs::read_to_string(path).map_err(|err| HotReloadError::Io(path.to_path_buf(), err))
+}
+
+/// Watches individual files for changes.
+///
+/// The parent directories are watched instead of the files themselves, since many
+/// editors save by writing a new file and renaming it over the old one.
+pub struct FileWatcher {
+    watcher: RecommendedWatcher,
+    watched_directories: HashSet<PathBuf>,
+    watched_files: HashSet<PathBuf>,
+    receiver: Receiver<PathBuf>,
+}
+
+impl Debug for FileWatcher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FileWatcher")
+            .field("watched_files", &self.watched_files)
+            .finish()
+    }
+}
+
+impl FileWatcher {
+    pub fn new() -> Result<Self, HotReloadError> {
+        let (sender, receiver) = channel();
+        let watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+            if let Ok(event) = result {
+                if event.kind.is_modify() || event.kind.is_create() {
+                    for path in event.paths {
+                        let _ = sender.send(path);
+                    }
+                }
+            }
+        })?;
+
+        Ok(Self {
+            watcher,
+            watched_directories: HashSet::new(),
+            watched_files: HashSet::new(),
+            receiver,
+        })
+    }
+
+    /// Returns the path as it will be reported by [`FileWatcher::changed_files`].
+    pub fn watch(&mut self, path: &Path) -> Result<PathBuf, HotReloadError> {
+        let canonical = path
+            .canonicalize()
+            .map_err(|err| HotReloadError::Io(path.to_path_buf(), err))?;
+        let directory = canonical
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| canonical.clone());
+
+        if self.watched_directories.insert(directory.clone()) {
+            self.watcher
+                .watch(&directory, RecursiveMode::NonRecursive)?;
+        }
+        self.watched_files.insert(canonical.clone());
+
+        Ok(canonical)
+    }
+
+    pub fn unwatch(&mut self, path: &Path) {
+        self.watched_files.remove(path);
+    }
+
+    /// Watched files that changed since the last call, without duplicates.
+    pub fn changed_files(&self) -> Vec<PathBuf> {
+        let mut changed: Vec<PathBuf> = Vec::new();
+        for path in self.receiver.try_iter() {
+            if self.watched_files.contains(&path) && !changed.contains(&path) {
+                changed.push(path);
+            }
+        }
+        changed
+    }
+}
+
+/// The shader source files of the sprite pipeline, see [`crate::Render::new_from_shader_paths`].
+#[derive(Debug)]
+pub struct ShaderHotReload {
+    pub vertex_path: PathBuf,
+    pub fragment_path: PathBuf,
+    pub watcher: FileWatcher,
+}
+
+impl ShaderHotReload {
+    pub fn new(vertex_path: &Path, fragment_path: &Path) -> Result<Self, HotReloadError> {
+        let mut watcher = FileWatcher::new()?;
+        let vertex_path = watcher.watch(vertex_path)?;
+        let fragment_path = watcher.watch(fragment_path)?;
+
+        Ok(Self {
+            vertex_path,
+            fragment_path,
+            watcher,
+        })
+    }
+
+    pub fn has_changed(&self) -> bool {
+        !self.watcher.changed_files().is_empty()
+    }
+
+    pub fn read_sources(&self) -> Result<(String, String), HotReloadError> {
+        Ok((
+            read_source(&self.vertex_path)?,
+            read_source(&self.fragment_path)?,
+        ))
+    }
+}
@@ -3,29 +3,94 @@
  * Licensed under the MIT License. See LICENSE in the project root for license information.
  */
 
+pub mod async_loading;
+pub mod debug;
+#[cfg(feature = "hot-reload")]
+pub mod hot_reload;
+pub mod lighting;
+pub mod material_registry;
+pub mod particles;
+pub mod post_process;
+pub mod recording;
+pub mod shadows;
+pub mod skeleton;
+pub mod trail;
+
+use async_loading::{LoadingProgress, MaterialLoadState, Placeholder, TextureLoader};
+use debug::{DebugMesh, DebugSettings};
 use int_math::{URect, UVec2, Vec2, Vec3};
-use log::info;
-use std::rc::Rc;
+use lighting::{Light, Lighting, LightingSettings, LitMaterialBinding};
+use log::{error, info, warn};
+pub use material_registry::MaterialHandle;
+use material_registry::MaterialRegistry;
+use particles::ParticleEmitter;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use swamp_wgpu_sprites::{FVec4, Mx4, SpriteInfo};
-use wgpu::{BindGroup, BindGroupLayout, RenderPass, RenderPipeline};
+use swamp_wgpu::mipmap::MipmapGenerator;
+use swamp_wgpu::shader_validation::{BindingKind, ExpectedBinding, ShaderError};
+pub use swamp_wgpu::{SamplerAddressMode, SamplerFilter, SamplerOptions, TextureRegionError};
+use swamp_wgpu_sprites::indexed::{IndexedImage, IndexedImageError};
+use swamp_wgpu_sprites::texture_formats::{ImageFileFormat, TextureLoadError};
+pub use swamp_wgpu_sprites::{BlendMode, SpriteVertex};
+use swamp_wgpu_sprites::{FVec4, Mx4, SpriteEffects, SpriteInfo, SpriteInstanceUniform};
+use wgpu::{BindGroup, BindGroupLayout, RenderPass, RenderPipeline, ShaderModule, TextureFormat};
 
 #[derive(Debug)]
 pub struct Render {
     index_buffer: wgpu::Buffer,
     vertex_buffer: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+    mesh_vertex_buffer: wgpu::Buffer,
+    mesh_vertex_capacity: usize,
+    mesh_index_buffer: wgpu::Buffer,
+    mesh_index_capacity: usize,
 
-    sprite_uniform_buffer: wgpu::Buffer,
-    sprite_uniform_bind_group: BindGroup,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: BindGroup,
+    camera_bind_group_layout: BindGroupLayout,
+    viewport: UVec2,
+    /// Skips sprites outside of the viewport before batching
+    culling: bool,
+    frame_stats: FrameStats,
+    debug: DebugSettings,
+    /// White texel for the debug overlay, created on first use
+    debug_material: Option<MaterialHandle>,
+    /// See [`Render::start_recording`]
+    recorder: Option<recording::Recorder>,
 
     device: Arc<wgpu::Device>,
     queue: Arc<wgpu::Queue>, // Queue to talk to device
 
     sprites: Vec<Sprite>,
-    materials: Vec<SpriteMaterialRef>,
+    layer_batches: Vec<LayerBatch>,
+    materials: MaterialRegistry<SpriteMaterial>,
     bind_group_layout: BindGroupLayout,
-    sampler: wgpu::Sampler,
+    samplers: HashMap<SamplerOptions, wgpu::Sampler>,
+    mipmap_generator: MipmapGenerator,
     pipeline: RenderPipelineRef,
+    /// Sprite pipelines for the other blend modes, cleared when the shaders are reloaded
+    blend_pipelines: HashMap<BlendMode, RenderPipelineRef>,
+    /// Mesh pipelines for each blend mode, cleared when the shaders are reloaded
+    mesh_pipelines: HashMap<BlendMode, RenderPipelineRef>,
+    mesh_vertex_shader: ShaderModule,
+    indexed_bind_group_layout: BindGroupLayout,
+    /// Created with the first indexed material
+    indexed_pipeline: Option<RenderPipelineRef>,
+    lighting: Lighting,
+    /// Created with the first lit material
+    lit_pipeline: Option<RenderPipelineRef>,
+    vertex_shader: ShaderModule,
+    fragment_shader: ShaderModule,
+    surface_texture_format: TextureFormat,
+    /// Started by the first [`Render::load_material_async`]
+    texture_loader: Option<TextureLoader>,
+    #[cfg(feature = "hot-reload")]
+    shader_hot_reload: Option<hot_reload::ShaderHotReload>,
+    #[cfg(feature = "hot-reload")]
+    texture_watcher: Option<hot_reload::FileWatcher>,
 }
 
 impl Render {
@@ -35,44 +100,287 @@ impl Render {
         surface_texture_format: wgpu::TextureFormat,
         vertex_shader_source: &str,
         fragment_shader_source: &str,
-    ) -> Self {
+    ) -> Result<Self, ShaderError> {
         let sprite_info = SpriteInfo::new(
             &device,
             surface_texture_format,
             vertex_shader_source,
             fragment_shader_source,
-        );
+        )?;
 
         let index_buffer =
             swamp_wgpu_sprites::create_sprite_index_buffer(&device, "sprite quad index buffer");
         let vertex_buffer =
             swamp_wgpu_sprites::create_sprite_vertex_buffer(&device, "sprite quad vertex buffer");
 
-        let sprite_uniform_buffer =
-            swamp_wgpu::create_uniform_buffer(&device, "uniform bind group");
+        const INITIAL_INSTANCE_CAPACITY: usize = 256;
+        let instance_buffer = swamp_wgpu_sprites::create_sprite_instance_buffer(
+            &device,
+            "sprite instance buffer",
+            INITIAL_INSTANCE_CAPACITY,
+        );
 
-        let sprite_uniform_bind_group_layout =
-            swamp_wgpu::create_uniform_bind_group_layout(&device, "bind group");
+        let camera_buffer = swamp_wgpu::create_uniform_buffer(&device, "camera uniform buffer");
 
-        let sprite_uniform_bind_group = swamp_wgpu::create_uniform_bind_group(
+        let camera_bind_group = swamp_wgpu::create_uniform_bind_group(
             &device,
-            &sprite_uniform_bind_group_layout,
-            &sprite_uniform_buffer,
-            "bind_group",
+            &sprite_info.camera_bind_group_layout,
+            &camera_buffer,
+            "camera bind group",
         );
 
-        Self {
+        const INITIAL_MESH_CAPACITY: usize = 1024;
+        let mesh_vertex_buffer = swamp_wgpu_sprites::create_sprite_mesh_vertex_buffer(
+            &device,
+            "sprite mesh vertex buffer",
+            INITIAL_MESH_CAPACITY,
+        );
+        let mesh_index_buffer = swamp_wgpu_sprites::create_sprite_mesh_index_buffer(
+            &device,
+            "sprite mesh index buffer",
+            INITIAL_MESH_CAPACITY,
+        );
+        let mesh_vertex_shader = swamp_wgpu::shader_validation::create_shader_module_checked(
+            &device,
+            "sprite mesh vertex",
+            swamp_wgpu_sprites::SPRITE_MESH_VERTEX_SHADER_SOURCE,
+            &swamp_wgpu_sprites::sprite_mesh_vertex_interface(),
+        )?;
+
+        let mipmap_generator = MipmapGenerator::new(&device);
+        let lighting = Lighting::new(&device, &queue);
+        let indexed_bind_group_layout =
+            swamp_wgpu_sprites::indexed::create_indexed_bind_group_layout(
+                &device,
+                "indexed material bind group layout",
+            );
+
+        Ok(Self {
             device,
             queue,
             sprites: Vec::new(),
-            materials: Vec::new(),
-            sampler: sprite_info.sampler,
-            pipeline: Rc::new(sprite_info.pipeline),
+            layer_batches: Vec::new(),
+            materials: MaterialRegistry::new(),
+            samplers: HashMap::from([(SamplerOptions::default(), sprite_info.sampler)]),
+            mipmap_generator,
+            pipeline: Arc::new(sprite_info.pipeline),
+            blend_pipelines: HashMap::new(),
+            mesh_pipelines: HashMap::new(),
+            mesh_vertex_shader,
+            indexed_bind_group_layout,
+            indexed_pipeline: None,
+            lighting,
+            lit_pipeline: None,
             bind_group_layout: sprite_info.bind_group_layout,
+            vertex_shader: sprite_info.vertex_shader,
+            fragment_shader: sprite_info.fragment_shader,
+            surface_texture_format,
             index_buffer,
             vertex_buffer,
-            sprite_uniform_buffer,
-            sprite_uniform_bind_group,
+            instance_buffer,
+            instance_capacity: INITIAL_INSTANCE_CAPACITY,
+            mesh_vertex_buffer,
+            mesh_vertex_capacity: INITIAL_MESH_CAPACITY,
+            mesh_index_buffer,
+            mesh_index_capacity: INITIAL_MESH_CAPACITY,
+            camera_buffer,
+            camera_bind_group,
+            camera_bind_group_layout: sprite_info.camera_bind_group_layout,
+            viewport: UVec2::new(0, 0),
+            culling: true,
+            frame_stats: FrameStats::default(),
+            debug: DebugSettings::default(),
+            debug_material: None,
+            recorder: None,
+            texture_loader: None,
+            #[cfg(feature = "hot-reload")]
+            shader_hot_reload: None,
+            #[cfg(feature = "hot-reload")]
+            texture_watcher: None,
+        })
+    }
+
+    /// Loads the sprite shaders from files and recompiles the pipeline when they change.
+    #[cfg(feature = "hot-reload")]
+    pub fn new_from_shader_paths(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        surface_texture_format: wgpu::TextureFormat,
+        vertex_shader_path: &std::path::Path,
+        fragment_shader_path: &std::path::Path,
+    ) -> Result<Self, hot_reload::HotReloadError> {
+        let shader_hot_reload =
+            hot_reload::ShaderHotReload::new(vertex_shader_path, fragment_shader_path)?;
+        let (vertex_shader_source, fragment_shader_source) = shader_hot_reload.read_sources()?;
+
+        let mut render = Self::new(
+            device,
+            queue,
+            surface_texture_format,
+            &vertex_shader_source,
+            &fragment_shader_source,
+        )?;
+        render.shader_hot_reload = Some(shader_hot_reload);
+
+        Ok(render)
+    }
+
+    #[cfg(feature = "hot-reload")]
+    fn reload_changed_shaders(&mut self) {
+        let Some(shader_hot_reload) = &self.shader_hot_reload else {
+            return;
+        };
+        if !shader_hot_reload.has_changed() {
+            return;
+        }
+
+        let result =
+            shader_hot_reload
+                .read_sources()
+                .and_then(|(vertex_source, fragment_source)| {
+                    Ok(self.reload_shaders(&vertex_source, &fragment_source)?)
+                });
+        match result {
+            Ok(()) => info!("reloaded sprite shaders"),
+            Err(err) => error!("keeping previous sprite shaders: {err}"),
+        }
+    }
+
+    /// Recompiles the sprite pipeline. The current pipeline is kept if the shaders are invalid.
+    pub fn reload_shaders(
+        &mut self,
+        vertex_shader_source: &str,
+        fragment_shader_source: &str,
+    ) -> Result<(), ShaderError> {
+        let vertex_shader = swamp_wgpu::shader_validation::create_shader_module_checked(
+            &self.device,
+            "sprite vertex",
+            vertex_shader_source,
+            &swamp_wgpu_sprites::sprite_vertex_interface(),
+        )?;
+        let fragment_shader = swamp_wgpu::shader_validation::create_shader_module_checked(
+            &self.device,
+            "sprite fragment",
+            fragment_shader_source,
+            &swamp_wgpu_sprites::sprite_fragment_interface(swamp_wgpu_sprites::SPRITE_BINDINGS),
+        )?;
+
+        let pipeline_layout = swamp_wgpu::create_pipeline_layout_with_groups(
+            &self.device,
+            "sprite pipeline layout",
+            &[&self.camera_bind_group_layout, &self.bind_group_layout],
+        );
+        let pipeline = swamp_wgpu_sprites::create_sprite_pipeline(
+            &self.device,
+            self.surface_texture_format,
+            &pipeline_layout,
+            &vertex_shader,
+            &fragment_shader,
+        );
+
+        self.pipeline = Arc::new(pipeline);
+        self.blend_pipelines.clear();
+        self.mesh_pipelines.clear();
+        self.vertex_shader = vertex_shader;
+        self.fragment_shader = fragment_shader;
+
+        Ok(())
+    }
+
+    /// Sets the size of the render target in pixels. Sprite positions are in pixels,
+    /// with the origin in the lower left corner.
+    pub fn set_viewport(&mut self, viewport: UVec2) {
+        self.viewport = viewport;
+        let projection =
+            Mx4::from_orthographic(0.0, viewport.x.into(), 0.0, viewport.y.into(), -1.0, 1.0);
+        self.queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[swamp_wgpu::Uniforms {
+                view_proj: projection.to_cols_array_2d(),
+            }]),
+        );
+    }
+
+    pub fn viewport(&self) -> UVec2 {
+        self.viewport
+    }
+
+    /// Sprites outside of the viewport are skipped by default. Nothing is culled until
+    /// [`Render::set_viewport`] has been called.
+    pub fn set_culling(&mut self, enabled: bool) {
+        self.culling = enabled;
+    }
+
+    /// Counted by the last [`Render::render`].
+    pub fn sprite_counters(&self) -> SpriteCounters {
+        self.frame_stats.sprites
+    }
+
+    /// Counted by the last [`Render::render`], with the current texture memory.
+    pub fn frame_stats(&self) -> FrameStats {
+        FrameStats {
+            texture_octets: self.stats().texture_octets,
+            ..self.frame_stats
+        }
+    }
+
+    /// Writes the submitted sprites and the viewport of every [`Render::render`] to a file,
+    /// that can be replayed with [`recording::Recording`]. Replaces an earlier recording.
+    pub fn start_recording(&mut self, path: &Path) -> std::io::Result<()> {
+        self.stop_recording();
+        self.recorder = Some(recording::Recorder::create(path)?);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(mut recorder) = self.recorder.take() {
+            if let Err(err) = recorder.flush() {
+                error!("could not finish the frame recording: {err}");
+            }
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// The overlay is drawn by [`Render::render`] after everything else.
+    pub fn set_debug(&mut self, settings: DebugSettings) {
+        self.debug = settings;
+    }
+
+    pub fn debug(&self) -> &DebugSettings {
+        &self.debug
+    }
+
+    /// Draws text with the built-in debug font this frame, after the sprites with the same
+    /// `z`. `position` is the upper left corner of the first line.
+    pub fn render_debug_text(&mut self, position: Vec2, text: &str, color: [u8; 4], z: i16) {
+        let mut mesh = DebugMesh::default();
+        mesh.text([f32::from(position.x), f32::from(position.y)], text, color);
+
+        let material = self.debug_material();
+        for (vertices, indices) in mesh.into_meshes() {
+            self.layer_batches.push(LayerBatch {
+                material,
+                z,
+                blend: BlendMode::Alpha,
+                geometry: LayerGeometry::Mesh { vertices, indices },
+            });
+        }
+    }
+
+    fn debug_material(&mut self) -> MaterialHandle {
+        match self.debug_material {
+            Some(material) if self.materials.contains(material) => material,
+            _ => {
+                let material = self
+                    .create_material_rgba(1, 1, &[255; 4])
+                    .expect("one pixel fills the debug texture");
+                self.debug_material = Some(material);
+                material
+            }
         }
     }
 
@@ -80,13 +388,13 @@ impl Render {
         &mut self,
         position: Vec3,
         atlas_rect: URect,
-        material: &SpriteMaterialRef,
+        material: MaterialHandle,
         params: SpriteParams,
     ) {
         self.sprites.push(Sprite {
             position,
             atlas_rect,
-            material: Rc::clone(material),
+            material,
             params,
         })
     }
@@ -95,153 +403,2130 @@ impl Render {
         &mut self,
         position: Vec2,
         atlas_rect: URect,
-        material: &SpriteMaterialRef,
+        material: MaterialHandle,
         params: SpriteParams,
     ) {
         self.sprites.push(Sprite {
             position: position.into(),
             atlas_rect,
-            material: Rc::clone(material),
+            material,
             params,
         })
     }
 
+    /// Draws the living particles of the emitter this frame, after the sprites with the same
+    /// `z`. Materials with their own pipeline, like custom or lit ones, ignore the blend mode.
+    pub fn render_particles(
+        &mut self,
+        emitter: &ParticleEmitter,
+        material: MaterialHandle,
+        z: i16,
+    ) {
+        let Some(sprite_material) = self.materials.get(material) else {
+            warn!("skipping particles with destroyed material {material:?}");
+            return;
+        };
+        if emitter.particles().is_empty() {
+            return;
+        }
+
+        let mut instances = Vec::with_capacity(emitter.particles().len());
+        emitter.push_instances(
+            sprite_material.texture.size,
+            sprite_material.load_state == MaterialLoadState::Loaded,
+            &mut instances,
+        );
+        self.layer_batches.push(LayerBatch {
+            material,
+            z,
+            blend: emitter.settings.blend,
+            geometry: LayerGeometry::Instances(instances),
+        });
+    }
+
+    /// Draws the trail this frame, after the sprites with the same `z`. Only materials
+    /// without their own pipeline can be used, since the trail has its own vertex shader.
+    pub fn render_trail(&mut self, trail: &trail::Trail, material: MaterialHandle, z: i16) {
+        let Some(tex_rect) = self.mesh_tex_rect(material, trail.settings.frame) else {
+            return;
+        };
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        trail.build_mesh(tex_rect, &mut vertices, &mut indices);
+        if indices.is_empty() {
+            return;
+        }
+
+        self.layer_batches.push(LayerBatch {
+            material,
+            z,
+            blend: trail.settings.blend,
+            geometry: LayerGeometry::Mesh { vertices, indices },
+        });
+    }
+
+    /// Draws a triangle list this frame, sorted with the sprites like [`Render::render_trail`].
+    /// Vertex texture coordinates span [`MeshParams::source`], or the whole texture.
+    pub fn render_mesh(
+        &mut self,
+        vertices: &[SpriteVertex],
+        indices: &[u16],
+        material: MaterialHandle,
+        params: MeshParams,
+    ) {
+        if !indices.len().is_multiple_of(3) {
+            warn!(
+                "skipping mesh with {} indices, expected whole triangles",
+                indices.len()
+            );
+            return;
+        }
+        if let Some(index) = indices
+            .iter()
+            .find(|index| usize::from(**index) >= vertices.len())
+        {
+            warn!(
+                "skipping mesh with index {index}, it only has {} vertices",
+                vertices.len()
+            );
+            return;
+        }
+        let Some(tex_rect) = self.mesh_tex_rect(material, params.source) else {
+            return;
+        };
+        if indices.is_empty() {
+            return;
+        }
+
+        let offset = [f32::from(params.offset.x), f32::from(params.offset.y)];
+        let vertices = vertices
+            .iter()
+            .map(|vertex| SpriteVertex {
+                position: [
+                    vertex.position[0] + offset[0],
+                    vertex.position[1] + offset[1],
+                ],
+                tex_coords: [
+                    tex_rect[0] + vertex.tex_coords[0] * tex_rect[2],
+                    tex_rect[1] + vertex.tex_coords[1] * tex_rect[3],
+                ],
+                color: vertex.color,
+            })
+            .collect();
+
+        self.layer_batches.push(LayerBatch {
+            material,
+            z: params.z,
+            blend: params.blend,
+            geometry: LayerGeometry::Mesh {
+                vertices,
+                indices: indices.to_vec(),
+            },
+        });
+    }
+
+    /// Draws the region attachments of the posed skeleton as a single mesh, see
+    /// [`skeleton::Skeleton::build_mesh`] for `regions`. [`MeshParams::offset`] places the
+    /// skeleton origin and [`MeshParams::source`] is not used.
+    pub fn render_skeleton(
+        &mut self,
+        skeleton: &skeleton::Skeleton,
+        regions: impl Fn(&str) -> Option<URect>,
+        material: MaterialHandle,
+        params: MeshParams,
+    ) {
+        let Some(sprite_material) = self.materials.get(material) else {
+            warn!("skipping skeleton with destroyed material {material:?}");
+            return;
+        };
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        skeleton.build_mesh(
+            regions,
+            sprite_material.texture_size(),
+            &mut vertices,
+            &mut indices,
+        );
+        self.render_mesh(
+            &vertices,
+            &indices,
+            material,
+            MeshParams {
+                source: None,
+                ..params
+            },
+        );
+    }
+
+    /// The offset in `xy` and the size in `zw` of `frame` in texture coordinates. `None` when
+    /// the material can not be drawn as a mesh, since meshes have their own vertex shader.
+    fn mesh_tex_rect(&self, material: MaterialHandle, frame: Option<URect>) -> Option<[f32; 4]> {
+        let Some(sprite_material) = self.materials.get(material) else {
+            warn!("skipping mesh with destroyed material {material:?}");
+            return None;
+        };
+        if sprite_material.has_own_pipeline() {
+            warn!("skipping mesh with material {material:?}, it has its own pipeline");
+            return None;
+        }
+
+        // Placeholders are shown in full, the frame is for the final texture
+        let size = sprite_material.texture.size;
+        Some(match frame {
+            Some(frame) if sprite_material.load_state == MaterialLoadState::Loaded => {
+                let width = f32::from(size.x.max(1));
+                let height = f32::from(size.y.max(1));
+                [
+                    f32::from(frame.position.x) / width,
+                    f32::from(frame.position.y) / height,
+                    f32::from(frame.size.x) / width,
+                    f32::from(frame.size.y) / height,
+                ]
+            }
+            _ => [0.0, 0.0, 1.0, 1.0],
+        })
+    }
+
+    /// Lights lit materials for this frame, see [`Render::set_material_lighting`].
+    pub fn render_light(&mut self, light: Light) {
+        self.lighting.add_light(light);
+    }
+
+    /// Walls that cast shadows from lights, e.g. from [`shadows::tile_edges`] or
+    /// [`shadows::polygon_edges`]. They stay until replaced.
+    pub fn set_occluders(&mut self, occluders: Vec<shadows::OccluderEdge>) {
+        self.lighting.set_occluders(occluders);
+    }
+
+    pub fn set_lighting(&mut self, settings: LightingSettings) {
+        self.lighting.settings = settings;
+    }
+
+    pub fn lighting(&self) -> LightingSettings {
+        self.lighting.settings
+    }
+
+    /// Adds the sprites of a batch filled elsewhere, possibly on another thread.
+    /// The batch is emptied, but keeps its capacity.
+    pub fn submit_batch(&mut self, batch: &mut SpriteBatch) {
+        self.sprites.append(&mut batch.sprites);
+    }
+
     pub fn render(&mut self, render_pass: &mut RenderPass) {
+        #[cfg(feature = "hot-reload")]
+        {
+            self.reload_changed_shaders();
+            self.reload_changed_textures();
+        }
+        self.upload_loaded_textures();
+
+        let debug_material = self.debug.is_enabled().then(|| self.debug_material());
+        // The stats of this frame are not known until it is drawn
+        let previous_stats = self.debug.stats_hud.then(|| self.frame_stats());
+
+        let materials = &self.materials;
+        self.sprites.retain(|sprite| {
+            let is_alive = materials.contains(sprite.material);
+            if !is_alive {
+                warn!(
+                    "skipping sprite with destroyed material {:?}",
+                    sprite.material
+                );
+            }
+            is_alive
+        });
+
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(err) = recorder.write_frame(self.viewport, &self.sprites, &self.materials) {
+                error!("stopped recording frames: {err}");
+                self.recorder = None;
+            }
+        }
+
+        let submitted = self.sprites.len();
+        if self.culling && self.viewport.x > 0 && self.viewport.y > 0 {
+            let viewport = [f32::from(self.viewport.x), f32::from(self.viewport.y)];
+            self.sprites
+                .retain(|sprite| is_sprite_visible(sprite, viewport));
+        }
+        self.frame_stats = FrameStats {
+            sprites_submitted: submitted,
+            sprites: SpriteCounters {
+                drawn: self.sprites.len(),
+                culled: submitted - self.sprites.len(),
+            },
+            ..FrameStats::default()
+        };
+
         sort_sprites_by_z_then_y(&mut self.sprites);
 
+        let mut layer_batches = std::mem::take(&mut self.layer_batches);
+        layer_batches.retain(|batch| materials.contains(batch.material));
+        layer_batches.sort_by_key(|batch| batch.z);
+        let mut layer_batches = layer_batches.into_iter().peekable();
+
         // -------- Batches
-        let mut material_batches: Vec<Vec<&Sprite>> = Vec::new();
-        let mut current_batch: Vec<&Sprite> = Vec::new();
-        let mut current_material: Option<&SpriteMaterialRef> = None;
+        let mut batches: Vec<DrawBatch> = Vec::new();
+        let mut instances: Vec<SpriteInstanceUniform> = Vec::with_capacity(self.sprites.len());
+        let mut mesh = MeshData::default();
 
         for sprite in &self.sprites {
-            if Some(&sprite.material) != current_material {
-                if !current_batch.is_empty() {
-                    material_batches.push(current_batch.clone());
-                    current_batch.clear();
-                }
-                current_material = Some(&sprite.material);
+            while let Some(layer_batch) =
+                layer_batches.next_if(|layer_batch| layer_batch.z < sprite.position.z)
+            {
+                push_layer_batch(&mut batches, &mut instances, &mut mesh, layer_batch);
             }
-            current_batch.push(sprite);
-        }
 
-        if !current_batch.is_empty() {
-            material_batches.push(current_batch);
+            let material = self
+                .materials
+                .get(sprite.material)
+                .expect("dead materials are removed");
+            // Placeholders are shown in full, the atlas rect is for the final texture
+            let texture_size =
+                (material.load_state == MaterialLoadState::Loaded).then_some(material.texture.size);
+            let index = instances.len() as u32;
+            instances.push(sprite_instance(sprite, texture_size));
+            push_draw_batch(
+                &mut batches,
+                sprite.material,
+                BlendMode::Alpha,
+                Draw::Instances(index..index + 1),
+            );
+        }
+        for layer_batch in layer_batches {
+            push_layer_batch(&mut batches, &mut instances, &mut mesh, layer_batch);
+        }
+        if self.debug.batch_colors {
+            tint_batches(&batches, &mut instances, &mut mesh);
+        }
+        if let Some(debug_material) = debug_material {
+            for layer_batch in self.debug_overlay(debug_material, previous_stats) {
+                push_layer_batch(&mut batches, &mut instances, &mut mesh, layer_batch);
+            }
         }
         // ---------------
 
-        render_pass.set_pipeline(&self.pipeline);
+        self.lighting.upload(&self.queue);
+
+        if batches.is_empty() {
+            return;
+        }
+
+        for batch in &batches {
+            match batch.draw {
+                Draw::Instances(_) => {
+                    if batch.blend != BlendMode::Alpha
+                        && !self.blend_pipelines.contains_key(&batch.blend)
+                    {
+                        let pipeline = self.create_blend_pipeline(batch.blend);
+                        self.blend_pipelines.insert(batch.blend, pipeline);
+                    }
+                }
+                Draw::Mesh { .. } => {
+                    if !self.mesh_pipelines.contains_key(&batch.blend) {
+                        let pipeline = self.create_mesh_pipeline(batch.blend);
+                        self.mesh_pipelines.insert(batch.blend, pipeline);
+                    }
+                }
+            }
+        }
 
-        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        self.write_instances(&instances);
+        self.write_mesh(&mut mesh);
+        self.frame_stats.batches = batches.len();
+        self.frame_stats.instance_octets = size_of_val(instances.as_slice()) as u64;
+        self.frame_stats.vertex_octets =
+            (size_of_val(mesh.vertices.as_slice()) + size_of_val(mesh.indices.as_slice())) as u64;
+
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
 
         let num_indices = swamp_wgpu_sprites::INDICES.len() as u32;
+        let mut current_pipeline: Option<&RenderPipelineRef> = None;
+        let mut current_material: Option<MaterialHandle> = None;
+        let mut meshes_bound: Option<bool> = None;
 
-        for batch in material_batches {
-            if let Some(first_sprite) = batch.first() {
-                let sprite_texture_bind_group = &first_sprite.material.bind_group;
-                render_pass.set_bind_group(1, sprite_texture_bind_group, &[]); // sets sampler and texture
+        for batch in batches {
+            let material = self
+                .materials
+                .get(batch.material)
+                .expect("dead materials are removed");
+            let is_mesh = matches!(batch.draw, Draw::Mesh { .. });
+            // Materials without a custom shader always use the current sprite pipeline,
+            // it is replaced when the shaders are reloaded
+            let pipeline = if is_mesh {
+                &self.mesh_pipelines[&batch.blend]
+            } else if material.has_own_pipeline() {
+                &material.render_pipeline
+            } else if batch.blend == BlendMode::Alpha {
+                &self.pipeline
+            } else {
+                &self.blend_pipelines[&batch.blend]
+            };
+            if current_pipeline.is_none_or(|current| !Arc::ptr_eq(current, pipeline)) {
+                render_pass.set_pipeline(pipeline);
+                current_pipeline = Some(pipeline);
+                self.frame_stats.pipeline_switches += 1;
             }
 
-            for sprite in batch {
-                let model_matrix =
-                    Mx4::from_translation(sprite.position.x.into(), sprite.position.y.into(), 0.0)
-                        * Mx4::from_scale(
-                            sprite.atlas_rect.size.x.into(),
-                            sprite.atlas_rect.size.y.into(),
-                            1.0,
-                        );
+            if meshes_bound != Some(is_mesh) {
+                if is_mesh {
+                    render_pass.set_index_buffer(
+                        self.mesh_index_buffer.slice(..),
+                        wgpu::IndexFormat::Uint16,
+                    );
+                    render_pass.set_vertex_buffer(0, self.mesh_vertex_buffer.slice(..));
+                } else {
+                    render_pass
+                        .set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                    render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                    render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+                }
+                meshes_bound = Some(is_mesh);
+            }
+
+            if current_material != Some(batch.material) {
+                render_pass.set_bind_group(1, &material.texture.bind_group, &[]); // sets texture and sampler
+                if let Some(custom) = &material.custom {
+                    render_pass.set_bind_group(2, &custom.bind_group, &[]);
+                } else if let Some(lit) = &material.lighting {
+                    render_pass.set_bind_group(2, &lit.bind_group, &[]);
+                }
+                current_material = Some(batch.material);
+                self.frame_stats.material_switches += 1;
+            }
+
+            self.frame_stats.draw_calls += 1;
+            match batch.draw {
+                Draw::Instances(instance_range) => {
+                    render_pass.draw_indexed(0..num_indices, 0, instance_range);
+                }
+                Draw::Mesh {
+                    indices,
+                    base_vertex,
+                } => render_pass.draw_indexed(indices, base_vertex, 0..1),
+            }
+        }
 
-                let atlas = sprite.atlas_rect;
+        self.sprites.clear();
+    }
 
-                let tex_coords = FVec4([
-                    atlas.position.x.into(),
-                    atlas.position.y.into(),
-                    (atlas.position.x + atlas.size.x).into(),
-                    (atlas.position.y + atlas.size.y).into(),
-                ]);
+    /// Layer batches of the debug overlay for the sprites of this frame, in drawing order.
+    fn debug_overlay(
+        &self,
+        debug_material: MaterialHandle,
+        previous_stats: Option<FrameStats>,
+    ) -> Vec<LayerBatch> {
+        let mesh_batches = |mesh: DebugMesh| {
+            mesh.into_meshes()
+                .into_iter()
+                .map(move |(vertices, indices)| LayerBatch {
+                    material: debug_material,
+                    z: i16::MAX,
+                    blend: BlendMode::Alpha,
+                    geometry: LayerGeometry::Mesh { vertices, indices },
+                })
+        };
+        let mut layer_batches = Vec::new();
+        let mut overlay = DebugMesh::default();
 
-                let sprite_uniform =
-                    swamp_wgpu_sprites::SpriteUniform::new(model_matrix, tex_coords);
+        if let Some(view) = &self.debug.atlas {
+            if let Some(material) = self.materials.get(view.material) {
+                let size = material.texture_size();
+                let texture_size = [f32::from(size.x), f32::from(size.y)];
+                let mut backdrop = DebugMesh::default();
+                debug::atlas_backdrop(&mut backdrop, view, texture_size);
+                layer_batches.extend(mesh_batches(backdrop));
 
-                // Data will be copied, so we can reuse the same buffer
-                self.queue.write_buffer(
-                    &self.sprite_uniform_buffer,
+                let model =
+                    Mx4::from_translation(view.position.x.into(), view.position.y.into(), 0.0)
+                        * Mx4::from_scale(
+                            texture_size[0] * view.scale,
+                            texture_size[1] * view.scale,
+                            1.0,
+                        );
+                let instance = SpriteInstanceUniform::new(
+                    model,
+                    FVec4([0.0, 0.0, 1.0, 1.0]),
+                    SpriteInstanceUniform::NO_TILING,
                     0,
-                    bytemuck::cast_slice(&[sprite_uniform]),
                 );
+                layer_batches.push(LayerBatch {
+                    material: view.material,
+                    z: i16::MAX,
+                    blend: BlendMode::Alpha,
+                    geometry: LayerGeometry::Instances(vec![instance]),
+                });
+                debug::atlas_outlines(&mut overlay, view, texture_size);
+            }
+        }
 
-                render_pass.set_bind_group(0, &self.sprite_uniform_bind_group, &[]);
+        for sprite in &self.sprites {
+            if self.debug.sprite_bounds {
+                let (min, max) = sprite_bounds(sprite);
+                debug::sprite_bounds(&mut overlay, min, max);
+            }
+            if self.debug.pivots {
+                let pivot = sprite.params.pivot.unwrap_or_default();
+                debug::pivot(
+                    &mut overlay,
+                    [
+                        f32::from(sprite.position.x) + f32::from(pivot.x),
+                        f32::from(sprite.position.y) + f32::from(pivot.y),
+                    ],
+                );
+            }
+        }
 
-                render_pass.draw_indexed(0..num_indices, 0, 0..1);
+        if let Some(stats) = previous_stats {
+            if self.viewport.x > 0 && self.viewport.y > 0 {
+                let viewport = [f32::from(self.viewport.x), f32::from(self.viewport.y)];
+                debug::stats_hud(&mut overlay, &stats, viewport);
             }
         }
 
-        /*
-        for sprite in &self.sprites {
-            let sprite_texture_bind_group = &sprite.material.bind_group;
-            render_pass.set_bind_group(0, sprite_texture_bind_group, &[]); // sets sampler and texture
+        layer_batches.extend(mesh_batches(overlay));
+        layer_batches
+    }
 
+    fn write_instances(&mut self, instances: &[SpriteInstanceUniform]) {
+        if instances.is_empty() {
+            return;
+        }
+        if instances.len() > self.instance_capacity {
+            self.instance_capacity = instances.len().next_power_of_two();
+            self.instance_buffer = swamp_wgpu_sprites::create_sprite_instance_buffer(
+                &self.device,
+                "sprite instance buffer",
+                self.instance_capacity,
+            );
+        }
 
+        // Data will be copied before the render pass is submitted
+        self.queue
+            .write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(instances));
+    }
 
-            render_pass.draw_indexed(0..num_indices, 0, 0..1);
+    fn write_mesh(&mut self, mesh: &mut MeshData) {
+        if mesh.indices.is_empty() {
+            return;
+        }
+        if mesh.vertices.len() > self.mesh_vertex_capacity {
+            self.mesh_vertex_capacity = mesh.vertices.len().next_power_of_two();
+            self.mesh_vertex_buffer = swamp_wgpu_sprites::create_sprite_mesh_vertex_buffer(
+                &self.device,
+                "sprite mesh vertex buffer",
+                self.mesh_vertex_capacity,
+            );
+        }
+        // Buffer writes must be whole multiples of four octets
+        if !mesh.indices.len().is_multiple_of(2) {
+            mesh.indices.push(0);
+        }
+        if mesh.indices.len() > self.mesh_index_capacity {
+            self.mesh_index_capacity = mesh.indices.len().next_power_of_two();
+            self.mesh_index_buffer = swamp_wgpu_sprites::create_sprite_mesh_index_buffer(
+                &self.device,
+                "sprite mesh index buffer",
+                self.mesh_index_capacity,
+            );
         }
-        */
 
-        self.sprites.clear();
+        self.queue.write_buffer(
+            &self.mesh_vertex_buffer,
+            0,
+            bytemuck::cast_slice(&mesh.vertices),
+        );
+        self.queue.write_buffer(
+            &self.mesh_index_buffer,
+            0,
+            bytemuck::cast_slice(&mesh.indices),
+        );
+    }
+
+    fn create_mesh_pipeline(&self, blend: BlendMode) -> RenderPipelineRef {
+        let pipeline_layout = swamp_wgpu::create_pipeline_layout_with_groups(
+            &self.device,
+            "sprite mesh pipeline layout",
+            &[&self.camera_bind_group_layout, &self.bind_group_layout],
+        );
+
+        Arc::new(swamp_wgpu_sprites::create_sprite_mesh_pipeline(
+            &self.device,
+            self.surface_texture_format,
+            &pipeline_layout,
+            &self.mesh_vertex_shader,
+            &self.fragment_shader,
+            blend,
+        ))
+    }
+
+    fn create_blend_pipeline(&self, blend: BlendMode) -> RenderPipelineRef {
+        let pipeline_layout = swamp_wgpu::create_pipeline_layout_with_groups(
+            &self.device,
+            "sprite pipeline layout",
+            &[&self.camera_bind_group_layout, &self.bind_group_layout],
+        );
+
+        Arc::new(swamp_wgpu_sprites::create_sprite_pipeline_with_blend(
+            &self.device,
+            self.surface_texture_format,
+            &pipeline_layout,
+            &self.vertex_shader,
+            &self.fragment_shader,
+            blend,
+        ))
+    }
+
+    /// Returns a handle right away, that shows the placeholder until the file has been
+    /// decoded on a background thread and uploaded at the start of a later [`Render::render`].
+    pub fn load_material_async(&mut self, path: &Path, placeholder: Placeholder) -> MaterialHandle {
+        let label = path.display().to_string();
+        let texture = swamp_wgpu_sprites::create_texture_from_rgba(
+            &self.device,
+            &self.queue,
+            &placeholder.image(),
+            &label,
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        let mut material =
+            self.create_sprite_material(texture, Arc::clone(&self.pipeline), None, &label);
+        material.load_state = MaterialLoadState::Loading;
+        let handle = self.materials.insert(material);
+
+        self.texture_loader
+            .get_or_insert_with(TextureLoader::new)
+            .request(handle, path.to_path_buf());
+
+        handle
+    }
+
+    /// For loading screens, see [`LoadingProgress::fraction`].
+    pub fn loading_progress(&self) -> LoadingProgress {
+        self.texture_loader
+            .as_ref()
+            .map(|loader| loader.progress)
+            .unwrap_or_default()
+    }
+
+    fn upload_loaded_textures(&mut self) {
+        let Some(loader) = &self.texture_loader else {
+            return;
+        };
+
+        for loaded in loader.finished() {
+            let label = loaded.path.display().to_string();
+            let texture = loaded.result.and_then(|decoded| match decoded {
+                async_loading::DecodedTexture::Rgba(img) => {
+                    Ok(swamp_wgpu_sprites::create_texture_from_rgba(
+                        &self.device,
+                        &self.queue,
+                        &img,
+                        &label,
+                        TextureFormat::Rgba8UnormSrgb,
+                    ))
+                }
+                async_loading::DecodedTexture::Encoded(octets, format) => {
+                    swamp_wgpu_sprites::texture_formats::load_texture(
+                        &self.device,
+                        &self.queue,
+                        &octets,
+                        Some(format),
+                        &label,
+                    )
+                    .map_err(|err| err.to_string())
+                }
+            });
+            let sampler = self
+                .materials
+                .get(loaded.handle)
+                .map(|material| material.sampler)
+                .unwrap_or_default();
+            let material_texture =
+                texture.map(|texture| self.create_material_texture(texture, sampler, &label));
+
+            let progress = &mut self
+                .texture_loader
+                .as_mut()
+                .expect("loader exists")
+                .progress;
+            let Some(material) = self.materials.get_mut(loaded.handle) else {
+                // Destroyed while loading
+                progress.failed += 1;
+                continue;
+            };
+            match material_texture {
+                Ok(material_texture) => {
+                    material.texture = material_texture;
+                    material.load_state = MaterialLoadState::Loaded;
+                    material.source_path = Some(loaded.path.clone());
+                    progress.loaded += 1;
+                }
+                Err(err) => {
+                    error!("could not load {label}: {err}");
+                    material.load_state = MaterialLoadState::Failed;
+                    progress.failed += 1;
+                    continue;
+                }
+            }
+
+            #[cfg(feature = "hot-reload")]
+            self.watch_texture(&loaded.path);
+        }
     }
 
-    pub fn create_material_png(&mut self, png: &[u8], label: &str) -> SpriteMaterialRef {
+    pub fn create_material_png(&mut self, png: &[u8], label: &str) -> MaterialHandle {
         let texture =
             swamp_wgpu_sprites::load_texture_from_memory(&self.device, &self.queue, png, label);
         info!("loaded texture!");
 
-        let bind_group = swamp_wgpu::create_texture_and_sampler_bind_group(
+        let material =
+            self.create_sprite_material(texture, Arc::clone(&self.pipeline), None, label);
+        self.materials.insert(material)
+    }
+
+    /// Creates a material from tightly packed sRGB RGBA pixels, e.g. for procedural content
+    /// that is later changed with [`Render::update_material_region`]. Fails if `octets` does
+    /// not fill the texture.
+    pub fn create_material_rgba(
+        &mut self,
+        width: u16,
+        height: u16,
+        octets: &[u8],
+    ) -> Result<MaterialHandle, TextureRegionError> {
+        let label = "rgba material";
+        let texture = swamp_wgpu::create_texture_with_format(
             &self.device,
-            &self.bind_group_layout,
-            &self.sampler,
-            texture,
             label,
+            width.into(),
+            height.into(),
+            TextureFormat::Rgba8UnormSrgb,
         );
+        swamp_wgpu::write_texture_region(
+            &self.queue,
+            &texture,
+            (0, 0),
+            (width.into(), height.into()),
+            octets,
+        )?;
 
-        let material = Rc::new(SpriteMaterial {
-            bind_group,
-            render_pipeline: Rc::clone(&self.pipeline),
-        });
-        self.materials.push(Rc::clone(&material));
-
-        material
+        let material =
+            self.create_sprite_material(texture, Arc::clone(&self.pipeline), None, label);
+        Ok(self.materials.insert(material))
     }
-}
 
-fn sort_sprites_by_z_then_y(sprites: &mut [Sprite]) {
-    sprites.sort_by_key(|sprite| (sprite.position.z, sprite.position.y));
-}
+    /// Overwrites a part of the material texture with tightly packed RGBA pixels.
+    /// Logs and skips the update if the region is outside of the texture or `octets` does
+    /// not fill it.
+    pub fn update_material_region(&self, material: MaterialHandle, region: URect, octets: &[u8]) {
+        let Some(material) = self.materials.get(material) else {
+            warn!("can not update destroyed material {material:?}");
+            return;
+        };
+        let texture = &material.texture.texture;
+        if texture.format().remove_srgb_suffix() != TextureFormat::Rgba8Unorm {
+            warn!(
+                "can not update {:?} material texture, only RGBA",
+                texture.format()
+            );
+            return;
+        }
 
-#[derive(Default, Debug)]
-pub struct SpriteParams {
-    pub dest_size: Option<UVec2>,
-    pub source: Option<URect>,
-    pub rotation: u16,
-    pub flip_x: bool,
-    pub flip_y: bool,
-    pub pivot: Option<Vec2>,
-}
+        if let Err(err) = swamp_wgpu::write_texture_region(
+            &self.queue,
+            texture,
+            (region.position.x.into(), region.position.y.into()),
+            (region.size.x.into(), region.size.y.into()),
+            octets,
+        ) {
+            warn!("can not update material region: {err}");
+        }
+    }
 
-pub type SpriteMaterialRef = Rc<SpriteMaterial>;
+    /// Creates a material from palette indices. `palettes` has one palette per row, the row is
+    /// selected with [`SpriteParams::palette_row`]. Indexed materials are always drawn
+    /// without filtering, and indices outside of the palette are transparent.
+    pub fn create_material_indexed(
+        &mut self,
+        image: &IndexedImage,
+        palettes: &image::RgbaImage,
+        label: &str,
+    ) -> MaterialHandle {
+        let index_texture = swamp_wgpu_sprites::indexed::create_index_texture(
+            &self.device,
+            &self.queue,
+            image,
+            label,
+        );
+        let palette_texture = swamp_wgpu_sprites::indexed::create_palette_texture(
+            &self.device,
+            &self.queue,
+            palettes,
+            label,
+        );
+        let bind_group = swamp_wgpu_sprites::indexed::create_indexed_bind_group(
+            &self.device,
+            &self.indexed_bind_group_layout,
+            &index_texture,
+            &palette_texture,
+            label,
+        );
 
-#[derive(Debug)]
-pub struct Sprite {
-    pub position: Vec3,
-    pub atlas_rect: URect,
-    pub material: SpriteMaterialRef,
-    pub params: SpriteParams,
-}
+        let material = SpriteMaterial {
+            texture: MaterialTexture {
+                size: UVec2::new(image.width as u16, image.height as u16),
+                texture: index_texture,
+                bind_group,
+            },
+            render_pipeline: self.indexed_pipeline(),
+            custom: None,
+            source_path: None,
+            load_state: MaterialLoadState::Loaded,
+            sampler: SamplerOptions::default(),
+            palette: Some(palette_texture),
+            lighting: None,
+        };
+        self.materials.insert(material)
+    }
 
-pub type RenderPipelineRef = Rc<RenderPipeline>;
+    /// Loads an indexed PNG and keeps its indices, with the palette of the file as row 0.
+    pub fn create_material_indexed_png(
+        &mut self,
+        png: &[u8],
+        label: &str,
+    ) -> Result<MaterialHandle, IndexedImageError> {
+        let image = swamp_wgpu_sprites::indexed::decode_indexed_png(png)?;
+        Ok(self.create_material_indexed(&image, &image.palette_image(), label))
+    }
+
+    /// Replaces all palette rows of an indexed material, e.g. to add team colors at runtime.
+    /// The palette texture is recreated if the size changed.
+    pub fn set_material_palettes(&mut self, material: MaterialHandle, palettes: &image::RgbaImage) {
+        let Some(current) = self.materials.get_mut(material) else {
+            warn!("can not set the palettes of destroyed material {material:?}");
+            return;
+        };
+        let Some(palette_texture) = &current.palette else {
+            warn!("material {material:?} is not indexed");
+            return;
+        };
+
+        if palette_texture.width() == palettes.width()
+            && palette_texture.height() == palettes.height()
+        {
+            swamp_wgpu_sprites::write_rgba_to_texture(&self.queue, palette_texture, palettes);
+            return;
+        }
+
+        let palette_texture = swamp_wgpu_sprites::indexed::create_palette_texture(
+            &self.device,
+            &self.queue,
+            palettes,
+            "material palettes",
+        );
+        current.texture.bind_group = swamp_wgpu_sprites::indexed::create_indexed_bind_group(
+            &self.device,
+            &self.indexed_bind_group_layout,
+            &current.texture.texture,
+            &palette_texture,
+            "material palettes",
+        );
+        current.palette = Some(palette_texture);
+    }
+
+    /// Draws the material with the lights from [`Render::render_light`] and the ambient light of
+    /// [`Render::set_lighting`]. The normal map PNG uses the same atlas layout as the color
+    /// texture, with green pointing up. Without a normal map the material is lit as flat.
+    pub fn set_material_lighting(
+        &mut self,
+        material: MaterialHandle,
+        normal_map_png: Option<&[u8]>,
+    ) {
+        let Some(current) = self.materials.get(material) else {
+            warn!("can not light destroyed material {material:?}");
+            return;
+        };
+        if current.custom.is_some() || current.is_indexed() {
+            warn!("only regular materials can be lit, not {material:?}");
+            return;
+        }
+
+        let normal_map = match normal_map_png {
+            Some(png) => match swamp_wgpu_sprites::try_load_data_texture_from_memory(
+                &self.device,
+                &self.queue,
+                png,
+                "normal map",
+            ) {
+                Ok(texture) => Some(texture),
+                Err(err) => {
+                    warn!("can not light {material:?} with an invalid normal map: {err}");
+                    return;
+                }
+            },
+            None => None,
+        };
+        let bind_group =
+            self.lighting
+                .create_bind_group(&self.device, normal_map.as_ref(), "lit material");
+        let pipeline = self.lit_pipeline();
+
+        if let Some(material) = self.materials.get_mut(material) {
+            material.render_pipeline = pipeline;
+            material.lighting = Some(LitMaterialBinding {
+                normal_map,
+                bind_group,
+            });
+        }
+    }
+
+    /// The material is drawn without lighting again.
+    pub fn set_material_unlit(&mut self, material: MaterialHandle) {
+        let pipeline = Arc::clone(&self.pipeline);
+        if let Some(material) = self.materials.get_mut(material) {
+            material.render_pipeline = pipeline;
+            material.lighting = None;
+        }
+    }
+
+    fn lit_pipeline(&mut self) -> RenderPipelineRef {
+        if let Some(pipeline) = &self.lit_pipeline {
+            return Arc::clone(pipeline);
+        }
+
+        let fragment_shader = swamp_wgpu::shader_validation::create_shader_module_checked(
+            &self.device,
+            "lit sprite fragment",
+            lighting::SPRITE_LIT_SHADER_SOURCE,
+            &swamp_wgpu_sprites::sprite_fragment_interface(lighting::LIT_BINDINGS),
+        )
+        .expect("built-in lit sprite shader is valid");
+        let pipeline_layout = swamp_wgpu::create_pipeline_layout_with_groups(
+            &self.device,
+            "lit sprite pipeline layout",
+            &[
+                &self.camera_bind_group_layout,
+                &self.bind_group_layout,
+                &self.lighting.bind_group_layout,
+            ],
+        );
+        let pipeline = Arc::new(swamp_wgpu_sprites::create_sprite_pipeline(
+            &self.device,
+            self.surface_texture_format,
+            &pipeline_layout,
+            &self.vertex_shader,
+            &fragment_shader,
+        ));
+        self.lit_pipeline = Some(Arc::clone(&pipeline));
+
+        pipeline
+    }
+
+    fn indexed_pipeline(&mut self) -> RenderPipelineRef {
+        if let Some(pipeline) = &self.indexed_pipeline {
+            return Arc::clone(pipeline);
+        }
+
+        let fragment_shader = swamp_wgpu::shader_validation::create_shader_module_checked(
+            &self.device,
+            "indexed sprite fragment",
+            swamp_wgpu_sprites::SPRITE_INDEXED_FRAGMENT_SHADER_SOURCE,
+            &swamp_wgpu_sprites::sprite_fragment_interface(
+                swamp_wgpu_sprites::indexed::INDEXED_BINDINGS,
+            ),
+        )
+        .expect("built-in indexed sprite shader is valid");
+        let pipeline_layout = swamp_wgpu::create_pipeline_layout_with_groups(
+            &self.device,
+            "indexed sprite pipeline layout",
+            &[
+                &self.camera_bind_group_layout,
+                &self.indexed_bind_group_layout,
+            ],
+        );
+        let pipeline = Arc::new(swamp_wgpu_sprites::create_sprite_pipeline(
+            &self.device,
+            self.surface_texture_format,
+            &pipeline_layout,
+            &self.vertex_shader,
+            &fragment_shader,
+        ));
+        self.indexed_pipeline = Some(Arc::clone(&pipeline));
+
+        pipeline
+    }
+
+    /// The first texture is the sprite texture, bound like for regular materials in
+    /// `@group(1)` (texture at `@binding(0)`, sampler at `@binding(1)`). `uniform_octets` is bound
+    /// to `@group(2) @binding(0)` and the rest of the textures follow at `@group(2) @binding(1..)`.
+    /// The fragment entry point is `fs_main` and receives the texture coordinates at `@location(1)`.
+    /// Those are not wrapped for [`FillMode::Tile`], the tile coordinates are at `@location(2)` and
+    /// the flat interpolated texture rect of one tile at `@location(3)`.
+    pub fn create_custom_material(
+        &mut self,
+        fragment_shader_source: &str,
+        textures: &[&[u8]],
+        uniform_octets: &[u8],
+        label: &str,
+    ) -> Result<MaterialHandle, CustomMaterialError> {
+        let (sprite_png, extra_pngs) = textures
+            .split_first()
+            .ok_or(CustomMaterialError::MissingSpriteTexture)?;
+
+        let mut bindings = swamp_wgpu_sprites::SPRITE_BINDINGS.to_vec();
+        bindings.push(ExpectedBinding::new(2, 0, BindingKind::UniformBuffer));
+        for index in 0..extra_pngs.len() {
+            bindings.push(ExpectedBinding::new(
+                2,
+                index as u32 + 1,
+                BindingKind::Texture2d,
+            ));
+        }
+        let fragment_shader = swamp_wgpu::shader_validation::create_shader_module_checked(
+            &self.device,
+            label,
+            fragment_shader_source,
+            &swamp_wgpu_sprites::sprite_fragment_interface(&bindings),
+        )?;
+
+        let texture = swamp_wgpu_sprites::try_load_texture_from_memory(
+            &self.device,
+            &self.queue,
+            sprite_png,
+            label,
+        )?;
+        let extra_textures = extra_pngs
+            .iter()
+            .map(|png| {
+                swamp_wgpu_sprites::try_load_texture_from_memory(
+                    &self.device,
+                    &self.queue,
+                    png,
+                    label,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let extra_views: Vec<wgpu::TextureView> = extra_textures
+            .iter()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()))
+            .collect();
+
+        let custom_bind_group_layout =
+            create_custom_material_bind_group_layout(&self.device, label, extra_views.len());
+        let uniform_buffer =
+            swamp_wgpu::create_uniform_buffer_with_octets(&self.device, label, uniform_octets);
+
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: uniform_buffer.as_entire_binding(),
+        }];
+        for (index, view) in extra_views.iter().enumerate() {
+            entries.push(wgpu::BindGroupEntry {
+                binding: index as u32 + 1,
+                resource: wgpu::BindingResource::TextureView(view),
+            });
+        }
+        let custom_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout: &custom_bind_group_layout,
+            entries: &entries,
+        });
+
+        let pipeline_layout = swamp_wgpu::create_pipeline_layout_with_groups(
+            &self.device,
+            label,
+            &[
+                &self.camera_bind_group_layout,
+                &self.bind_group_layout,
+                &custom_bind_group_layout,
+            ],
+        );
+        let pipeline = swamp_wgpu_sprites::create_sprite_pipeline(
+            &self.device,
+            self.surface_texture_format,
+            &pipeline_layout,
+            &self.vertex_shader,
+            &fragment_shader,
+        );
+
+        let material = self.create_sprite_material(
+            texture,
+            Arc::new(pipeline),
+            Some(CustomMaterialBinding {
+                uniform_buffer,
+                bind_group: custom_bind_group,
+                textures: extra_textures,
+            }),
+            label,
+        );
+
+        Ok(self.materials.insert(material))
+    }
+
+    /// Replaces the uniform data of a material created with [`Render::create_custom_material`].
+    /// The size must be the same as the octets it was created with, other sizes are ignored.
+    pub fn update_material_uniforms(&self, handle: MaterialHandle, uniform_octets: &[u8]) {
+        let Some(material) = self.materials.get(handle) else {
+            warn!("can not update destroyed material {handle:?}");
+            return;
+        };
+        let Some(custom) = &material.custom else {
+            warn!("can not update uniforms of {handle:?}, only custom materials have them");
+            return;
+        };
+        let octets = swamp_wgpu::pad_uniform_octets(uniform_octets);
+        if octets.len() as u64 != custom.uniform_buffer.size() {
+            warn!(
+                "can not update uniforms of {handle:?} with {} octets, it was created with {}",
+                octets.len(),
+                custom.uniform_buffer.size()
+            );
+            return;
+        }
+        self.queue.write_buffer(&custom.uniform_buffer, 0, &octets);
+    }
+
+    /// Frees the textures and bind groups of the material. Sprites that still use the
+    /// handle are skipped, and the handle never refers to another material.
+    pub fn destroy_material(&mut self, material: MaterialHandle) {
+        if self.materials.remove(material).is_none() {
+            warn!("material {material:?} is already destroyed");
+        }
+        self.sprites.retain(|sprite| sprite.material != material);
+    }
+
+    pub fn material(&self, material: MaterialHandle) -> Option<&SpriteMaterial> {
+        self.materials.get(material)
+    }
+
+    /// Live materials and the textures they own.
+    pub fn stats(&self) -> RenderStats {
+        let mut stats = RenderStats::default();
+
+        for (_, material) in self.materials.iter() {
+            stats.material_count += 1;
+            stats.add_texture(&material.texture.texture);
+            if let Some(palette) = &material.palette {
+                stats.add_texture(palette);
+            }
+            if let Some(normal_map) = material
+                .lighting
+                .as_ref()
+                .and_then(|lit| lit.normal_map.as_ref())
+            {
+                stats.add_texture(normal_map);
+            }
+            if let Some(custom) = &material.custom {
+                for texture in &custom.textures {
+                    stats.add_texture(texture);
+                }
+            }
+        }
+
+        stats
+    }
+
+    fn create_sprite_material(
+        &mut self,
+        texture: wgpu::Texture,
+        render_pipeline: RenderPipelineRef,
+        custom: Option<CustomMaterialBinding>,
+        label: &str,
+    ) -> SpriteMaterial {
+        let sampler = SamplerOptions::default();
+        SpriteMaterial {
+            texture: self.create_material_texture(texture, sampler, label),
+            render_pipeline,
+            custom,
+            source_path: None,
+            load_state: MaterialLoadState::Loaded,
+            sampler,
+            palette: None,
+            lighting: None,
+        }
+    }
+
+    /// Adds a mip chain if the sampler needs it and binds the texture with the sampler.
+    fn create_material_texture(
+        &mut self,
+        texture: wgpu::Texture,
+        sampler: SamplerOptions,
+        label: &str,
+    ) -> MaterialTexture {
+        let texture = if sampler.filter == SamplerFilter::LinearMipmapped
+            && texture.mip_level_count() == 1
+            && MipmapGenerator::supports_format(texture.format())
+        {
+            self.mipmap_generator
+                .create_mipmapped_copy(&self.device, &self.queue, &texture, label)
+        } else {
+            texture
+        };
+        let size = UVec2::new(texture.width() as u16, texture.height() as u16);
+        let bind_group = create_material_bind_group(
+            &self.device,
+            &self.bind_group_layout,
+            &mut self.samplers,
+            &texture,
+            sampler,
+            label,
+        );
+
+        MaterialTexture {
+            texture,
+            bind_group,
+            size,
+        }
+    }
+
+    /// Replaces the texture, keeping the sampler of the material.
+    fn set_material_texture(
+        &mut self,
+        material: MaterialHandle,
+        texture: wgpu::Texture,
+        label: &str,
+    ) {
+        let Some(sampler) = self
+            .materials
+            .get(material)
+            .map(|material| material.sampler)
+        else {
+            return;
+        };
+        let material_texture = self.create_material_texture(texture, sampler, label);
+        if let Some(material) = self.materials.get_mut(material) {
+            material.texture = material_texture;
+        }
+    }
+
+    /// Selects filtering and address mode for the material. A mip chain is generated
+    /// for [`SamplerFilter::LinearMipmapped`] if the texture does not have one.
+    pub fn set_material_sampler(&mut self, material: MaterialHandle, sampler: SamplerOptions) {
+        let Some(current) = self.materials.get(material) else {
+            warn!("can not set the sampler of destroyed material {material:?}");
+            return;
+        };
+        if current.is_indexed() {
+            warn!("indexed material {material:?} is always drawn without a sampler");
+            return;
+        }
+        let current_texture = &current.texture.texture;
+        let needs_mipmaps = sampler.filter == SamplerFilter::LinearMipmapped
+            && current_texture.mip_level_count() == 1;
+
+        if needs_mipmaps && MipmapGenerator::supports_format(current_texture.format()) {
+            let texture = self.mipmap_generator.create_mipmapped_copy(
+                &self.device,
+                &self.queue,
+                current_texture,
+                "mipmapped material texture",
+            );
+            let material_texture =
+                self.create_material_texture(texture, sampler, "mipmapped material texture");
+            if let Some(material) = self.materials.get_mut(material) {
+                material.texture = material_texture;
+                material.sampler = sampler;
+            }
+            return;
+        }
+
+        if needs_mipmaps {
+            warn!(
+                "{:?} textures can not get generated mip levels",
+                current_texture.format()
+            );
+        }
+        if let Some(material) = self.materials.get_mut(material) {
+            material.texture.bind_group = create_material_bind_group(
+                &self.device,
+                &self.bind_group_layout,
+                &mut self.samplers,
+                &material.texture.texture,
+                sampler,
+                "material",
+            );
+            material.sampler = sampler;
+        }
+    }
+
+    /// Regenerates the mip levels from the first level, e.g. after
+    /// [`Render::update_material_region`]. Adds a mip chain if the texture has none, and
+    /// replaces textures that can not be drawn into, e.g. loaded with a stored mip chain.
+    pub fn generate_mipmaps(&mut self, material: MaterialHandle) {
+        let Some(current) = self.materials.get(material) else {
+            warn!("can not generate mipmaps for destroyed material {material:?}");
+            return;
+        };
+        if current.is_indexed() {
+            warn!("indices of material {material:?} can not be filtered into mip levels");
+            return;
+        }
+        let texture = &current.texture.texture;
+        if !MipmapGenerator::supports_format(texture.format()) {
+            warn!(
+                "{:?} textures can not get generated mip levels",
+                texture.format()
+            );
+            return;
+        }
+
+        if MipmapGenerator::can_generate_in_place(texture.mip_level_count(), texture.usage()) {
+            self.mipmap_generator
+                .generate(&self.device, &self.queue, texture);
+        } else {
+            let texture = self.mipmap_generator.create_mipmapped_copy(
+                &self.device,
+                &self.queue,
+                texture,
+                "mipmapped material texture",
+            );
+            self.set_material_texture(material, texture, "mipmapped material texture");
+        }
+    }
+
+    /// Loads any of the [`ImageFileFormat`]s. The format is detected if `format` is `None`.
+    pub fn create_material_from_bytes(
+        &mut self,
+        octets: &[u8],
+        format: Option<ImageFileFormat>,
+        label: &str,
+    ) -> Result<MaterialHandle, TextureLoadError> {
+        let texture = swamp_wgpu_sprites::texture_formats::load_texture(
+            &self.device,
+            &self.queue,
+            octets,
+            format,
+            label,
+        )?;
+
+        let material =
+            self.create_sprite_material(texture, Arc::clone(&self.pipeline), None, label);
+        Ok(self.materials.insert(material))
+    }
+
+    /// Loads an image file. The path is kept in the material, and with the `hot-reload`
+    /// feature the texture is reloaded when the file changes.
+    ///
+    /// The format is taken from the file extension, or detected from the contents.
+    pub fn create_material_from_path(&mut self, path: &Path) -> std::io::Result<MaterialHandle> {
+        let source_path = path.canonicalize()?;
+        let label = source_path.display().to_string();
+        let octets = std::fs::read(&source_path)?;
+        let texture = swamp_wgpu_sprites::texture_formats::load_texture(
+            &self.device,
+            &self.queue,
+            &octets,
+            ImageFileFormat::from_path(&source_path),
+            &label,
+        )
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+        let mut material =
+            self.create_sprite_material(texture, Arc::clone(&self.pipeline), None, &label);
+
+        #[cfg(feature = "hot-reload")]
+        self.watch_texture(&source_path);
+
+        material.source_path = Some(source_path);
+
+        Ok(self.materials.insert(material))
+    }
+
+    /// Uploads a new image into the material. The texture is recreated if the size changed or
+    /// it is not RGBA, e.g. block compressed, the handle stays the same either way.
+    /// Mip levels are regenerated from the new image.
+    pub fn replace_material_image(&mut self, material: MaterialHandle, img: &image::RgbaImage) {
+        let Some(current) = self.materials.get(material) else {
+            warn!("can not replace the image of destroyed material {material:?}");
+            return;
+        };
+        if current.is_indexed() {
+            warn!("material {material:?} is indexed, use Render::set_material_palettes");
+            return;
+        }
+        let texture = &current.texture.texture;
+        let is_rgba = texture.format().remove_srgb_suffix() == TextureFormat::Rgba8Unorm;
+        let has_mip_levels = texture.mip_level_count() > 1;
+        let (width, height) = img.dimensions();
+        let is_writable = !has_mip_levels
+            || MipmapGenerator::can_generate_in_place(texture.mip_level_count(), texture.usage());
+        if is_rgba && is_writable && texture.width() == width && texture.height() == height {
+            swamp_wgpu_sprites::write_rgba_to_texture(&self.queue, texture, img);
+            if has_mip_levels {
+                self.mipmap_generator
+                    .generate(&self.device, &self.queue, texture);
+            }
+        } else {
+            let format = if is_rgba {
+                texture.format()
+            } else {
+                TextureFormat::Rgba8UnormSrgb
+            };
+            let texture = swamp_wgpu_sprites::create_texture_from_rgba(
+                &self.device,
+                &self.queue,
+                img,
+                "replaced material texture",
+                format,
+            );
+            self.set_material_texture(material, texture, "replaced material texture");
+            if has_mip_levels {
+                self.generate_mipmaps(material);
+            }
+        }
+    }
+
+    #[cfg(feature = "hot-reload")]
+    fn watch_texture(&mut self, path: &Path) {
+        if self.texture_watcher.is_none() {
+            match hot_reload::FileWatcher::new() {
+                Ok(watcher) => self.texture_watcher = Some(watcher),
+                Err(err) => {
+                    error!("texture hot reloading is disabled: {err}");
+                    return;
+                }
+            }
+        }
+        if let Some(watcher) = &mut self.texture_watcher {
+            if let Err(err) = watcher.watch(path) {
+                error!("could not watch {}: {err}", path.display());
+            }
+        }
+    }
+
+    #[cfg(feature = "hot-reload")]
+    fn reload_changed_textures(&mut self) {
+        let Some(watcher) = &self.texture_watcher else {
+            return;
+        };
+
+        for path in watcher.changed_files() {
+            let octets = match std::fs::read(&path) {
+                Ok(octets) => octets,
+                Err(err) => {
+                    error!("keeping previous texture for {}: {err}", path.display());
+                    continue;
+                }
+            };
+            let label = path.display().to_string();
+
+            let handles: Vec<MaterialHandle> = self
+                .materials
+                .iter()
+                .filter(|(_, material)| material.source_path.as_deref() == Some(path.as_path()))
+                .map(|(handle, _)| handle)
+                .collect();
+
+            for handle in handles {
+                match swamp_wgpu_sprites::texture_formats::load_texture(
+                    &self.device,
+                    &self.queue,
+                    &octets,
+                    ImageFileFormat::from_path(&path),
+                    &label,
+                ) {
+                    Ok(texture) => self.set_material_texture(handle, texture, &label),
+                    Err(err) => {
+                        // Often the file is still being written, there will be another event
+                        error!("keeping previous texture for {}: {err}", path.display());
+                    }
+                }
+            }
+            info!("reloaded texture {}", path.display());
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Draw {
+    Instances(std::ops::Range<u32>),
+    /// Indices are relative to `base_vertex`
+    Mesh {
+        indices: std::ops::Range<u32>,
+        base_vertex: i32,
+    },
+}
+
+/// Drawn with a single draw call.
+#[derive(Debug)]
+struct DrawBatch {
+    material: MaterialHandle,
+    blend: BlendMode,
+    draw: Draw,
+}
+
+/// Vertices and indices of all meshes of a frame.
+#[derive(Debug, Default)]
+struct MeshData {
+    vertices: Vec<SpriteVertex>,
+    indices: Vec<u16>,
+}
+
+/// Instances in a row with the same material and blend mode are merged into one batch.
+fn push_draw_batch(
+    batches: &mut Vec<DrawBatch>,
+    material: MaterialHandle,
+    blend: BlendMode,
+    draw: Draw,
+) {
+    if let (Some(batch), Draw::Instances(instances)) = (batches.last_mut(), &draw) {
+        if let Draw::Instances(previous) = &mut batch.draw {
+            if batch.material == material && batch.blend == blend && previous.end == instances.start
+            {
+                previous.end = instances.end;
+                return;
+            }
+        }
+    }
+
+    batches.push(DrawBatch {
+        material,
+        blend,
+        draw,
+    });
+}
+
+fn push_layer_batch(
+    batches: &mut Vec<DrawBatch>,
+    instances: &mut Vec<SpriteInstanceUniform>,
+    mesh: &mut MeshData,
+    layer_batch: LayerBatch,
+) {
+    let draw = match layer_batch.geometry {
+        LayerGeometry::Instances(layer_instances) => {
+            let start = instances.len() as u32;
+            instances.extend(layer_instances);
+            Draw::Instances(start..instances.len() as u32)
+        }
+        LayerGeometry::Mesh { vertices, indices } => {
+            let base_vertex = mesh.vertices.len() as i32;
+            let start = mesh.indices.len() as u32;
+            mesh.vertices.extend(vertices);
+            mesh.indices.extend(indices);
+            Draw::Mesh {
+                indices: start..mesh.indices.len() as u32,
+                base_vertex,
+            }
+        }
+    };
+
+    push_draw_batch(batches, layer_batch.material, layer_batch.blend, draw);
+}
+
+/// Replaces the tint of everything in a batch with a color of its own, for
+/// [`DebugSettings::batch_colors`].
+fn tint_batches(
+    batches: &[DrawBatch],
+    instances: &mut [SpriteInstanceUniform],
+    mesh: &mut MeshData,
+) {
+    for (index, batch) in batches.iter().enumerate() {
+        let color = debug::batch_color(index);
+        match &batch.draw {
+            Draw::Instances(range) => {
+                for instance in &mut instances[range.start as usize..range.end as usize] {
+                    *instance = instance.with_tint(color);
+                }
+            }
+            Draw::Mesh {
+                indices,
+                base_vertex,
+            } => {
+                for &vertex_index in &mesh.indices[indices.start as usize..indices.end as usize] {
+                    mesh.vertices[*base_vertex as usize + usize::from(vertex_index)].color = color;
+                }
+            }
+        }
+    }
+}
+
+fn sort_sprites_by_z_then_y(sprites: &mut [Sprite]) {
+    sprites.sort_by_key(|sprite| (sprite.position.z, sprite.position.y));
+}
+
+/// `texture_size` is `None` when the whole texture should be shown.
+fn sprite_instance(sprite: &Sprite, texture_size: Option<UVec2>) -> SpriteInstanceUniform {
+    let size = sprite.params.dest_size.unwrap_or(sprite.atlas_rect.size);
+    let model_matrix = sprite_model(sprite, size);
+
+    let (mut u, mut v, mut u_scale, mut v_scale) = match texture_size {
+        Some(texture_size) => {
+            let atlas = sprite.atlas_rect;
+            let texture_width: f32 = texture_size.x.into();
+            let texture_height: f32 = texture_size.y.into();
+            (
+                f32::from(atlas.position.x) / texture_width,
+                f32::from(atlas.position.y) / texture_height,
+                f32::from(atlas.size.x) / texture_width,
+                f32::from(atlas.size.y) / texture_height,
+            )
+        }
+        None => (0.0, 0.0, 1.0, 1.0),
+    };
+
+    if sprite.params.flip_x {
+        u += u_scale;
+        u_scale = -u_scale;
+    }
+    if sprite.params.flip_y {
+        v += v_scale;
+        v_scale = -v_scale;
+    }
+
+    SpriteInstanceUniform::new(
+        model_matrix,
+        FVec4([u, v, u_scale, v_scale]),
+        sprite_tiling(sprite, size),
+        sprite.params.palette_row.into(),
+    )
+    .with_effects(sprite_effects(sprite, size))
+}
+
+/// Maps the unit quad to pixels, scaled to `size` and rotated around the pivot. Flips only
+/// mirror the texture coordinates, so they do not change the quad.
+fn sprite_model(sprite: &Sprite, size: UVec2) -> Mx4 {
+    let pivot = sprite.params.pivot.unwrap_or_default();
+    let pivot_x = f32::from(pivot.x);
+    let pivot_y = f32::from(pivot.y);
+
+    Mx4::from_translation(
+        f32::from(sprite.position.x) + pivot_x,
+        f32::from(sprite.position.y) + pivot_y,
+        0.0,
+    ) * Mx4::from_rotation_z(f32::from(sprite.params.rotation).to_radians())
+        * Mx4::from_translation(-pivot_x, -pivot_y, 0.0)
+        * Mx4::from_scale(size.x.into(), size.y.into(), 1.0)
+}
+
+/// Lower left and upper right corner in pixels, including the outline and the drop shadow.
+/// The quad is transformed the same way as in [`sprite_instance`].
+fn sprite_bounds(sprite: &Sprite) -> ([f32; 2], [f32; 2]) {
+    let size = sprite.params.dest_size.unwrap_or(sprite.atlas_rect.size);
+    let effects = sprite_effects(sprite, size);
+
+    // The same margin as in the sprite vertex shader, in unit quad coordinates
+    let outline_texels: f32 = if effects.outline_color[3] > 0 {
+        1.0
+    } else {
+        0.0
+    };
+    let margin = |axis: usize| {
+        let shadow_texels = if effects.shadow_color[3] > 0 {
+            effects.shadow_offset[axis].abs()
+        } else {
+            0.0
+        };
+        outline_texels.max(shadow_texels) * effects.texel_size[axis]
+    };
+    let (margin_x, margin_y) = (margin(0), margin(1));
+
+    let model = sprite_model(sprite, size);
+    let corners = [
+        [-margin_x, -margin_y],
+        [1.0 + margin_x, -margin_y],
+        [-margin_x, 1.0 + margin_y],
+        [1.0 + margin_x, 1.0 + margin_y],
+    ]
+    .map(|corner| model.transform_point2(corner));
+
+    corners.iter().fold(
+        ([f32::INFINITY; 2], [f32::NEG_INFINITY; 2]),
+        |(min, max), corner| {
+            (
+                [min[0].min(corner[0]), min[1].min(corner[1])],
+                [max[0].max(corner[0]), max[1].max(corner[1])],
+            )
+        },
+    )
+}
+
+/// True if any part of the sprite bounds is inside of the viewport.
+fn is_sprite_visible(sprite: &Sprite, viewport: [f32; 2]) -> bool {
+    let (min, max) = sprite_bounds(sprite);
+    max[0] > 0.0 && max[1] > 0.0 && min[0] < viewport[0] && min[1] < viewport[1]
+}
+
+/// `size` is the destination size of the sprite in pixels.
+fn sprite_effects(sprite: &Sprite, size: UVec2) -> SpriteEffects {
+    let params = &sprite.params;
+    if params.outline.is_none() && params.drop_shadow.is_none() && params.silhouette.is_none() {
+        return SpriteEffects::default();
+    }
+
+    // The unit quad spans the atlas rect once, or the destination size when tiled
+    let texels = match params.fill {
+        FillMode::Stretch => sprite.atlas_rect.size,
+        FillMode::Tile { .. } => size,
+    };
+    let (shadow_offset, shadow_color) = params.drop_shadow.map_or(([0.0; 2], [0; 4]), |shadow| {
+        (
+            [f32::from(shadow.offset.x), -f32::from(shadow.offset.y)],
+            shadow.color,
+        )
+    });
+
+    SpriteEffects {
+        texel_size: [
+            1.0 / f32::from(texels.x.max(1)),
+            1.0 / f32::from(texels.y.max(1)),
+        ],
+        shadow_offset,
+        outline_color: params.outline.unwrap_or_default(),
+        shadow_color,
+        silhouette_color: params.silhouette.unwrap_or_default(),
+    }
+}
+
+fn sprite_tiling(sprite: &Sprite, size: UVec2) -> FVec4 {
+    let FillMode::Tile { offset } = sprite.params.fill else {
+        return SpriteInstanceUniform::NO_TILING;
+    };
+    let tile_width = f32::from(sprite.atlas_rect.size.x.max(1));
+    let tile_height = f32::from(sprite.atlas_rect.size.y.max(1));
+
+    FVec4([
+        f32::from(size.x) / tile_width,
+        f32::from(size.y) / tile_height,
+        f32::from(offset.x) / tile_width,
+        f32::from(offset.y) / tile_height,
+    ])
+}
+
+/// Samplers are created on first use and shared by all materials with the same options.
+fn create_material_bind_group(
+    device: &wgpu::Device,
+    bind_group_layout: &BindGroupLayout,
+    samplers: &mut HashMap<SamplerOptions, wgpu::Sampler>,
+    texture: &wgpu::Texture,
+    sampler: SamplerOptions,
+    label: &str,
+) -> BindGroup {
+    let sampler = samplers
+        .entry(sampler)
+        .or_insert_with(|| swamp_wgpu::create_sampler(device, &format!("{sampler:?}"), sampler));
+
+    swamp_wgpu::create_texture_and_sampler_bind_group(
+        device,
+        bind_group_layout,
+        sampler,
+        texture,
+        label,
+    )
+}
+
+fn create_custom_material_bind_group_layout(
+    device: &wgpu::Device,
+    label: &str,
+    texture_count: usize,
+) -> BindGroupLayout {
+    let mut entries = vec![wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }];
+    for index in 0..texture_count {
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: index as u32 + 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        });
+    }
+
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some(label),
+        entries: &entries,
+    })
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct RenderStats {
+    pub material_count: usize,
+    pub textures: Vec<TextureStats>,
+    /// Sum of [`TextureStats::octet_size`]
+    pub texture_octets: u64,
+}
+
+impl RenderStats {
+    fn add_texture(&mut self, texture: &wgpu::Texture) {
+        let octet_size = swamp_wgpu::texture_octet_size(texture);
+        self.texture_octets += octet_size;
+        self.textures.push(TextureStats {
+            width: texture.width(),
+            height: texture.height(),
+            format: texture.format(),
+            octet_size,
+        });
+    }
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SpriteCounters {
+    pub drawn: usize,
+    /// Outside of the viewport, see [`Render::set_culling`]
+    pub culled: usize,
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Including the culled sprites
+    pub sprites_submitted: usize,
+    pub sprites: SpriteCounters,
+    /// Sprites, particles and meshes that share a material and a blend mode
+    pub batches: usize,
+    pub draw_calls: usize,
+    pub pipeline_switches: usize,
+    pub material_switches: usize,
+    /// Uploaded sprite and particle instances
+    pub instance_octets: u64,
+    /// Uploaded mesh vertices and indices
+    pub vertex_octets: u64,
+    /// Textures of all materials, see [`Render::stats`]
+    pub texture_octets: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextureStats {
+    pub width: u32,
+    pub height: u32,
+    pub format: TextureFormat,
+    /// Including all mip levels
+    pub octet_size: u64,
+}
+
+/// How the atlas rect covers `dest_size`.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FillMode {
+    #[default]
+    Stretch,
+    /// Repeats the atlas rect at its own size, also when it is only a part of an atlas.
+    /// `offset` is in texels and scrolls the tiles, e.g. for water or conveyor belts.
+    Tile { offset: Vec2 },
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct SpriteParams {
+    pub dest_size: Option<UVec2>,
+    pub source: Option<URect>,
+    /// Degrees, counter clockwise around the pivot
+    pub rotation: u16,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// In pixels from the lower left corner, which is the pivot when `None`
+    pub pivot: Option<Vec2>,
+    pub fill: FillMode,
+    /// Palette of indexed materials, see [`Render::create_material_indexed`]
+    pub palette_row: u16,
+    /// A one texel outline around the opaque texels, in straight alpha sRGB
+    pub outline: Option<[u8; 4]>,
+    pub drop_shadow: Option<DropShadow>,
+    /// Draws the opaque texels in a single color, e.g. for drawing the sprite again on top of
+    /// the walls that hide it
+    pub silhouette: Option<[u8; 4]>,
+}
+
+/// For [`Render::render_mesh`].
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MeshParams {
+    /// Drawn after the sprites with the same `z`
+    pub z: i16,
+    /// Added to all vertex positions, in pixels
+    pub offset: Vec2,
+    /// Atlas rect that the vertex texture coordinates 0.0 to 1.0 span, `None` uses the
+    /// whole texture
+    pub source: Option<URect>,
+    pub blend: BlendMode,
+}
+
+/// Like the outline and the silhouette, only drawn by the built-in sprite shader.
+/// Custom and indexed materials ignore it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DropShadow {
+    /// In texels, with `y` pointing up like sprite positions
+    pub offset: Vec2,
+    /// Straight alpha sRGB
+    pub color: [u8; 4],
+}
+
+/// Drawn after the sprites with the same `z`, from [`Render::render_particles`],
+/// [`Render::render_trail`] and [`Render::render_mesh`].
+#[derive(Debug)]
+struct LayerBatch {
+    material: MaterialHandle,
+    z: i16,
+    blend: BlendMode,
+    geometry: LayerGeometry,
+}
+
+#[derive(Debug)]
+enum LayerGeometry {
+    Instances(Vec<SpriteInstanceUniform>),
+    /// Indices start at zero for each mesh
+    Mesh {
+        vertices: Vec<SpriteVertex>,
+        indices: Vec<u16>,
+    },
+}
+
+#[derive(Debug)]
+pub struct Sprite {
+    pub position: Vec3,
+    pub atlas_rect: URect,
+    pub material: MaterialHandle,
+    pub params: SpriteParams,
+}
+
+/// Sprites recorded away from [`Render`], e.g. on a simulation thread, and handed over
+/// with [`Render::submit_batch`].
+#[derive(Debug, Default)]
+pub struct SpriteBatch {
+    sprites: Vec<Sprite>,
+}
+
+impl SpriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            sprites: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn render_sprite(
+        &mut self,
+        position: Vec3,
+        atlas_rect: URect,
+        material: MaterialHandle,
+        params: SpriteParams,
+    ) {
+        self.sprites.push(Sprite {
+            position,
+            atlas_rect,
+            material,
+            params,
+        })
+    }
+
+    pub fn render_sprite_2d(
+        &mut self,
+        position: Vec2,
+        atlas_rect: URect,
+        material: MaterialHandle,
+        params: SpriteParams,
+    ) {
+        self.render_sprite(position.into(), atlas_rect, material, params)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sprites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sprites.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.sprites.clear();
+    }
+}
+
+pub type RenderPipelineRef = Arc<RenderPipeline>;
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct SpriteMaterial {
-    pub bind_group: BindGroup,
+    /// Replaced when the image is reloaded, see [`Render::replace_material_image`]
+    pub texture: MaterialTexture,
     pub render_pipeline: RenderPipelineRef,
+    pub custom: Option<CustomMaterialBinding>,
+    /// Set for materials created with [`Render::create_material_from_path`]
+    /// and [`Render::load_material_async`]
+    pub source_path: Option<PathBuf>,
+    pub load_state: MaterialLoadState,
+    /// See [`Render::set_material_sampler`]
+    pub sampler: SamplerOptions,
+    /// Set for materials created with [`Render::create_material_indexed`],
+    /// [`MaterialTexture::texture`] then holds the indices
+    pub palette: Option<wgpu::Texture>,
+    /// See [`Render::set_material_lighting`]
+    pub lighting: Option<LitMaterialBinding>,
+}
+
+impl SpriteMaterial {
+    pub fn texture_size(&self) -> UVec2 {
+        self.texture.size
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.palette.is_some()
+    }
+
+    pub fn is_lit(&self) -> bool {
+        self.lighting.is_some()
+    }
+
+    /// Other materials use the sprite pipeline of [`Render`], which follows shader reloads.
+    fn has_own_pipeline(&self) -> bool {
+        self.custom.is_some() || self.is_indexed() || self.is_lit()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct MaterialTexture {
+    pub texture: wgpu::Texture,
+    pub bind_group: BindGroup,
+    pub size: UVec2,
+}
+
+/// The extra bind group (`@group(2)`) of a custom material.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CustomMaterialBinding {
+    pub uniform_buffer: wgpu::Buffer,
+    pub bind_group: BindGroup,
+    /// The extra textures, bound from `@binding(1)`
+    pub textures: Vec<wgpu::Texture>,
+}
+
+#[derive(Debug)]
+pub enum CustomMaterialError {
+    /// The textures must at least hold the sprite texture
+    MissingSpriteTexture,
+    Shader(ShaderError),
+    Texture(TextureLoadError),
+}
+
+impl Display for CustomMaterialError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingSpriteTexture => write!(f, "custom material needs the sprite texture"),
+            Self::Shader(err) => write!(f, "{err}"),
+            Self::Texture(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for CustomMaterialError {}
+
+impl From<ShaderError> for CustomMaterialError {
+    fn from(err: ShaderError) -> Self {
+        Self::Shader(err)
+    }
+}
+
+impl From<TextureLoadError> for CustomMaterialError {
+    fn from(err: TextureLoadError) -> Self {
+        Self::Texture(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use material_registry::MaterialRegistry;
+
+    const VIEWPORT: [f32; 2] = [320.0, 240.0];
+
+    fn sprite(x: i16, y: i16, width: u16, height: u16, params: SpriteParams) -> Sprite {
+        Sprite {
+            position: Vec2::new(x, y).into(),
+            atlas_rect: URect::new(0, 0, width, height),
+            material: MaterialRegistry::new().insert(()),
+            params,
+        }
+    }
+
+    fn assert_bounds(sprite: &Sprite, min: [f32; 2], max: [f32; 2]) {
+        let (actual_min, actual_max) = sprite_bounds(sprite);
+        let near =
+            |a: [f32; 2], b: [f32; 2]| (a[0] - b[0]).abs() < 1e-3 && (a[1] - b[1]).abs() < 1e-3;
+        assert!(
+            near(actual_min, min) && near(actual_max, max),
+            "{actual_min:?} {actual_max:?}"
+        );
+    }
+
+    #[test]
+    fn partially_visible_sprite_is_kept() {
+        let left_edge = sprite(-8, 100, 16, 16, SpriteParams::default());
+        assert_bounds(&left_edge, [-8.0, 100.0], [8.0, 116.0]);
+        assert!(is_sprite_visible(&left_edge, VIEWPORT));
+
+        let top_right = sprite(310, 230, 16, 16, SpriteParams::default());
+        assert!(is_sprite_visible(&top_right, VIEWPORT));
+
+        let outside = sprite(-16, 100, 16, 16, SpriteParams::default());
+        assert!(!is_sprite_visible(&outside, VIEWPORT));
+    }
+
+    #[test]
+    fn bounds_follow_rotation_around_the_pivot() {
+        let rotated = |pivot| SpriteParams {
+            rotation: 90,
+            pivot,
+            ..SpriteParams::default()
+        };
+
+        // Around the lower left corner, the sprite now extends to the left
+        let corner = sprite(100, 50, 20, 10, rotated(None));
+        assert_bounds(&corner, [90.0, 50.0], [100.0, 70.0]);
+
+        let center = sprite(100, 50, 20, 10, rotated(Some(Vec2::new(10, 5))));
+        assert_bounds(&center, [105.0, 45.0], [115.0, 65.0]);
+
+        // Visible unrotated, but rotated completely to the left of the viewport
+        assert!(is_sprite_visible(
+            &sprite(-5, 50, 20, 10, SpriteParams::default()),
+            VIEWPORT
+        ));
+        assert!(!is_sprite_visible(
+            &sprite(-5, 50, 20, 10, rotated(None)),
+            VIEWPORT
+        ));
+    }
+
+    #[test]
+    fn flips_keep_the_bounds() {
+        let params = SpriteParams {
+            rotation: 30,
+            pivot: Some(Vec2::new(4, 2)),
+            ..SpriteParams::default()
+        };
+        let flipped = SpriteParams {
+            flip_x: true,
+            flip_y: true,
+            ..params.clone()
+        };
+
+        assert_eq!(
+            sprite_bounds(&sprite(40, 40, 16, 8, params)),
+            sprite_bounds(&sprite(40, 40, 16, 8, flipped))
+        );
+    }
+
+    #[test]
+    fn bounds_include_the_outline_and_the_drop_shadow() {
+        let params = SpriteParams {
+            dest_size: Some(UVec2::new(32, 32)),
+            outline: Some([0, 0, 0, 255]),
+            drop_shadow: Some(DropShadow {
+                offset: Vec2::new(3, 0),
+                color: [0, 0, 0, 128],
+            }),
+            ..SpriteParams::default()
+        };
+
+        // Texels of the 16x16 atlas rect are two pixels each
+        assert_bounds(&sprite(0, 0, 16, 16, params), [-6.0, -2.0], [38.0, 34.0]);
+    }
 }
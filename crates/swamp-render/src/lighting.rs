@@ -0,0 +1,414 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+use crate::shadows::{OccluderEdge, ShadowGeometry};
+use bytemuck::{Pod, Zeroable};
+use int_math::Vec2;
+use log::warn;
+use swamp_wgpu::shader_validation::{BindingKind, ExpectedBinding};
+use wgpu::{BindGroup, BindGroupLayout, Buffer};
+
+pub(crate) const SPRITE_LIT_SHADER_SOURCE: &str = include_str!("shaders/lighting/sprite_lit.wgsl");
+
+/// Lights beyond this count are skipped, the lights are applied in a single forward pass.
+pub const MAX_LIGHTS: usize = 32;
+
+/// Occluder edges are tested per light, edges beyond this count cast no shadows.
+pub const MAX_SHADOW_EDGES: usize = 1024;
+
+pub(crate) const LIT_BINDINGS: &[ExpectedBinding] = &[
+    ExpectedBinding::new(1, 0, BindingKind::Texture2d),
+    ExpectedBinding::new(1, 1, BindingKind::Sampler),
+    ExpectedBinding::new(2, 0, BindingKind::UniformBuffer),
+    ExpectedBinding::new(2, 1, BindingKind::Texture2d),
+    ExpectedBinding::new(2, 2, BindingKind::UniformBuffer),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LightKind {
+    Point,
+    /// Angles are in radians, `direction` is counter clockwise from the positive x axis.
+    /// The light fades out between the inner and the outer half angle of the cone, an inner
+    /// angle that is not smaller than the outer angle gives a hard edge.
+    Spot {
+        direction: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LightShadow {
+    None,
+    Hard,
+    /// Soft edges from a light that is a disc with this radius in pixels
+    Soft {
+        source_radius: u16,
+    },
+}
+
+/// Added for a single frame with [`crate::Render::render_light`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Light {
+    /// In pixels, like sprite positions
+    pub position: Vec2,
+    /// Pixels above the sprites, lower lights make normal maps stand out more
+    pub height: u16,
+    /// Nothing is lit beyond the radius
+    pub radius: u16,
+    /// sRGB
+    pub color: [u8; 3],
+    pub intensity: f32,
+    /// Exponent of the fade towards the radius, 1.0 is linear
+    pub falloff: f32,
+    pub kind: LightKind,
+    /// Shadows from the edges of [`crate::Render::set_occluders`]
+    pub shadow: LightShadow,
+}
+
+impl Light {
+    pub fn point(position: Vec2, radius: u16, color: [u8; 3]) -> Self {
+        Self {
+            position,
+            height: 16,
+            radius,
+            color,
+            intensity: 1.0,
+            falloff: 1.0,
+            kind: LightKind::Point,
+            shadow: LightShadow::Hard,
+        }
+    }
+
+    pub fn spot(
+        position: Vec2,
+        radius: u16,
+        color: [u8; 3],
+        direction: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                direction,
+                inner_angle: outer_angle * 0.75,
+                outer_angle,
+            },
+            ..Self::point(position, radius, color)
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LightingSettings {
+    /// sRGB
+    pub ambient: [u8; 3],
+    pub ambient_intensity: f32,
+    /// The number of brightness bands per light, zero gives smooth lighting.
+    /// Quantized lighting is also evaluated once per whole pixel.
+    pub quantize_steps: u8,
+}
+
+impl Default for LightingSettings {
+    fn default() -> Self {
+        Self {
+            ambient: [255, 255, 255],
+            ambient_intensity: 0.2,
+            quantize_steps: 4,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct LightUniform {
+    /// Position in `xy`, height in `z` and radius in `w`
+    position: [f32; 4],
+    /// Linear color times intensity in `rgb`, falloff in `w`
+    color: [f32; 4],
+    /// Direction in `xy`, cosine of the inner and outer angle in `zw`
+    spot: [f32; 4],
+    /// Range in the shadow edges
+    first_edge: u32,
+    edge_count: u32,
+    /// Zero for hard shadows
+    source_radius: f32,
+    _pad0: f32,
+}
+
+unsafe impl Pod for LightUniform {}
+unsafe impl Zeroable for LightUniform {}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct LightingUniform {
+    ambient: [f32; 4],
+    light_count: u32,
+    quantize_steps: u32,
+    _pad0: u32,
+    _pad1: u32,
+    lights: [LightUniform; MAX_LIGHTS],
+}
+
+unsafe impl Pod for LightingUniform {}
+unsafe impl Zeroable for LightingUniform {}
+
+impl LightUniform {
+    fn new(light: &Light, (first_edge, edge_count): (u32, u32)) -> Self {
+        let [r, g, b] = srgb_to_linear(light.color).map(|channel| channel * light.intensity);
+        let spot = match light.kind {
+            // An outer cosine of -1.0 turns the cone off
+            LightKind::Point => [1.0, 0.0, -1.0, -1.0],
+            LightKind::Spot {
+                direction,
+                inner_angle,
+                outer_angle,
+            } => {
+                // The shader fades with smoothstep, which needs distinct edges
+                let outer_cos = outer_angle.cos();
+                [
+                    direction.cos(),
+                    direction.sin(),
+                    inner_angle.cos().max(outer_cos + 1e-4),
+                    outer_cos,
+                ]
+            }
+        };
+
+        Self {
+            position: [
+                light.position.x.into(),
+                light.position.y.into(),
+                light.height.into(),
+                f32::from(light.radius.max(1)),
+            ],
+            color: [r, g, b, light.falloff],
+            spot,
+            first_edge,
+            edge_count,
+            source_radius: match light.shadow {
+                LightShadow::Soft { source_radius } => source_radius.into(),
+                LightShadow::None | LightShadow::Hard => 0.0,
+            },
+            _pad0: 0.0,
+        }
+    }
+}
+
+fn srgb_to_linear(color: [u8; 3]) -> [f32; 3] {
+    color.map(|channel| {
+        let value = f32::from(channel) / 255.0;
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    })
+}
+
+/// Normal maps are bound next to the shared light buffer in `@group(2)` of lit materials.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LitMaterialBinding {
+    /// `None` lights the material with flat normals
+    pub normal_map: Option<wgpu::Texture>,
+    pub bind_group: BindGroup,
+}
+
+#[derive(Debug)]
+pub(crate) struct Lighting {
+    pub settings: LightingSettings,
+    lights: Vec<Light>,
+    occluders: Vec<OccluderEdge>,
+    buffer: Buffer,
+    edge_buffer: Buffer,
+    pub bind_group_layout: BindGroupLayout,
+    flat_normal_map: wgpu::Texture,
+}
+
+impl Lighting {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let buffer = swamp_wgpu::create_uniform_buffer_with_octets(
+            device,
+            "lighting uniform buffer",
+            bytemuck::bytes_of(&LightingUniform::zeroed()),
+        );
+        let edge_buffer = swamp_wgpu::create_uniform_buffer_with_octets(
+            device,
+            "shadow edge buffer",
+            bytemuck::cast_slice(&[[0.0f32; 4]; MAX_SHADOW_EDGES]),
+        );
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lighting bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let flat_normal_map = swamp_wgpu_sprites::create_texture_from_rgba(
+            device,
+            queue,
+            &image::RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 255, 255])),
+            "flat normal map",
+            wgpu::TextureFormat::Rgba8Unorm,
+        );
+
+        Self {
+            settings: LightingSettings::default(),
+            lights: Vec::new(),
+            occluders: Vec::new(),
+            buffer,
+            edge_buffer,
+            bind_group_layout,
+            flat_normal_map,
+        }
+    }
+
+    pub fn add_light(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+
+    pub fn set_occluders(&mut self, occluders: Vec<OccluderEdge>) {
+        self.occluders = occluders;
+    }
+
+    pub fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        normal_map: Option<&wgpu::Texture>,
+        label: &str,
+    ) -> BindGroup {
+        let normal_view = normal_map
+            .unwrap_or(&self.flat_normal_map)
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&normal_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.edge_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Writes the lights of this frame and clears them for the next one.
+    pub fn upload(&mut self, queue: &wgpu::Queue) {
+        if self.lights.len() > MAX_LIGHTS {
+            warn!(
+                "skipping {} lights, only {MAX_LIGHTS} are supported",
+                self.lights.len() - MAX_LIGHTS
+            );
+        }
+
+        let mut uniform = LightingUniform::zeroed();
+        let [r, g, b] = srgb_to_linear(self.settings.ambient)
+            .map(|channel| channel * self.settings.ambient_intensity);
+        uniform.ambient = [r, g, b, 1.0];
+        uniform.quantize_steps = self.settings.quantize_steps.into();
+
+        let lights = &self.lights[..self.lights.len().min(MAX_LIGHTS)];
+        let shadow_geometry = ShadowGeometry::build(
+            &self.occluders,
+            lights
+                .iter()
+                .filter(|light| light.shadow != LightShadow::None)
+                .map(|light| {
+                    (
+                        [light.position.x.into(), light.position.y.into()],
+                        light.radius.into(),
+                    )
+                }),
+            MAX_SHADOW_EDGES,
+        );
+        if shadow_geometry.is_full(MAX_SHADOW_EDGES) {
+            warn!("reached {MAX_SHADOW_EDGES} shadow edges, some lights cast no shadows");
+        }
+
+        let mut ranges = shadow_geometry.ranges.iter();
+        for (target, light) in uniform.lights.iter_mut().zip(lights) {
+            let range = if light.shadow == LightShadow::None {
+                (0, 0)
+            } else {
+                ranges.next().copied().unwrap_or_default()
+            };
+            *target = LightUniform::new(light, range);
+            uniform.light_count += 1;
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+        if !shadow_geometry.edges.is_empty() {
+            queue.write_buffer(
+                &self.edge_buffer,
+                0,
+                bytemuck::cast_slice(&shadow_geometry.edges),
+            );
+        }
+
+        self.lights.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spot(inner_angle: f32, outer_angle: f32) -> [f32; 4] {
+        let mut light = Light::spot(Vec2::new(0, 0), 100, [255; 3], 0.0, outer_angle);
+        light.kind = LightKind::Spot {
+            direction: 0.0,
+            inner_angle,
+            outer_angle,
+        };
+        LightUniform::new(&light, (0, 0)).spot
+    }
+
+    #[test]
+    fn spot_inner_cosine_is_above_the_outer_cosine() {
+        for (inner_angle, outer_angle) in [(0.5, 0.5), (0.8, 0.5), (0.0, 0.0), (1.0, 0.0)] {
+            let [_, _, inner_cos, outer_cos] = spot(inner_angle, outer_angle);
+            assert!(inner_cos > outer_cos, "{inner_angle} {outer_angle}");
+        }
+
+        let [_, _, inner_cos, outer_cos] = spot(0.25, 0.5);
+        assert_eq!(inner_cos, 0.25f32.cos());
+        assert_eq!(outer_cos, 0.5f32.cos());
+    }
+}
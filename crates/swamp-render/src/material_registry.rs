@@ -0,0 +1,188 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+/// Refers to a material owned by [`crate::Render`].
+///
+/// Handles are plain values, so they can be copied to and used from any thread.
+/// A handle to a destroyed material is detected, even if its slot has been reused.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MaterialHandle {
+    index: u32,
+    generation: u32,
+}
+
+impl MaterialHandle {
+    /// Unique for the lifetime of a [`crate::Render`], also after the slot has been reused.
+    pub(crate) fn to_bits(self) -> u64 {
+        (u64::from(self.generation) << 32) | u64::from(self.index)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn from_bits(bits: u64) -> Self {
+        Self {
+            index: bits as u32,
+            generation: (bits >> 32) as u32,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+/// Generational slots, freed slots are reused with a new generation.
+#[derive(Debug)]
+pub struct MaterialRegistry<T> {
+    slots: Vec<Slot<T>>,
+    free_indices: Vec<u32>,
+}
+
+impl<T> Default for MaterialRegistry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> MaterialRegistry<T> {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free_indices: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, value: T) -> MaterialHandle {
+        if let Some(index) = self.free_indices.pop() {
+            let slot = &mut self.slots[index as usize];
+            slot.value = Some(value);
+            return MaterialHandle {
+                index,
+                generation: slot.generation,
+            };
+        }
+
+        let index = self.slots.len() as u32;
+        self.slots.push(Slot {
+            generation: 0,
+            value: Some(value),
+        });
+        MaterialHandle {
+            index,
+            generation: 0,
+        }
+    }
+
+    pub fn remove(&mut self, handle: MaterialHandle) -> Option<T> {
+        let slot = self.slots.get_mut(handle.index as usize)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        let value = slot.value.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free_indices.push(handle.index);
+
+        Some(value)
+    }
+
+    pub fn get(&self, handle: MaterialHandle) -> Option<&T> {
+        let slot = self.slots.get(handle.index as usize)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        slot.value.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: MaterialHandle) -> Option<&mut T> {
+        let slot = self.slots.get_mut(handle.index as usize)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        slot.value.as_mut()
+    }
+
+    pub fn contains(&self, handle: MaterialHandle) -> bool {
+        self.get(handle).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free_indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (MaterialHandle, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.value.as_ref().map(|value| {
+                (
+                    MaterialHandle {
+                        index: index as u32,
+                        generation: slot.generation,
+                    },
+                    value,
+                )
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_handle_is_rejected_after_slot_reuse() {
+        let mut registry = MaterialRegistry::new();
+        let stale = registry.insert("first");
+        assert_eq!(registry.remove(stale), Some("first"));
+
+        let reused = registry.insert("second");
+        assert_eq!(reused.index, stale.index);
+        assert_ne!(reused, stale);
+
+        assert!(!registry.contains(stale));
+        assert_eq!(registry.get(stale), None);
+        assert_eq!(registry.get_mut(stale), None);
+        assert_eq!(registry.remove(stale), None);
+        assert_eq!(registry.get(reused), Some(&"second"));
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn removing_twice_frees_the_slot_once() {
+        let mut registry = MaterialRegistry::new();
+        let handle = registry.insert(1);
+        assert_eq!(registry.remove(handle), Some(1));
+        assert_eq!(registry.remove(handle), None);
+
+        let first = registry.insert(2);
+        let second = registry.insert(3);
+        assert_ne!(first.index, second.index);
+        assert_eq!(registry.len(), 2);
+    }
+
+    #[test]
+    fn bits_round_trip() {
+        let mut registry = MaterialRegistry::new();
+        let first = registry.insert(());
+        registry.remove(first);
+        let reused = registry.insert(());
+        let other = registry.insert(());
+
+        for handle in [first, reused, other] {
+            assert_eq!(MaterialHandle::from_bits(handle.to_bits()), handle);
+        }
+        assert_ne!(first.to_bits(), reused.to_bits());
+
+        let high = MaterialHandle {
+            index: u32::MAX,
+            generation: u32::MAX - 1,
+        };
+        assert_eq!(MaterialHandle::from_bits(high.to_bits()), high);
+    }
+}
@@ -0,0 +1,569 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+use int_math::{URect, UVec2, Vec2};
+use std::fmt::{Display, Formatter};
+use swamp_wgpu_sprites::{BlendMode, FVec4, Mx4, SpriteInstanceUniform};
+
+/// Values that can be interpolated by a [`Curve`].
+pub trait Interpolate: Copy {
+    fn interpolate(self, other: Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for [u8; 4] {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        std::array::from_fn(|index| {
+            f32::from(self[index])
+                .interpolate(f32::from(other[index]), t)
+                .round() as u8
+        })
+    }
+}
+
+/// Linear interpolation between keys, with time from 0.0 at birth to 1.0 at death.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve<T> {
+    /// Sorted by time
+    keys: Vec<(f32, T)>,
+}
+
+impl<T: Interpolate> Curve<T> {
+    pub fn constant(value: T) -> Self {
+        Self {
+            keys: vec![(0.0, value)],
+        }
+    }
+
+    pub fn linear(start: T, end: T) -> Self {
+        Self {
+            keys: vec![(0.0, start), (1.0, end)],
+        }
+    }
+
+    /// `keys` are `(time, value)` pairs and are sorted by time.
+    pub fn new(mut keys: Vec<(f32, T)>) -> Result<Self, EmptyCurveError> {
+        if keys.is_empty() {
+            return Err(EmptyCurveError);
+        }
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Self { keys })
+    }
+
+    pub fn sample(&self, time: f32) -> T {
+        let after = self.keys.partition_point(|(key_time, _)| *key_time <= time);
+        match (after.checked_sub(1), self.keys.get(after)) {
+            (Some(before), Some(&(end_time, end))) => {
+                let (start_time, start) = self.keys[before];
+                let span = end_time - start_time;
+                let t = if span > 0.0 {
+                    (time - start_time) / span
+                } else {
+                    1.0
+                };
+                start.interpolate(end, t)
+            }
+            (Some(before), None) => self.keys[before].1,
+            (None, _) => self.keys[0].1,
+        }
+    }
+}
+
+/// A [`Curve`] needs at least one key.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EmptyCurveError;
+
+impl Display for EmptyCurveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "a curve needs at least one key")
+    }
+}
+
+impl std::error::Error for EmptyCurveError {}
+
+/// A random value between `min` and `max` for each particle.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Range {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Range {
+    pub const fn new(min: f32, max: f32) -> Self {
+        Self { min, max }
+    }
+
+    pub const fn constant(value: f32) -> Self {
+        Self::new(value, value)
+    }
+
+    fn sample(self, rng: &mut ParticleRng) -> f32 {
+        self.min + (self.max - self.min) * rng.next_f32()
+    }
+}
+
+/// Particles emitted at once when the emitter reaches `time` seconds.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Burst {
+    pub time: f32,
+    pub count: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FrameAnimation {
+    /// The frames are spread over the lifetime of each particle
+    OverLife,
+    PerSecond(f32),
+    /// Each particle keeps a random frame
+    Random,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmitterSettings {
+    /// Particles per second, emitted while [`ParticleEmitter::emitting`] is set
+    pub rate: f32,
+    pub bursts: Vec<Burst>,
+    /// Emission stops and the bursts restart after this many seconds, `None` runs forever
+    pub duration: Option<f32>,
+    pub looping: bool,
+    /// Seconds
+    pub lifetime: Range,
+    /// Particles start at a random point within this many pixels of the emitter
+    pub spawn_radius: f32,
+    /// Radians, counter clockwise from the positive x axis
+    pub direction: f32,
+    /// Radians on each side of `direction`
+    pub spread: f32,
+    /// Pixels per second
+    pub speed: Range,
+    /// Pixels per second squared, `y` points up
+    pub gravity: [f32; 2],
+    /// Straight alpha sRGB tint
+    pub color_over_life: Curve<[u8; 4]>,
+    /// Multiplied with the size of the atlas frame
+    pub size_over_life: Curve<f32>,
+    /// Atlas rects in the material texture
+    pub frames: Vec<URect>,
+    pub frame_animation: FrameAnimation,
+    pub blend: BlendMode,
+    /// New particles are skipped while this many are alive
+    pub max_particles: usize,
+}
+
+impl Default for EmitterSettings {
+    fn default() -> Self {
+        Self {
+            rate: 10.0,
+            bursts: Vec::new(),
+            duration: None,
+            looping: true,
+            lifetime: Range::constant(1.0),
+            spawn_radius: 0.0,
+            direction: std::f32::consts::FRAC_PI_2,
+            spread: 0.3,
+            speed: Range::new(20.0, 40.0),
+            gravity: [0.0, 0.0],
+            color_over_life: Curve::linear([255, 255, 255, 255], [255, 255, 255, 0]),
+            size_over_life: Curve::constant(1.0),
+            frames: Vec::new(),
+            frame_animation: FrameAnimation::OverLife,
+            blend: BlendMode::Alpha,
+            max_particles: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Particle {
+    /// In pixels, like sprite positions
+    pub position: [f32; 2],
+    pub velocity: [f32; 2],
+    /// Seconds since birth
+    pub age: f32,
+    pub lifetime: f32,
+    /// Start frame, used by [`FrameAnimation::Random`] and as an offset otherwise
+    pub frame: u16,
+}
+
+impl Particle {
+    /// From 0.0 at birth to 1.0 at death.
+    pub fn life(&self) -> f32 {
+        (self.age / self.lifetime).clamp(0.0, 1.0)
+    }
+}
+
+/// Small and fast, and gives the same sequence on every platform for the same seed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ParticleRng {
+    state: u64,
+}
+
+impl ParticleRng {
+    fn new(seed: u64) -> Self {
+        Self {
+            // Zero would only give zeros
+            state: seed ^ 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    // splitmix64
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// From 0.0 up to, but not including, 1.0.
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// Simulated on the CPU with a fixed seed, so the same settings, seed and time steps
+/// always give the same particles. Drawn with [`crate::Render::render_particles`].
+///
+/// There is no compute shader simulation yet, all emitters run on the CPU.
+#[derive(Debug, Clone)]
+pub struct ParticleEmitter {
+    pub settings: EmitterSettings,
+    /// In pixels, new particles start here
+    pub position: [f32; 2],
+    /// Rate based emission and bursts only happen while emitting
+    pub emitting: bool,
+    particles: Vec<Particle>,
+    rng: ParticleRng,
+    time: f32,
+    emit_accumulator: f32,
+}
+
+impl ParticleEmitter {
+    pub fn new(settings: EmitterSettings, position: Vec2, seed: u64) -> Self {
+        Self {
+            settings,
+            position: [position.x.into(), position.y.into()],
+            emitting: true,
+            particles: Vec::new(),
+            rng: ParticleRng::new(seed),
+            time: 0.0,
+            emit_accumulator: 0.0,
+        }
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// Seconds since start, wraps for looping emitters with a duration.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// True when it does not emit anymore and all particles are gone.
+    pub fn is_finished(&self) -> bool {
+        self.particles.is_empty()
+            && (!self.emitting
+                || (!self.settings.looping
+                    && self
+                        .settings
+                        .duration
+                        .is_some_and(|duration| self.time >= duration)))
+    }
+
+    /// Emits particles right away, also when not [`ParticleEmitter::emitting`].
+    pub fn burst(&mut self, count: u32) {
+        for _ in 0..count {
+            self.spawn();
+        }
+    }
+
+    /// Advances the simulation by `delta_seconds`. Use a fixed step for repeatable runs.
+    pub fn update(&mut self, delta_seconds: f32) {
+        let previous_time = self.time;
+        self.time += delta_seconds;
+
+        if self.emitting {
+            let duration = self.settings.duration;
+            let loop_duration =
+                duration.filter(|&duration| self.settings.looping && duration > 0.0);
+            let active =
+                loop_duration.is_some() || duration.is_none_or(|duration| previous_time < duration);
+            if active {
+                match loop_duration {
+                    Some(duration) if self.time >= duration => {
+                        // A step can be longer than a loop, e.g. after a hitch, every loop it
+                        // crosses fires its bursts
+                        let loops = (self.time / duration) as u32;
+                        self.emit_bursts(previous_time, duration);
+                        for _ in 1..loops {
+                            self.emit_bursts(0.0, duration);
+                        }
+                        self.time = self.time.rem_euclid(duration);
+                        self.emit_bursts(0.0, self.time);
+                    }
+                    _ => self.emit_bursts(previous_time, self.time),
+                }
+
+                self.emit_accumulator += self.settings.rate * delta_seconds;
+                while self.emit_accumulator >= 1.0 {
+                    self.emit_accumulator -= 1.0;
+                    self.spawn();
+                }
+            }
+        }
+
+        let gravity = self.settings.gravity;
+        for particle in &mut self.particles {
+            particle.velocity[0] += gravity[0] * delta_seconds;
+            particle.velocity[1] += gravity[1] * delta_seconds;
+            particle.position[0] += particle.velocity[0] * delta_seconds;
+            particle.position[1] += particle.velocity[1] * delta_seconds;
+            particle.age += delta_seconds;
+        }
+        self.particles
+            .retain(|particle| particle.age < particle.lifetime);
+    }
+
+    /// Bursts with a time from `start` up to, but not including, `end`.
+    fn emit_bursts(&mut self, start: f32, end: f32) {
+        for index in 0..self.settings.bursts.len() {
+            let burst = self.settings.bursts[index];
+            if burst.time >= start && burst.time < end {
+                self.burst(burst.count);
+            }
+        }
+    }
+
+    fn spawn(&mut self) {
+        if self.particles.len() >= self.settings.max_particles {
+            return;
+        }
+
+        let rng = &mut self.rng;
+        let settings = &self.settings;
+        let spawn_angle = rng.next_f32() * std::f32::consts::TAU;
+        let spawn_distance = settings.spawn_radius * rng.next_f32().sqrt();
+        let angle = settings.direction + settings.spread * (rng.next_f32() * 2.0 - 1.0);
+        let speed = settings.speed.sample(rng);
+        let lifetime = settings.lifetime.sample(rng).max(f32::EPSILON);
+        let frame = (rng.next_f32() * settings.frames.len() as f32) as u16;
+
+        self.particles.push(Particle {
+            position: [
+                self.position[0] + spawn_angle.cos() * spawn_distance,
+                self.position[1] + spawn_angle.sin() * spawn_distance,
+            ],
+            velocity: [angle.cos() * speed, angle.sin() * speed],
+            age: 0.0,
+            lifetime,
+            frame,
+        });
+    }
+
+    /// The atlas frame a particle shows right now.
+    pub fn frame(&self, particle: &Particle) -> Option<URect> {
+        let frames = &self.settings.frames;
+        if frames.is_empty() {
+            return None;
+        }
+        let index = match self.settings.frame_animation {
+            FrameAnimation::OverLife => {
+                ((particle.life() * frames.len() as f32) as usize).min(frames.len() - 1)
+            }
+            FrameAnimation::PerSecond(frames_per_second) => {
+                (particle.frame as usize + (particle.age * frames_per_second) as usize)
+                    % frames.len()
+            }
+            FrameAnimation::Random => particle.frame as usize % frames.len(),
+        };
+
+        Some(frames[index])
+    }
+
+    /// One centered sprite instance per particle. Without frames, or when `use_atlas` is
+    /// false because the texture is still a placeholder, the whole texture is shown.
+    pub(crate) fn push_instances(
+        &self,
+        texture_size: UVec2,
+        use_atlas: bool,
+        instances: &mut Vec<SpriteInstanceUniform>,
+    ) {
+        let texture_width = f32::from(texture_size.x.max(1));
+        let texture_height = f32::from(texture_size.y.max(1));
+
+        for particle in &self.particles {
+            let frame =
+                self.frame(particle)
+                    .unwrap_or(URect::new(0, 0, texture_size.x, texture_size.y));
+            let life = particle.life();
+            let scale = self.settings.size_over_life.sample(life);
+            let width = f32::from(frame.size.x) * scale;
+            let height = f32::from(frame.size.y) * scale;
+
+            let model = Mx4::from_translation(
+                particle.position[0] - width / 2.0,
+                particle.position[1] - height / 2.0,
+                0.0,
+            ) * Mx4::from_scale(width, height, 1.0);
+            let tex_coords = if use_atlas {
+                FVec4([
+                    f32::from(frame.position.x) / texture_width,
+                    f32::from(frame.position.y) / texture_height,
+                    f32::from(frame.size.x) / texture_width,
+                    f32::from(frame.size.y) / texture_height,
+                ])
+            } else {
+                FVec4([0.0, 0.0, 1.0, 1.0])
+            };
+
+            instances.push(
+                SpriteInstanceUniform::new(model, tex_coords, SpriteInstanceUniform::NO_TILING, 0)
+                    .with_tint(self.settings.color_over_life.sample(life)),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn looping_settings() -> EmitterSettings {
+        EmitterSettings {
+            rate: 7.0,
+            bursts: vec![
+                Burst {
+                    time: 0.0,
+                    count: 3,
+                },
+                Burst {
+                    time: 0.4,
+                    count: 2,
+                },
+            ],
+            duration: Some(1.0),
+            looping: true,
+            lifetime: Range::new(0.5, 1.5),
+            spawn_radius: 4.0,
+            gravity: [0.0, -10.0],
+            ..EmitterSettings::default()
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_particles() {
+        let mut first = ParticleEmitter::new(looping_settings(), Vec2::new(10, 20), 42);
+        let mut second = ParticleEmitter::new(looping_settings(), Vec2::new(10, 20), 42);
+        let mut other_seed = ParticleEmitter::new(looping_settings(), Vec2::new(10, 20), 43);
+
+        let mut wrapped = false;
+        for _ in 0..150 {
+            let time = first.time();
+            first.update(1.0 / 60.0);
+            second.update(1.0 / 60.0);
+            other_seed.update(1.0 / 60.0);
+            wrapped |= first.time() < time;
+
+            assert_eq!(first.particles(), second.particles());
+            assert_eq!(first.time(), second.time());
+        }
+
+        assert!(wrapped);
+        assert!(!first.particles().is_empty());
+        assert_ne!(first.particles(), other_seed.particles());
+    }
+
+    #[test]
+    fn bursts_restart_when_looping() {
+        let settings = EmitterSettings {
+            rate: 0.0,
+            lifetime: Range::constant(10.0),
+            ..looping_settings()
+        };
+        let mut emitter = ParticleEmitter::new(settings, Vec2::new(0, 0), 1);
+
+        // Steps that do not land on the loop end, so bursts at the start of the
+        // next loop happen in the same step as the wrap
+        for _ in 0..7 {
+            emitter.update(0.3);
+        }
+
+        assert_eq!(emitter.particles().len(), 3 + 2 + 3 + 2 + 3);
+        assert!(emitter.time() < 1.0);
+    }
+
+    #[test]
+    fn step_longer_than_a_loop_fires_every_loop() {
+        let settings = EmitterSettings {
+            rate: 0.0,
+            lifetime: Range::constant(10.0),
+            ..looping_settings()
+        };
+        let mut emitter = ParticleEmitter::new(settings, Vec2::new(0, 0), 1);
+
+        // Two whole loops and the start of a third
+        emitter.update(2.5);
+        assert_eq!(emitter.particles().len(), 3 * 5);
+        assert!((emitter.time() - 0.5).abs() < 1e-5);
+
+        // Keeps emitting after the long step
+        emitter.update(0.5);
+        emitter.update(0.1);
+        assert_eq!(emitter.particles().len(), 3 * 5 + 3);
+        assert!(emitter.time() < 1.0);
+    }
+
+    #[test]
+    fn rate_emission_continues_after_a_long_step() {
+        let settings = EmitterSettings {
+            rate: 10.0,
+            bursts: Vec::new(),
+            lifetime: Range::constant(10.0),
+            ..looping_settings()
+        };
+        let mut emitter = ParticleEmitter::new(settings, Vec2::new(0, 0), 1);
+
+        emitter.update(2.5);
+        let after_long_step = emitter.particles().len();
+        emitter.update(0.5);
+        assert_eq!(emitter.particles().len(), after_long_step + 5);
+    }
+
+    #[test]
+    fn bursts_stop_without_looping() {
+        let settings = EmitterSettings {
+            rate: 0.0,
+            looping: false,
+            lifetime: Range::constant(10.0),
+            ..looping_settings()
+        };
+        let mut emitter = ParticleEmitter::new(settings, Vec2::new(0, 0), 1);
+        for _ in 0..7 {
+            emitter.update(0.3);
+        }
+
+        assert_eq!(emitter.particles().len(), 3 + 2);
+    }
+
+    #[test]
+    fn curve_needs_a_key() {
+        assert_eq!(Curve::<f32>::new(Vec::new()), Err(EmptyCurveError));
+    }
+
+    #[test]
+    fn curve_keys_are_sorted() {
+        let curve = Curve::new(vec![(1.0, 10.0), (0.0, 0.0), (0.5, 2.0)]).unwrap();
+        assert_eq!(curve.sample(-1.0), 0.0);
+        assert_eq!(curve.sample(0.25), 1.0);
+        assert_eq!(curve.sample(0.75), 6.0);
+        assert_eq!(curve.sample(2.0), 10.0);
+    }
+}
@@ -0,0 +1,771 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+use bytemuck::{Pod, Zeroable};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+use swamp_wgpu::render_graph::{
+    ColorTarget, RenderGraph, RenderPassDesc, TextureId, TextureSize, TransientTextureDesc,
+    TransientViewId,
+};
+use swamp_wgpu::shader_validation::{
+    BindingKind, ExpectedBinding, ShaderError, ShaderInterface, ShaderStage,
+};
+use wgpu::{
+    BindGroupLayout, Buffer, PipelineLayout, RenderPipeline, Sampler, TextureFormat, TextureView,
+};
+
+const PRELUDE: &str = include_str!("shaders/post/prelude.wgsl");
+const COPY: &str = include_str!("shaders/post/copy.wgsl");
+const CRT: &str = include_str!("shaders/post/crt.wgsl");
+const PALETTE_QUANTIZE: &str = include_str!("shaders/post/palette_quantize.wgsl");
+const BLOOM_EXTRACT: &str = include_str!("shaders/post/bloom_extract.wgsl");
+const BLOOM_BLUR: &str = include_str!("shaders/post/bloom_blur.wgsl");
+const BLOOM_COMBINE: &str = include_str!("shaders/post/bloom_combine.wgsl");
+const VIGNETTE: &str = include_str!("shaders/post/vignette.wgsl");
+const COLOR_GRADING: &str = include_str!("shaders/post/color_grading.wgsl");
+
+const POST_BINDINGS: &[ExpectedBinding] = &[
+    ExpectedBinding::new(0, 0, BindingKind::Texture2d),
+    ExpectedBinding::new(0, 1, BindingKind::Texture2d),
+    ExpectedBinding::new(0, 2, BindingKind::Sampler),
+    ExpectedBinding::new(0, 3, BindingKind::UniformBuffer),
+    ExpectedBinding::new(0, 4, BindingKind::UniformBuffer),
+    ExpectedBinding::new(0, 5, BindingKind::Texture2d),
+];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct CrtParams {
+    pub curvature: f32,
+    pub scanline_intensity: f32,
+    /// Zero uses one scanline per source pixel row
+    pub scanline_count: f32,
+    pub brightness: f32,
+}
+
+unsafe impl Pod for CrtParams {}
+unsafe impl Zeroable for CrtParams {}
+
+impl Default for CrtParams {
+    fn default() -> Self {
+        Self {
+            curvature: 0.04,
+            scanline_intensity: 0.35,
+            scanline_count: 0.0,
+            brightness: 1.2,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct PaletteQuantizeParams {
+    /// Number of levels per color channel
+    pub levels: f32,
+    /// Ordered dithering strength, 0.0 disables dithering
+    pub dither: f32,
+    _pad0: f32,
+    _pad1: f32,
+}
+
+unsafe impl Pod for PaletteQuantizeParams {}
+unsafe impl Zeroable for PaletteQuantizeParams {}
+
+impl PaletteQuantizeParams {
+    pub fn new(levels: f32, dither: f32) -> Self {
+        Self {
+            levels,
+            dither,
+            _pad0: 0.0,
+            _pad1: 0.0,
+        }
+    }
+}
+
+impl Default for PaletteQuantizeParams {
+    fn default() -> Self {
+        Self::new(8.0, 1.0)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct BloomParams {
+    pub threshold: f32,
+    pub intensity: f32,
+    /// Blur sample distance in (half resolution) texels
+    pub radius: f32,
+    _pad0: f32,
+}
+
+unsafe impl Pod for BloomParams {}
+unsafe impl Zeroable for BloomParams {}
+
+impl BloomParams {
+    pub fn new(threshold: f32, intensity: f32, radius: f32) -> Self {
+        Self {
+            threshold,
+            intensity,
+            radius,
+            _pad0: 0.0,
+        }
+    }
+}
+
+impl Default for BloomParams {
+    fn default() -> Self {
+        Self::new(0.7, 0.8, 1.5)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct VignetteParams {
+    pub intensity: f32,
+    pub radius: f32,
+    pub softness: f32,
+    _pad0: f32,
+}
+
+unsafe impl Pod for VignetteParams {}
+unsafe impl Zeroable for VignetteParams {}
+
+impl VignetteParams {
+    pub fn new(intensity: f32, radius: f32, softness: f32) -> Self {
+        Self {
+            intensity,
+            radius,
+            softness,
+            _pad0: 0.0,
+        }
+    }
+}
+
+impl Default for VignetteParams {
+    fn default() -> Self {
+        Self::new(0.6, 0.75, 0.45)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ColorGradingParams {
+    /// How much of the graded color is mixed in, 1.0 is only the graded color
+    pub intensity: f32,
+    _pad0: f32,
+    _pad1: f32,
+    _pad2: f32,
+}
+
+unsafe impl Pod for ColorGradingParams {}
+unsafe impl Zeroable for ColorGradingParams {}
+
+impl ColorGradingParams {
+    pub fn new(intensity: f32) -> Self {
+        Self {
+            intensity,
+            _pad0: 0.0,
+            _pad1: 0.0,
+            _pad2: 0.0,
+        }
+    }
+}
+
+impl Default for ColorGradingParams {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct PostGlobals {
+    time: f32,
+    _pad: [f32; 3],
+}
+
+unsafe impl Pod for PostGlobals {}
+unsafe impl Zeroable for PostGlobals {}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PostEffectId(usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum StageScale {
+    Full,
+    Half,
+}
+
+#[derive(Debug)]
+struct PostEffectStage {
+    pipeline: RenderPipeline,
+    scale: StageScale,
+}
+
+#[derive(Debug)]
+struct PostEffect {
+    label: String,
+    stages: Vec<PostEffectStage>,
+    params_buffer: Buffer,
+    aux_view: Option<TextureView>,
+    enabled: bool,
+}
+
+/// The pass that a cached bind group belongs to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum PassSlot {
+    Copy,
+    Stage { effect: usize, stage: usize },
+}
+
+#[derive(Debug)]
+struct CachedBindGroup {
+    /// The source and the effect input
+    views: [TransientViewId; 2],
+    bind_group: wgpu::BindGroup,
+}
+
+/// A stack of fullscreen effects that are applied in the order they were added.
+///
+/// Every effect is WGSL with a `fs_main` entry point, appended to a shared prelude
+/// (see `shaders/post/prelude.wgsl`) that declares the input textures, sampler and
+/// the fullscreen vertex shader. Effect parameters are bound as a uniform at
+/// `@group(0) @binding(4)`.
+#[derive(Debug)]
+pub struct PostProcess {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    format: TextureFormat,
+    bind_group_layout: BindGroupLayout,
+    pipeline_layout: PipelineLayout,
+    sampler: Sampler,
+    globals_buffer: Buffer,
+    empty_params_buffer: Buffer,
+    empty_aux_view: TextureView,
+    copy_pipeline: RenderPipeline,
+    effects: Vec<PostEffect>,
+    /// Kept between frames, rebuilt when the pool hands out other transient textures
+    bind_groups: RefCell<HashMap<PassSlot, CachedBindGroup>>,
+}
+
+impl PostProcess {
+    pub fn new(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        target_format: TextureFormat,
+    ) -> Self {
+        let bind_group_layout =
+            create_post_bind_group_layout(&device, "post process bind group layout");
+        let pipeline_layout = swamp_wgpu::create_pipeline_layout(
+            &device,
+            "post process pipeline layout",
+            &bind_group_layout,
+        );
+        let sampler = swamp_wgpu::create_linear_sampler(&device, "post process linear sampler");
+
+        let globals_buffer = swamp_wgpu::create_uniform_buffer_with_octets(
+            &device,
+            "post process globals",
+            bytemuck::bytes_of(&PostGlobals {
+                time: 0.0,
+                _pad: [0.0; 3],
+            }),
+        );
+        let empty_params_buffer = swamp_wgpu::create_uniform_buffer_with_octets(
+            &device,
+            "post process empty params",
+            &[],
+        );
+
+        let empty_aux_view = swamp_wgpu::create_texture(&device, 1, 1)
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let copy_pipeline = create_post_pipeline(
+            &device,
+            &pipeline_layout,
+            target_format,
+            "post process copy",
+            &compose_source(COPY),
+        );
+
+        Self {
+            device,
+            queue,
+            format: target_format,
+            bind_group_layout,
+            pipeline_layout,
+            sampler,
+            globals_buffer,
+            empty_params_buffer,
+            empty_aux_view,
+            copy_pipeline,
+            effects: Vec::new(),
+            bind_groups: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn add_crt(&mut self, params: CrtParams) -> PostEffectId {
+        let stages = vec![self.create_stage("crt", CRT, StageScale::Full)];
+        self.push_effect("crt", stages, bytemuck::bytes_of(&params), None)
+    }
+
+    pub fn add_palette_quantize(&mut self, params: PaletteQuantizeParams) -> PostEffectId {
+        let stages =
+            vec![self.create_stage("palette quantize", PALETTE_QUANTIZE, StageScale::Full)];
+        self.push_effect(
+            "palette quantize",
+            stages,
+            bytemuck::bytes_of(&params),
+            None,
+        )
+    }
+
+    pub fn add_bloom(&mut self, params: BloomParams) -> PostEffectId {
+        let blur_horizontal = format!("const BLUR_DIRECTION = vec2<f32>(1.0, 0.0);\n{BLOOM_BLUR}");
+        let blur_vertical = format!("const BLUR_DIRECTION = vec2<f32>(0.0, 1.0);\n{BLOOM_BLUR}");
+        let stages = vec![
+            self.create_stage("bloom extract", BLOOM_EXTRACT, StageScale::Half),
+            self.create_stage("bloom blur horizontal", &blur_horizontal, StageScale::Half),
+            self.create_stage("bloom blur vertical", &blur_vertical, StageScale::Half),
+            self.create_stage("bloom combine", BLOOM_COMBINE, StageScale::Full),
+        ];
+        self.push_effect("bloom", stages, bytemuck::bytes_of(&params), None)
+    }
+
+    pub fn add_vignette(&mut self, params: VignetteParams) -> PostEffectId {
+        let stages = vec![self.create_stage("vignette", VIGNETTE, StageScale::Full)];
+        self.push_effect("vignette", stages, bytemuck::bytes_of(&params), None)
+    }
+
+    /// `lut_png` is a lookup table strip of N slices of N x N texels, e.g. 256 x 16.
+    /// Nothing is added when the PNG can not be decoded.
+    pub fn add_color_grading(
+        &mut self,
+        lut_png: &[u8],
+        params: ColorGradingParams,
+    ) -> Result<PostEffectId, image::ImageError> {
+        let lut = swamp_wgpu_sprites::try_load_data_texture_from_memory(
+            &self.device,
+            &self.queue,
+            lut_png,
+            "color grading lut",
+        )?;
+        let lut_view = lut.create_view(&wgpu::TextureViewDescriptor::default());
+        let stages = vec![self.create_stage("color grading", COLOR_GRADING, StageScale::Full)];
+        Ok(self.push_effect(
+            "color grading",
+            stages,
+            bytemuck::bytes_of(&params),
+            Some(lut_view),
+        ))
+    }
+
+    /// Adds an effect from WGSL source with a `fs_main` fragment entry point.
+    /// `params` is bound as the uniform at `@group(0) @binding(4)`.
+    ///
+    /// The source is validated first, line numbers in the error refer to `fragment_source`.
+    pub fn add_custom_effect(
+        &mut self,
+        label: &str,
+        fragment_source: &str,
+        params: &[u8],
+    ) -> Result<PostEffectId, ShaderError> {
+        let module = validate_effect_source(label, fragment_source)?;
+        swamp_wgpu::shader_validation::check_interface(
+            label,
+            &module,
+            &ShaderInterface {
+                entry_point: "fs_main",
+                stage: ShaderStage::Fragment,
+                bindings: POST_BINDINGS,
+                vertex_locations: None,
+            },
+        )?;
+
+        let stages = vec![self.create_stage(label, fragment_source, StageScale::Full)];
+        Ok(self.push_effect(label, stages, params, None))
+    }
+
+    /// Replaces the uniform parameters of an effect, e.g. `bytemuck::bytes_of(&CrtParams { .. })`.
+    /// Returns `false` for an unknown effect.
+    pub fn set_params(&mut self, id: PostEffectId, params: &[u8]) -> bool {
+        let padded = swamp_wgpu::pad_uniform_octets(params);
+        let Some(effect) = self.effects.get_mut(id.0) else {
+            return false;
+        };
+        if effect.params_buffer.size() == padded.len() as u64 {
+            self.queue.write_buffer(&effect.params_buffer, 0, &padded);
+        } else {
+            effect.params_buffer =
+                swamp_wgpu::create_uniform_buffer_with_octets(&self.device, &effect.label, params);
+            self.bind_groups.get_mut().clear();
+        }
+
+        true
+    }
+
+    /// Returns `false` for an unknown effect.
+    pub fn set_enabled(&mut self, id: PostEffectId, enabled: bool) -> bool {
+        let Some(effect) = self.effects.get_mut(id.0) else {
+            return false;
+        };
+        effect.enabled = enabled;
+
+        true
+    }
+
+    /// `false` for an unknown effect.
+    pub fn is_enabled(&self, id: PostEffectId) -> bool {
+        self.effects.get(id.0).is_some_and(|effect| effect.enabled)
+    }
+
+    /// Time in seconds, available to the effects as `globals.time`.
+    pub fn set_time(&mut self, time: f32) {
+        self.queue.write_buffer(
+            &self.globals_buffer,
+            0,
+            bytemuck::bytes_of(&PostGlobals {
+                time,
+                _pad: [0.0; 3],
+            }),
+        );
+    }
+
+    pub fn has_enabled_effects(&self) -> bool {
+        self.effects.iter().any(|effect| effect.enabled)
+    }
+
+    /// Adds one render pass per effect stage, reading from `input` and writing the
+    /// last stage to `output`. Intermediate targets are transient textures in the graph.
+    pub fn add_to_graph<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        input: TextureId,
+        output: ColorTarget,
+    ) {
+        let mut stages: Vec<(PassSlot, &PostEffect, &PostEffectStage)> = Vec::new();
+        for (effect_index, effect) in self.effects.iter().enumerate() {
+            if !effect.enabled {
+                continue;
+            }
+            for (stage_index, stage) in effect.stages.iter().enumerate() {
+                let slot = PassSlot::Stage {
+                    effect: effect_index,
+                    stage: stage_index,
+                };
+                stages.push((slot, effect, stage));
+            }
+        }
+
+        if stages.is_empty() {
+            let mut desc = RenderPassDesc::new("post process copy", output);
+            desc.reads = vec![input];
+            graph.add_render_pass(desc, move |render_pass, context| {
+                let mut bind_groups = self.bind_groups.borrow_mut();
+                let view_id = context.texture_view_id(input);
+                let bind_group =
+                    cached_bind_group(&mut bind_groups, PassSlot::Copy, [view_id; 2], || {
+                        let view = context.texture_view(input);
+                        self.create_bind_group(
+                            "post process copy",
+                            view,
+                            view,
+                            &self.empty_params_buffer,
+                            &self.empty_aux_view,
+                        )
+                    });
+                render_pass.set_pipeline(&self.copy_pipeline);
+                render_pass.set_bind_group(0, bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            });
+            return;
+        }
+
+        let last_index = stages.len() - 1;
+        let mut previous = input;
+        let mut effect_input = input;
+
+        for (index, (slot, effect, stage)) in stages.into_iter().enumerate() {
+            if matches!(slot, PassSlot::Stage { stage: 0, .. }) {
+                effect_input = previous;
+            }
+
+            let (target, target_texture) = if index == last_index {
+                (output, None)
+            } else {
+                let size = match stage.scale {
+                    StageScale::Full => TextureSize::Surface,
+                    StageScale::Half => TextureSize::SurfaceDivided(2),
+                };
+                let texture = graph.create_texture(TransientTextureDesc {
+                    size,
+                    format: Some(self.format),
+                });
+                (ColorTarget::Texture(texture), Some(texture))
+            };
+
+            let mut desc = RenderPassDesc::new(&effect.label, target);
+            desc.clear = Some(wgpu::Color::BLACK);
+            desc.reads = vec![previous];
+            if effect_input != previous {
+                desc.reads.push(effect_input);
+            }
+
+            let source = previous;
+            let first = effect_input;
+            graph.add_render_pass(desc, move |render_pass, context| {
+                let mut bind_groups = self.bind_groups.borrow_mut();
+                let views = [
+                    context.texture_view_id(source),
+                    context.texture_view_id(first),
+                ];
+                let bind_group = cached_bind_group(&mut bind_groups, slot, views, || {
+                    self.create_bind_group(
+                        &effect.label,
+                        context.texture_view(source),
+                        context.texture_view(first),
+                        &effect.params_buffer,
+                        effect.aux_view.as_ref().unwrap_or(&self.empty_aux_view),
+                    )
+                });
+                render_pass.set_pipeline(&stage.pipeline);
+                render_pass.set_bind_group(0, bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            });
+
+            if let Some(texture) = target_texture {
+                previous = texture;
+            }
+        }
+    }
+
+    fn create_stage(
+        &self,
+        label: &str,
+        fragment_source: &str,
+        scale: StageScale,
+    ) -> PostEffectStage {
+        PostEffectStage {
+            pipeline: create_post_pipeline(
+                &self.device,
+                &self.pipeline_layout,
+                self.format,
+                label,
+                &compose_source(fragment_source),
+            ),
+            scale,
+        }
+    }
+
+    fn push_effect(
+        &mut self,
+        label: &str,
+        stages: Vec<PostEffectStage>,
+        params: &[u8],
+        aux_view: Option<TextureView>,
+    ) -> PostEffectId {
+        self.effects.push(PostEffect {
+            label: label.to_string(),
+            stages,
+            params_buffer: swamp_wgpu::create_uniform_buffer_with_octets(
+                &self.device,
+                label,
+                params,
+            ),
+            aux_view,
+            enabled: true,
+        });
+        PostEffectId(self.effects.len() - 1)
+    }
+
+    fn create_bind_group(
+        &self,
+        label: &str,
+        source: &TextureView,
+        effect_input: &TextureView,
+        params: &Buffer,
+        aux: &TextureView,
+    ) -> wgpu::BindGroup {
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(effect_input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.globals_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(aux),
+                },
+            ],
+        })
+    }
+}
+
+/// The bind group of the pass from an earlier frame, or a new one if the pass reads
+/// other transient textures now.
+fn cached_bind_group(
+    bind_groups: &mut HashMap<PassSlot, CachedBindGroup>,
+    slot: PassSlot,
+    views: [TransientViewId; 2],
+    create: impl FnOnce() -> wgpu::BindGroup,
+) -> &wgpu::BindGroup {
+    if bind_groups
+        .get(&slot)
+        .is_none_or(|cached| cached.views != views)
+    {
+        let bind_group = create();
+        bind_groups.insert(slot, CachedBindGroup { views, bind_group });
+    }
+
+    &bind_groups[&slot].bind_group
+}
+
+fn validate_effect_source(
+    label: &str,
+    fragment_source: &str,
+) -> Result<wgpu::naga::Module, ShaderError> {
+    let prelude_lines = PRELUDE.lines().count() as u32 + 1;
+    swamp_wgpu::shader_validation::validate_wgsl(label, &compose_source(fragment_source))
+        .map_err(|err| relative_to_effect_source(err, prelude_lines, fragment_source))
+}
+
+fn relative_to_effect_source(
+    err: ShaderError,
+    prelude_lines: u32,
+    fragment_source: &str,
+) -> ShaderError {
+    match err {
+        ShaderError::Parse(diagnostic) => {
+            ShaderError::Parse(diagnostic.without_prelude(prelude_lines, fragment_source))
+        }
+        ShaderError::Validation(diagnostic) => {
+            ShaderError::Validation(diagnostic.without_prelude(prelude_lines, fragment_source))
+        }
+        other => other,
+    }
+}
+
+fn compose_source(fragment_source: &str) -> String {
+    format!("{PRELUDE}\n{fragment_source}")
+}
+
+fn create_post_bind_group_layout(device: &wgpu::Device, label: &str) -> BindGroupLayout {
+    let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    };
+    let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some(label),
+        entries: &[
+            texture_entry(0),
+            texture_entry(1),
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            uniform_entry(3),
+            uniform_entry(4),
+            texture_entry(5),
+        ],
+    })
+}
+
+fn create_post_pipeline(
+    device: &wgpu::Device,
+    pipeline_layout: &PipelineLayout,
+    format: TextureFormat,
+    label: &str,
+    source: &str,
+) -> RenderPipeline {
+    let shader = swamp_wgpu::create_shader_module(device, label, source);
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            compilation_options: Default::default(),
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            compilation_options: Default::default(),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effect_errors_refer_to_the_effect_source() {
+        let source =
+            "@fragment\nfn fs_main() -> @location(0) vec4<f32> {\n    return vec4<f32>(1.0)\n}\n";
+        let Err(ShaderError::Parse(diagnostic)) = validate_effect_source("effect", source) else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(diagnostic.line, Some(4));
+        assert!(diagnostic.rendered.contains("effect:4:1"));
+        assert!(diagnostic.rendered.contains("4 | }"));
+    }
+
+    #[test]
+    fn valid_effect_source() {
+        let source = "@fragment\nfn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {\n    return textureSample(source_texture, post_sampler, input.uv);\n}\n";
+        validate_effect_source("effect", source).unwrap();
+    }
+}
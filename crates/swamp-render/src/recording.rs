@@ -0,0 +1,762 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+//! The sprites and the viewport of each frame, recorded with
+//! [`crate::Render::start_recording`] so a frame can be replayed without the game.
+//!
+//! The file is a header followed by records. A material record comes before the first
+//! frame that uses the material, and again when its size or image file changes, e.g. when
+//! it has been loaded with [`crate::Render::load_material_async`]. Particles, trails,
+//! meshes and lights are not recorded.
+
+use crate::material_registry::{MaterialHandle, MaterialRegistry};
+use crate::{DropShadow, FillMode, Render, Sprite, SpriteMaterial, SpriteParams};
+use int_math::{URect, UVec2, Vec2, Vec3};
+use log::warn;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: [u8; 4] = *b"SWRC";
+const VERSION: u16 = 1;
+
+const MATERIAL_RECORD: u8 = 1;
+const FRAME_RECORD: u8 = 2;
+
+// Longer paths are a corrupt recording, instead of a huge allocation
+const MAX_PATH_LENGTH: usize = u16::MAX as usize;
+
+// Optional sprite params that are present in a sprite record
+const HAS_DEST_SIZE: u16 = 1 << 0;
+const HAS_SOURCE: u16 = 1 << 1;
+const FLIP_X: u16 = 1 << 2;
+const FLIP_Y: u16 = 1 << 3;
+const HAS_PIVOT: u16 = 1 << 4;
+const FILL_TILE: u16 = 1 << 5;
+const HAS_OUTLINE: u16 = 1 << 6;
+const HAS_DROP_SHADOW: u16 = 1 << 7;
+const HAS_SILHOUETTE: u16 = 1 << 8;
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(std::io::Error),
+    NotARecording,
+    UnsupportedVersion(u16),
+    UnknownRecord(u8),
+    /// A sprite refers to a material without a material record before it
+    UnknownMaterial(u64),
+}
+
+impl Display for RecordingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::NotARecording => write!(f, "not a frame recording"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported recording version {version}")
+            }
+            Self::UnknownRecord(tag) => write!(f, "unknown record type {tag}"),
+            Self::UnknownMaterial(id) => write!(f, "unknown material {id}"),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+impl From<std::io::Error> for RecordingError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Writes a frame record for each [`Render::render`].
+#[derive(Debug)]
+pub(crate) struct Recorder<W: Write = BufWriter<File>> {
+    writer: W,
+    /// The last record written for each material
+    recorded_materials: HashMap<MaterialHandle, RecordedMaterial>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    fn new(mut writer: W) -> std::io::Result<Self> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            recorded_materials: HashMap::new(),
+        })
+    }
+
+    /// Flushed for every frame, so the frames before a crash can be replayed.
+    pub fn write_frame(
+        &mut self,
+        viewport: UVec2,
+        sprites: &[Sprite],
+        materials: &MaterialRegistry<SpriteMaterial>,
+    ) -> std::io::Result<()> {
+        self.write_records(viewport, sprites, |handle| {
+            materials.get(handle).map(|material| RecordedMaterial {
+                id: handle.to_bits(),
+                texture_size: material.texture.size,
+                source_path: material.source_path.clone(),
+            })
+        })
+    }
+
+    /// Sprites with a material that `material` does not know are skipped, so every
+    /// recorded frame can be read back.
+    fn write_records(
+        &mut self,
+        viewport: UVec2,
+        sprites: &[Sprite],
+        material: impl Fn(MaterialHandle) -> Option<RecordedMaterial>,
+    ) -> std::io::Result<()> {
+        let mut checked_materials = HashSet::new();
+        let mut recorded_sprites = Vec::with_capacity(sprites.len());
+        for sprite in sprites {
+            if !checked_materials.contains(&sprite.material) {
+                let Some(material) = material(sprite.material) else {
+                    continue;
+                };
+                if self.recorded_materials.get(&sprite.material) != Some(&material) {
+                    write_material(&mut self.writer, &material)?;
+                    self.recorded_materials.insert(sprite.material, material);
+                }
+                checked_materials.insert(sprite.material);
+            }
+            recorded_sprites.push(sprite);
+        }
+
+        let w = &mut self.writer;
+        w.write_all(&[FRAME_RECORD])?;
+        write_u16(w, viewport.x)?;
+        write_u16(w, viewport.y)?;
+        w.write_all(&(recorded_sprites.len() as u32).to_le_bytes())?;
+        for sprite in recorded_sprites {
+            write_sprite(w, sprite)?;
+        }
+
+        self.writer.flush()
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedMaterial {
+    pub id: u64,
+    pub texture_size: UVec2,
+    /// For materials created from image files
+    pub source_path: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct RecordedSprite {
+    pub position: Vec3,
+    pub atlas_rect: URect,
+    /// [`RecordedMaterial::id`]
+    pub material: u64,
+    pub params: SpriteParams,
+}
+
+#[derive(Debug)]
+pub struct RecordedFrame {
+    pub viewport: UVec2,
+    /// In the order they were submitted, including the ones outside of the viewport
+    pub sprites: Vec<RecordedSprite>,
+}
+
+impl RecordedFrame {
+    /// Sets the viewport and submits the sprites for the next [`Render::render`].
+    /// Sprites with a material that is missing from `materials` are skipped.
+    pub fn submit(&self, render: &mut Render, materials: &HashMap<u64, MaterialHandle>) {
+        render.set_viewport(self.viewport);
+        for sprite in &self.sprites {
+            let Some(&material) = materials.get(&sprite.material) else {
+                continue;
+            };
+            render.render_sprite(
+                sprite.position,
+                sprite.atlas_rect,
+                material,
+                sprite.params.clone(),
+            );
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Recording {
+    /// The last record of each material
+    pub materials: Vec<RecordedMaterial>,
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self, RecordingError> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    /// A record that was cut off, e.g. when the game crashed while writing it, ends the
+    /// recording without an error.
+    pub fn read(mut reader: impl Read) -> Result<Self, RecordingError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(RecordingError::NotARecording);
+        }
+        let version = read_u16(&mut reader)?;
+        if version != VERSION {
+            return Err(RecordingError::UnsupportedVersion(version));
+        }
+
+        let mut recording = Self::default();
+        loop {
+            let mut tag = [0; 1];
+            if reader.read(&mut tag)? == 0 {
+                break;
+            }
+            let result = match tag[0] {
+                MATERIAL_RECORD => {
+                    read_material(&mut reader).map(|material| recording.set_material(material))
+                }
+                FRAME_RECORD => read_frame(&mut reader).map(|frame| recording.frames.push(frame)),
+                other => return Err(RecordingError::UnknownRecord(other)),
+            };
+            match result {
+                Ok(()) => {}
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                    warn!("recording ends in the middle of a record");
+                    break;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        recording.check_materials()?;
+
+        Ok(recording)
+    }
+
+    /// Replaces an earlier record of the same material.
+    fn set_material(&mut self, material: RecordedMaterial) {
+        match self
+            .materials
+            .iter_mut()
+            .find(|recorded| recorded.id == material.id)
+        {
+            Some(recorded) => *recorded = material,
+            None => self.materials.push(material),
+        }
+    }
+
+    fn check_materials(&self) -> Result<(), RecordingError> {
+        let ids: HashSet<u64> = self.materials.iter().map(|material| material.id).collect();
+        for frame in &self.frames {
+            if let Some(sprite) = frame
+                .sprites
+                .iter()
+                .find(|sprite| !ids.contains(&sprite.material))
+            {
+                return Err(RecordingError::UnknownMaterial(sprite.material));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Loads the recorded materials from their image files, looking in `asset_dir` for files
+    /// with the same name when the recorded path does not exist. Materials without a file
+    /// get a checkerboard of the recorded size, so the atlas rects still line up.
+    pub fn create_materials(
+        &self,
+        render: &mut Render,
+        asset_dir: Option<&Path>,
+    ) -> HashMap<u64, MaterialHandle> {
+        self.materials
+            .iter()
+            .map(|recorded| {
+                let candidates = recorded.source_path.iter().flat_map(|path| {
+                    let in_asset_dir = asset_dir
+                        .zip(path.file_name())
+                        .map(|(dir, file_name)| dir.join(file_name));
+                    std::iter::once(path.clone()).chain(in_asset_dir)
+                });
+                let loaded = candidates
+                    .filter(|path| path.exists())
+                    .find_map(|path| match render.create_material_from_path(&path) {
+                        Ok(material) => Some(material),
+                        Err(err) => {
+                            warn!("could not load recorded material {}: {err}", path.display());
+                            None
+                        }
+                    });
+                let material = loaded.unwrap_or_else(|| {
+                    let size = recorded.texture_size;
+                    let (width, height) = (size.x.max(1), size.y.max(1));
+                    render
+                        .create_material_rgba(width, height, &checkerboard(width, height))
+                        .expect("checkerboard fills the texture")
+                });
+
+                (recorded.id, material)
+            })
+            .collect()
+    }
+}
+
+/// Magenta and black squares of eight pixels.
+fn checkerboard(width: u16, height: u16) -> Vec<u8> {
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x / 8 + y / 8) % 2 == 0))
+        .flat_map(|magenta| {
+            if magenta {
+                [255, 0, 255, 255]
+            } else {
+                [0, 0, 0, 255]
+            }
+        })
+        .collect()
+}
+
+fn write_material(w: &mut impl Write, material: &RecordedMaterial) -> std::io::Result<()> {
+    w.write_all(&[MATERIAL_RECORD])?;
+    w.write_all(&material.id.to_le_bytes())?;
+    write_u16(w, material.texture_size.x)?;
+    write_u16(w, material.texture_size.y)?;
+    let path = material
+        .source_path
+        .as_ref()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    w.write_all(&(path.len() as u32).to_le_bytes())?;
+    w.write_all(path.as_bytes())
+}
+
+fn write_sprite(w: &mut impl Write, sprite: &Sprite) -> std::io::Result<()> {
+    let params = &sprite.params;
+    let flags = [
+        (params.dest_size.is_some(), HAS_DEST_SIZE),
+        (params.source.is_some(), HAS_SOURCE),
+        (params.flip_x, FLIP_X),
+        (params.flip_y, FLIP_Y),
+        (params.pivot.is_some(), HAS_PIVOT),
+        (matches!(params.fill, FillMode::Tile { .. }), FILL_TILE),
+        (params.outline.is_some(), HAS_OUTLINE),
+        (params.drop_shadow.is_some(), HAS_DROP_SHADOW),
+        (params.silhouette.is_some(), HAS_SILHOUETTE),
+    ]
+    .iter()
+    .filter(|(is_set, _)| *is_set)
+    .fold(0, |flags, (_, flag)| flags | flag);
+
+    write_i16(w, sprite.position.x)?;
+    write_i16(w, sprite.position.y)?;
+    write_i16(w, sprite.position.z)?;
+    write_rect(w, sprite.atlas_rect)?;
+    w.write_all(&sprite.material.to_bits().to_le_bytes())?;
+    write_u16(w, flags)?;
+    write_u16(w, params.rotation)?;
+    write_u16(w, params.palette_row)?;
+
+    if let Some(dest_size) = params.dest_size {
+        write_u16(w, dest_size.x)?;
+        write_u16(w, dest_size.y)?;
+    }
+    if let Some(source) = params.source {
+        write_rect(w, source)?;
+    }
+    if let Some(pivot) = params.pivot {
+        write_vec2(w, pivot)?;
+    }
+    if let FillMode::Tile { offset } = params.fill {
+        write_vec2(w, offset)?;
+    }
+    if let Some(outline) = params.outline {
+        w.write_all(&outline)?;
+    }
+    if let Some(shadow) = params.drop_shadow {
+        write_vec2(w, shadow.offset)?;
+        w.write_all(&shadow.color)?;
+    }
+    if let Some(silhouette) = params.silhouette {
+        w.write_all(&silhouette)?;
+    }
+
+    Ok(())
+}
+
+fn read_sprite(r: &mut impl Read) -> std::io::Result<RecordedSprite> {
+    let position = Vec3 {
+        x: read_i16(r)?,
+        y: read_i16(r)?,
+        z: read_i16(r)?,
+    };
+    let atlas_rect = read_rect(r)?;
+    let material = read_u64(r)?;
+    let flags = read_u16(r)?;
+    let rotation = read_u16(r)?;
+    let palette_row = read_u16(r)?;
+    let has = |flag: u16| flags & flag != 0;
+
+    let dest_size = if has(HAS_DEST_SIZE) {
+        Some(UVec2::new(read_u16(r)?, read_u16(r)?))
+    } else {
+        None
+    };
+    let source = if has(HAS_SOURCE) {
+        Some(read_rect(r)?)
+    } else {
+        None
+    };
+    let pivot = if has(HAS_PIVOT) {
+        Some(read_vec2(r)?)
+    } else {
+        None
+    };
+    let fill = if has(FILL_TILE) {
+        FillMode::Tile {
+            offset: read_vec2(r)?,
+        }
+    } else {
+        FillMode::Stretch
+    };
+    let outline = if has(HAS_OUTLINE) {
+        Some(read_color(r)?)
+    } else {
+        None
+    };
+    let drop_shadow = if has(HAS_DROP_SHADOW) {
+        Some(DropShadow {
+            offset: read_vec2(r)?,
+            color: read_color(r)?,
+        })
+    } else {
+        None
+    };
+    let silhouette = if has(HAS_SILHOUETTE) {
+        Some(read_color(r)?)
+    } else {
+        None
+    };
+
+    Ok(RecordedSprite {
+        position,
+        atlas_rect,
+        material,
+        params: SpriteParams {
+            dest_size,
+            source,
+            rotation,
+            flip_x: has(FLIP_X),
+            flip_y: has(FLIP_Y),
+            pivot,
+            fill,
+            palette_row,
+            outline,
+            drop_shadow,
+            silhouette,
+        },
+    })
+}
+
+fn read_frame(r: &mut impl Read) -> std::io::Result<RecordedFrame> {
+    let viewport = UVec2::new(read_u16(r)?, read_u16(r)?);
+    let count = read_u32(r)?;
+    let sprites = (0..count)
+        .map(|_| read_sprite(r))
+        .collect::<std::io::Result<_>>()?;
+
+    Ok(RecordedFrame { viewport, sprites })
+}
+
+fn read_material(r: &mut impl Read) -> std::io::Result<RecordedMaterial> {
+    let id = read_u64(r)?;
+    let texture_size = UVec2::new(read_u16(r)?, read_u16(r)?);
+    let path_length = read_u32(r)? as usize;
+    if path_length > MAX_PATH_LENGTH {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("material path of {path_length} octets"),
+        ));
+    }
+    let mut path = vec![0; path_length];
+    r.read_exact(&mut path)?;
+    let path =
+        String::from_utf8(path).map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))?;
+
+    Ok(RecordedMaterial {
+        id,
+        texture_size,
+        source_path: (!path.is_empty()).then(|| PathBuf::from(path)),
+    })
+}
+
+fn write_u16(w: &mut impl Write, value: u16) -> std::io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_i16(w: &mut impl Write, value: i16) -> std::io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_vec2(w: &mut impl Write, value: Vec2) -> std::io::Result<()> {
+    write_i16(w, value.x)?;
+    write_i16(w, value.y)
+}
+
+fn write_rect(w: &mut impl Write, rect: URect) -> std::io::Result<()> {
+    write_u16(w, rect.position.x)?;
+    write_u16(w, rect.position.y)?;
+    write_u16(w, rect.size.x)?;
+    write_u16(w, rect.size.y)
+}
+
+fn read_octets<const N: usize>(r: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut octets = [0; N];
+    r.read_exact(&mut octets)?;
+    Ok(octets)
+}
+
+fn read_u16(r: &mut impl Read) -> std::io::Result<u16> {
+    read_octets(r).map(u16::from_le_bytes)
+}
+
+fn read_i16(r: &mut impl Read) -> std::io::Result<i16> {
+    read_octets(r).map(i16::from_le_bytes)
+}
+
+fn read_u32(r: &mut impl Read) -> std::io::Result<u32> {
+    read_octets(r).map(u32::from_le_bytes)
+}
+
+fn read_u64(r: &mut impl Read) -> std::io::Result<u64> {
+    read_octets(r).map(u64::from_le_bytes)
+}
+
+fn read_color(r: &mut impl Read) -> std::io::Result<[u8; 4]> {
+    read_octets(r)
+}
+
+fn read_vec2(r: &mut impl Read) -> std::io::Result<Vec2> {
+    Ok(Vec2::new(read_i16(r)?, read_i16(r)?))
+}
+
+fn read_rect(r: &mut impl Read) -> std::io::Result<URect> {
+    Ok(URect::new(
+        read_u16(r)?,
+        read_u16(r)?,
+        read_u16(r)?,
+        read_u16(r)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprite(material: MaterialHandle, params: SpriteParams) -> Sprite {
+        Sprite {
+            position: Vec3::new(-12, 34, 5),
+            atlas_rect: URect::new(16, 32, 8, 24),
+            material,
+            params,
+        }
+    }
+
+    fn all_params() -> SpriteParams {
+        SpriteParams {
+            dest_size: Some(UVec2::new(40, 20)),
+            source: Some(URect::new(1, 2, 3, 4)),
+            rotation: 270,
+            flip_x: true,
+            flip_y: false,
+            pivot: Some(Vec2::new(-3, 7)),
+            fill: FillMode::Tile {
+                offset: Vec2::new(5, -6),
+            },
+            palette_row: 9,
+            outline: Some([1, 2, 3, 4]),
+            drop_shadow: Some(DropShadow {
+                offset: Vec2::new(2, -2),
+                color: [0, 0, 0, 128],
+            }),
+            silhouette: Some([255, 0, 255, 200]),
+        }
+    }
+
+    fn assert_recorded(recorded: &RecordedSprite, sprite: &Sprite) {
+        assert_eq!(recorded.position, sprite.position);
+        assert_eq!(recorded.atlas_rect, sprite.atlas_rect);
+        assert_eq!(recorded.material, sprite.material.to_bits());
+        let (read, written) = (&recorded.params, &sprite.params);
+        assert_eq!(read.dest_size, written.dest_size);
+        assert_eq!(read.source, written.source);
+        assert_eq!(read.rotation, written.rotation);
+        assert_eq!(read.flip_x, written.flip_x);
+        assert_eq!(read.flip_y, written.flip_y);
+        assert_eq!(read.pivot, written.pivot);
+        assert_eq!(read.fill, written.fill);
+        assert_eq!(read.palette_row, written.palette_row);
+        assert_eq!(read.outline, written.outline);
+        assert_eq!(read.drop_shadow, written.drop_shadow);
+        assert_eq!(read.silhouette, written.silhouette);
+    }
+
+    fn recorded_material(handle: MaterialHandle) -> RecordedMaterial {
+        RecordedMaterial {
+            id: handle.to_bits(),
+            texture_size: UVec2::new(64, 32),
+            source_path: (handle.to_bits() == 0).then(|| PathBuf::from("assets/hero.png")),
+        }
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let mut registry = MaterialRegistry::new();
+        let hero = registry.insert(());
+        let plain = registry.insert(());
+        let sprites = [
+            sprite(hero, all_params()),
+            sprite(plain, SpriteParams::default()),
+            sprite(hero, SpriteParams::default()),
+        ];
+
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+        recorder
+            .write_records(UVec2::new(320, 240), &sprites, |handle| {
+                Some(recorded_material(handle))
+            })
+            .unwrap();
+        recorder
+            .write_records(UVec2::new(640, 480), &sprites[1..], |handle| {
+                Some(recorded_material(handle))
+            })
+            .unwrap();
+
+        let recording = Recording::read(recorder.writer.as_slice()).unwrap();
+        assert_eq!(
+            recording.materials,
+            [recorded_material(hero), recorded_material(plain)]
+        );
+        assert_eq!(recording.frames.len(), 2);
+        assert_eq!(recording.frames[0].viewport, UVec2::new(320, 240));
+        assert_eq!(recording.frames[1].viewport, UVec2::new(640, 480));
+        for (frame, written) in recording.frames.iter().zip([&sprites[..], &sprites[1..]]) {
+            assert_eq!(frame.sprites.len(), written.len());
+            for (recorded, sprite) in frame.sprites.iter().zip(written) {
+                assert_recorded(recorded, sprite);
+            }
+        }
+    }
+
+    #[test]
+    fn sprites_without_material_are_skipped() {
+        let mut registry = MaterialRegistry::new();
+        let alive = registry.insert(());
+        let destroyed = registry.insert(());
+        registry.remove(destroyed);
+        let sprites = [
+            sprite(destroyed, SpriteParams::default()),
+            sprite(alive, SpriteParams::default()),
+        ];
+
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+        recorder
+            .write_records(UVec2::new(320, 240), &sprites, |handle| {
+                registry.contains(handle).then(|| recorded_material(handle))
+            })
+            .unwrap();
+
+        let recording = Recording::read(recorder.writer.as_slice()).unwrap();
+        assert_eq!(recording.materials, [recorded_material(alive)]);
+        assert_eq!(recording.frames[0].sprites.len(), 1);
+        assert_recorded(&recording.frames[0].sprites[0], &sprites[1]);
+    }
+
+    #[test]
+    fn cut_off_frame_ends_the_recording() {
+        let mut registry = MaterialRegistry::new();
+        let handle = registry.insert(());
+        let sprites = [sprite(handle, all_params())];
+
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+        for _ in 0..2 {
+            recorder
+                .write_records(UVec2::new(320, 240), &sprites, |handle| {
+                    Some(recorded_material(handle))
+                })
+                .unwrap();
+        }
+        let octets = &recorder.writer[..recorder.writer.len() - 3];
+
+        let recording = Recording::read(octets).unwrap();
+        assert_eq!(recording.frames.len(), 1);
+    }
+
+    #[test]
+    fn changed_material_is_recorded_again() {
+        let mut registry = MaterialRegistry::new();
+        let handle = registry.insert(());
+        let sprites = [sprite(handle, SpriteParams::default())];
+        let loading = RecordedMaterial {
+            id: handle.to_bits(),
+            texture_size: UVec2::new(8, 8),
+            source_path: None,
+        };
+        let loaded = RecordedMaterial {
+            id: handle.to_bits(),
+            texture_size: UVec2::new(64, 32),
+            source_path: Some(PathBuf::from("assets/hero.png")),
+        };
+
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+        let mut written = Vec::new();
+        for material in [&loading, &loading, &loaded, &loaded] {
+            let start = recorder.writer.len();
+            recorder
+                .write_records(UVec2::new(320, 240), &sprites, |_| Some(material.clone()))
+                .unwrap();
+            written.push(recorder.writer.len() - start);
+        }
+
+        // Only the first frame and the frame after the change have a material record
+        assert!(written[0] > written[1]);
+        assert!(written[2] > written[3]);
+        assert_eq!(written[1], written[3]);
+        let loading_frames = Recording::read(&recorder.writer[..6 + written[0] + written[1]]);
+        assert_eq!(loading_frames.unwrap().materials, [loading]);
+        let recording = Recording::read(recorder.writer.as_slice()).unwrap();
+        assert_eq!(recording.materials, [loaded]);
+        assert_eq!(recording.frames.len(), 4);
+    }
+
+    #[test]
+    fn huge_material_path_is_invalid_data() {
+        let mut octets = MAGIC.to_vec();
+        octets.extend(VERSION.to_le_bytes());
+        octets.push(MATERIAL_RECORD);
+        octets.extend(7u64.to_le_bytes());
+        octets.extend([16, 0, 16, 0]);
+        octets.extend(u32::MAX.to_le_bytes());
+
+        let err = Recording::read(octets.as_slice()).unwrap_err();
+        assert!(
+            matches!(&err, RecordingError::Io(err) if err.kind() == ErrorKind::InvalidData),
+            "{err:?}"
+        );
+    }
+}
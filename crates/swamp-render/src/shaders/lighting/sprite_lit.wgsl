@@ -0,0 +1,139 @@
+// Forward lit sprites, used with the sprite vertex shader
+
+const MAX_LIGHTS: u32 = 32u;
+const MAX_SHADOW_EDGES: u32 = 1024u;
+const SOFT_SHADOW_SAMPLES: u32 = 5u;
+
+struct Light {
+    position: vec4<f32>, // Position in xy, height above the sprites in z, radius in w
+    color: vec4<f32>,    // Linear color times intensity in rgb, falloff exponent in w
+    spot: vec4<f32>,     // Direction in xy, cosine of the inner and outer cone angle in zw
+    first_edge: u32,     // Range in the shadow edges
+    edge_count: u32,
+    source_radius: f32,  // Zero for hard shadows
+    _pad0: f32,
+};
+
+struct Lighting {
+    ambient: vec4<f32>,
+    light_count: u32,
+    quantize_steps: u32, // Zero for smooth lighting
+    _pad0: u32,
+    _pad1: u32,
+    lights: array<Light, MAX_LIGHTS>,
+};
+
+@group(1) @binding(0) var texture: texture_2d<f32>;
+@group(1) @binding(1) var texture_sampler: sampler;
+
+@group(2) @binding(0) var<uniform> lighting: Lighting;
+@group(2) @binding(1) var normal_map: texture_2d<f32>;
+@group(2) @binding(2) var<uniform> shadow_edges: array<vec4<f32>, MAX_SHADOW_EDGES>; // Start in xy, end in zw
+
+struct FragmentInput {
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) tile_coords: vec2<f32>,
+    @location(3) @interpolate(flat) tile_rect: vec4<f32>,
+    @location(11) world_position: vec2<f32>,
+    @location(12) tint: vec4<f32>,
+};
+
+fn tint_to_linear(tint: vec4<f32>) -> vec4<f32> {
+    let low = tint.rgb / 12.92;
+    let high = pow((tint.rgb + 0.055) / 1.055, vec3<f32>(2.4));
+    return vec4<f32>(select(high, low, tint.rgb <= vec3<f32>(0.04045)), tint.a);
+}
+
+fn quantize(value: f32) -> f32 {
+    if (lighting.quantize_steps == 0u) {
+        return value;
+    }
+    let steps = f32(lighting.quantize_steps);
+    return floor(value * steps + 0.5) / steps;
+}
+
+fn cross_2d(origin: vec2<f32>, a: vec2<f32>, b: vec2<f32>) -> f32 {
+    let u = a - origin;
+    let v = b - origin;
+    return u.x * v.y - u.y * v.x;
+}
+
+fn segments_intersect(a: vec2<f32>, b: vec2<f32>, c: vec2<f32>, d: vec2<f32>) -> bool {
+    return cross_2d(c, d, a) * cross_2d(c, d, b) < 0.0 && cross_2d(a, b, c) * cross_2d(a, b, d) < 0.0;
+}
+
+fn is_blocked(position: vec2<f32>, light_position: vec2<f32>, light: Light) -> bool {
+    let last = min(light.first_edge + light.edge_count, MAX_SHADOW_EDGES);
+    for (var index = light.first_edge; index < last; index++) {
+        let edge = shadow_edges[index];
+        if (segments_intersect(position, light_position, edge.xy, edge.zw)) {
+            return true;
+        }
+    }
+    return false;
+}
+
+// Fraction of the light that reaches the position. Soft shadows test points spread
+// across the light, perpendicular to the direction towards it.
+fn visibility(position: vec2<f32>, light: Light) -> f32 {
+    if (light.edge_count == 0u) {
+        return 1.0;
+    }
+    if (light.source_radius <= 0.0) {
+        return select(1.0, 0.0, is_blocked(position, light.position.xy, light));
+    }
+
+    let to_light = light.position.xy - position;
+    let distance = length(to_light);
+    if (distance <= 0.0) {
+        return 1.0;
+    }
+    let across = vec2<f32>(-to_light.y, to_light.x) / distance;
+    var visible = 0.0;
+    for (var sample = 0u; sample < SOFT_SHADOW_SAMPLES; sample++) {
+        let offset = (f32(sample) / f32(SOFT_SHADOW_SAMPLES - 1u) * 2.0 - 1.0) * light.source_radius;
+        let sample_position = light.position.xy + across * offset;
+        visible += select(1.0, 0.0, is_blocked(position, sample_position, light));
+    }
+    return visible / f32(SOFT_SHADOW_SAMPLES);
+}
+
+@fragment
+fn fs_main(input: FragmentInput) -> @location(0) vec4<f32> {
+    let grad_x = dpdx(input.tex_coords);
+    let grad_y = dpdy(input.tex_coords);
+    let wrapped = input.tile_rect.xy + fract(input.tile_coords) * input.tile_rect.zw;
+    let color = textureSampleGrad(texture, texture_sampler, wrapped, grad_x, grad_y) * tint_to_linear(input.tint);
+
+    // Green points up in the normal map, flipped sprites flip their normals as well
+    var normal = textureSampleGrad(normal_map, texture_sampler, wrapped, grad_x, grad_y).xyz * 2.0 - 1.0;
+    normal = normalize(normal * vec3<f32>(sign(input.tile_rect.z), sign(input.tile_rect.w), 1.0));
+
+    // Quantized lighting is constant over each pixel of the pixel art
+    var position = input.world_position;
+    if (lighting.quantize_steps != 0u) {
+        position = floor(position) + 0.5;
+    }
+
+    var light_sum = lighting.ambient.rgb;
+    for (var index = 0u; index < min(lighting.light_count, MAX_LIGHTS); index++) {
+        let light = lighting.lights[index];
+        let to_light = vec3<f32>(light.position.xy - position, light.position.z);
+        let distance = length(to_light.xy);
+        let attenuation = pow(clamp(1.0 - distance / light.position.w, 0.0, 1.0), light.color.w);
+        let diffuse = max(dot(normal, normalize(to_light)), 0.0);
+
+        let from_light = select(vec2<f32>(0.0), -to_light.xy / distance, distance > 0.0);
+        let cos_angle = dot(from_light, light.spot.xy);
+        let cone = select(1.0, smoothstep(light.spot.w, light.spot.z, cos_angle), light.spot.w > -1.0);
+
+        if (attenuation * cone <= 0.0) {
+            continue;
+        }
+        let shadow = visibility(position, light);
+
+        light_sum += light.color.rgb * quantize(attenuation * diffuse * cone * shadow);
+    }
+
+    return vec4<f32>(color.rgb * light_sum, color.a);
+}
@@ -0,0 +1,24 @@
+// BLUR_DIRECTION is declared in front of this source, one stage per direction
+struct Params {
+    threshold: f32,
+    intensity: f32,
+    radius: f32,
+    _pad0: f32,
+};
+
+@group(0) @binding(4) var<uniform> params: Params;
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    var weights = array<f32, 5>(0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+    let texel = BLUR_DIRECTION * params.radius / vec2<f32>(textureDimensions(source_texture));
+
+    var color = sample_source(input.uv).rgb * weights[0];
+    for (var i = 1; i < 5; i++) {
+        let offset = texel * f32(i);
+        color += sample_source(input.uv + offset).rgb * weights[i];
+        color += sample_source(input.uv - offset).rgb * weights[i];
+    }
+
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,16 @@
+struct Params {
+    threshold: f32,
+    intensity: f32,
+    radius: f32,
+    _pad0: f32,
+};
+
+@group(0) @binding(4) var<uniform> params: Params;
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let original = textureSampleLevel(effect_input_texture, post_sampler, input.uv, 0.0);
+    let bloom = sample_source(input.uv).rgb;
+
+    return vec4<f32>(original.rgb + bloom * params.intensity, original.a);
+}
@@ -0,0 +1,17 @@
+struct Params {
+    threshold: f32,
+    intensity: f32,
+    radius: f32,
+    _pad0: f32,
+};
+
+@group(0) @binding(4) var<uniform> params: Params;
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let color = sample_source(input.uv).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - params.threshold, 0.0) / max(brightness, 0.0001);
+
+    return vec4<f32>(color * contribution, 1.0);
+}
@@ -0,0 +1,36 @@
+// The lookup table in `aux_texture` is a horizontal strip of `size` slices of `size` x `size` texels,
+// one slice per blue value, e.g. 256 x 16.
+struct Params {
+    intensity: f32,
+    _pad0: f32,
+    _pad1: f32,
+    _pad2: f32,
+};
+
+@group(0) @binding(4) var<uniform> params: Params;
+
+fn sample_lut(color: vec3<f32>) -> vec3<f32> {
+    let size = f32(textureDimensions(aux_texture).y);
+    let max_index = size - 1.0;
+
+    let blue = color.b * max_index;
+    let slice_low = floor(blue);
+    let slice_high = min(slice_low + 1.0, max_index);
+
+    let x = (color.r * max_index + 0.5) / (size * size);
+    let y = (color.g * max_index + 0.5) / size;
+
+    let low = textureSampleLevel(aux_texture, post_sampler, vec2<f32>(x + slice_low / size, y), 0.0).rgb;
+    let high = textureSampleLevel(aux_texture, post_sampler, vec2<f32>(x + slice_high / size, y), 0.0).rgb;
+
+    return mix(low, high, blue - slice_low);
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let color = sample_source(input.uv);
+    let perceptual = linear_to_srgb(clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0)));
+    let graded = srgb_to_linear(sample_lut(perceptual));
+
+    return vec4<f32>(mix(color.rgb, graded, params.intensity), color.a);
+}
@@ -0,0 +1,34 @@
+struct Params {
+    curvature: f32,
+    scanline_intensity: f32,
+    // Zero uses one scanline per source pixel row
+    scanline_count: f32,
+    brightness: f32,
+};
+
+@group(0) @binding(4) var<uniform> params: Params;
+
+fn curve(uv: vec2<f32>) -> vec2<f32> {
+    let centered = uv * 2.0 - 1.0;
+    let offset = centered.yx * centered.yx * params.curvature;
+    return (centered + centered * offset) * 0.5 + 0.5;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let uv = curve(input.uv);
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+
+    var scanline_count = params.scanline_count;
+    if scanline_count <= 0.0 {
+        scanline_count = f32(textureDimensions(source_texture).y);
+    }
+
+    let color = sample_source(uv);
+    let scanline = abs(sin(uv.y * scanline_count * 3.14159265));
+    let shade = mix(1.0, scanline, params.scanline_intensity);
+
+    return vec4<f32>(color.rgb * shade * params.brightness, color.a);
+}
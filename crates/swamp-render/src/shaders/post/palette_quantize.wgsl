@@ -0,0 +1,27 @@
+struct Params {
+    levels: f32,
+    dither: f32,
+    _pad0: f32,
+    _pad1: f32,
+};
+
+@group(0) @binding(4) var<uniform> params: Params;
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    var bayer = array<f32, 16>(
+        0.0, 8.0, 2.0, 10.0,
+        12.0, 4.0, 14.0, 6.0,
+        3.0, 11.0, 1.0, 9.0,
+        15.0, 7.0, 13.0, 5.0,
+    );
+    let cell = vec2<u32>(input.position.xy) % 4u;
+    let threshold = (bayer[cell.y * 4u + cell.x] + 0.5) / 16.0 - 0.5;
+
+    let color = sample_source(input.uv);
+    let steps = max(params.levels - 1.0, 1.0);
+    let perceptual = linear_to_srgb(clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0)));
+    let quantized = floor(perceptual * steps + 0.5 + threshold * params.dither) / steps;
+
+    return vec4<f32>(srgb_to_linear(clamp(quantized, vec3<f32>(0.0), vec3<f32>(1.0))), color.a);
+}
@@ -0,0 +1,50 @@
+// Shared by all post process effects. The effect source is appended to this.
+
+struct PostGlobals {
+    time: f32,
+    _pad0: f32,
+    _pad1: f32,
+    _pad2: f32,
+};
+
+// Output of the previous stage (or the scene for the first stage)
+@group(0) @binding(0) var source_texture: texture_2d<f32>;
+// Input to the first stage of the current effect
+@group(0) @binding(1) var effect_input_texture: texture_2d<f32>;
+@group(0) @binding(2) var post_sampler: sampler;
+@group(0) @binding(3) var<uniform> globals: PostGlobals;
+// @group(0) @binding(4) is the effect specific `params` uniform, declared by each effect
+@group(0) @binding(5) var aux_texture: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    // A single triangle that covers the whole target
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    output.uv = uv;
+
+    return output;
+}
+
+fn sample_source(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(source_texture, post_sampler, uv, 0.0);
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
@@ -0,0 +1,17 @@
+struct Params {
+    intensity: f32,
+    radius: f32,
+    softness: f32,
+    _pad0: f32,
+};
+
+@group(0) @binding(4) var<uniform> params: Params;
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let color = sample_source(input.uv);
+    let distance_from_center = distance(input.uv, vec2<f32>(0.5));
+    let vignette = 1.0 - smoothstep(params.radius - params.softness, params.radius, distance_from_center);
+
+    return vec4<f32>(color.rgb * mix(1.0, vignette, params.intensity), color.a);
+}
@@ -0,0 +1,327 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+//! Occluder geometry for 2D shadows. Everything here runs on the CPU, the edges that
+//! survive [`ShadowGeometry::build`] are what the lit sprite shader tests against.
+
+use int_math::{UVec2, Vec2};
+
+/// A wall segment in pixels. The outward normal is to the right of `start` to `end`,
+/// so counter clockwise polygons (with `y` pointing up) face outwards.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OccluderEdge {
+    pub start: Vec2,
+    pub end: Vec2,
+}
+
+impl OccluderEdge {
+    pub const fn new(start: Vec2, end: Vec2) -> Self {
+        Self { start, end }
+    }
+
+    fn start_f32(&self) -> [f32; 2] {
+        [self.start.x.into(), self.start.y.into()]
+    }
+
+    fn end_f32(&self) -> [f32; 2] {
+        [self.end.x.into(), self.end.y.into()]
+    }
+
+    /// Edges only cast shadows from their back side, so light reaches the front of a wall
+    /// and the wall is not shadowed by itself.
+    pub fn faces_away_from(&self, point: [f32; 2]) -> bool {
+        let [start_x, start_y] = self.start_f32();
+        let [end_x, end_y] = self.end_f32();
+        let normal = [end_y - start_y, start_x - end_x];
+        let to_point = [point[0] - start_x, point[1] - start_y];
+
+        normal[0] * to_point[0] + normal[1] * to_point[1] <= 0.0
+    }
+
+    pub fn distance_to(&self, point: [f32; 2]) -> f32 {
+        let [start_x, start_y] = self.start_f32();
+        let [end_x, end_y] = self.end_f32();
+        let edge = [end_x - start_x, end_y - start_y];
+        let to_point = [point[0] - start_x, point[1] - start_y];
+        let length_squared = edge[0] * edge[0] + edge[1] * edge[1];
+        let t = if length_squared > 0.0 {
+            ((to_point[0] * edge[0] + to_point[1] * edge[1]) / length_squared).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let closest = [start_x + edge[0] * t, start_y + edge[1] * t];
+
+        ((point[0] - closest[0]).powi(2) + (point[1] - closest[1]).powi(2)).sqrt()
+    }
+
+    /// True if the segment from `from` to `to` crosses the edge. Touching does not count,
+    /// the same as in the shader.
+    pub fn blocks(&self, from: [f32; 2], to: [f32; 2]) -> bool {
+        segments_intersect(from, to, self.start_f32(), self.end_f32())
+    }
+}
+
+fn cross(origin: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+    (a[0] - origin[0]) * (b[1] - origin[1]) - (a[1] - origin[1]) * (b[0] - origin[0])
+}
+
+fn segments_intersect(a: [f32; 2], b: [f32; 2], c: [f32; 2], d: [f32; 2]) -> bool {
+    cross(c, d, a) * cross(c, d, b) < 0.0 && cross(a, b, c) * cross(a, b, d) < 0.0
+}
+
+/// The edges of a closed polygon, which should be counter clockwise to face outwards.
+pub fn polygon_edges(points: &[Vec2]) -> Vec<OccluderEdge> {
+    if points.len() < 2 {
+        return Vec::new();
+    }
+
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(start, end)| OccluderEdge::new(*start, *end))
+        .collect()
+}
+
+/// The outlines of the solid tiles in a `size` grid, with tile (0, 0) in the lower left
+/// corner at `origin`. Neighbouring edges along the same row or column are merged, so a
+/// straight wall becomes a single edge no matter how many tiles it spans.
+pub fn tile_edges(
+    size: UVec2,
+    tile_size: UVec2,
+    origin: Vec2,
+    is_solid: impl Fn(u16, u16) -> bool,
+) -> Vec<OccluderEdge> {
+    let solid = |x: i32, y: i32| {
+        x >= 0
+            && y >= 0
+            && x < i32::from(size.x)
+            && y < i32::from(size.y)
+            && is_solid(x as u16, y as u16)
+    };
+    let corner = |x: i32, y: i32| {
+        Vec2::new(
+            (i32::from(origin.x) + x * i32::from(tile_size.x)) as i16,
+            (i32::from(origin.y) + y * i32::from(tile_size.y)) as i16,
+        )
+    };
+
+    let mut edges = Vec::new();
+    let width = i32::from(size.x);
+    let height = i32::from(size.y);
+
+    // Horizontal edges, on the line below row `y`
+    for y in 0..=height {
+        let mut run: Option<(i32, bool)> = None;
+        for x in 0..=width {
+            // Solid above facing down, or solid below facing up
+            let facing = match (solid(x, y), solid(x, y - 1)) {
+                (true, false) => Some(false),
+                (false, true) => Some(true),
+                _ => None,
+            };
+            if let Some((start, facing_up)) = run {
+                if facing != Some(facing_up) {
+                    edges.push(if facing_up {
+                        OccluderEdge::new(corner(x, y), corner(start, y))
+                    } else {
+                        OccluderEdge::new(corner(start, y), corner(x, y))
+                    });
+                    run = None;
+                }
+            }
+            if run.is_none() {
+                run = facing.map(|facing_up| (x, facing_up));
+            }
+        }
+    }
+
+    // Vertical edges, on the line left of column `x`
+    for x in 0..=width {
+        let mut run: Option<(i32, bool)> = None;
+        for y in 0..=height {
+            // Solid to the right facing left, or solid to the left facing right
+            let facing = match (solid(x, y), solid(x - 1, y)) {
+                (true, false) => Some(false),
+                (false, true) => Some(true),
+                _ => None,
+            };
+            if let Some((start, facing_right)) = run {
+                if facing != Some(facing_right) {
+                    edges.push(if facing_right {
+                        OccluderEdge::new(corner(x, start), corner(x, y))
+                    } else {
+                        OccluderEdge::new(corner(x, y), corner(x, start))
+                    });
+                    run = None;
+                }
+            }
+            if run.is_none() {
+                run = facing.map(|facing_right| (y, facing_right));
+            }
+        }
+    }
+
+    edges
+}
+
+/// True if any edge blocks the line between the point and the light, ignoring the radius.
+pub fn is_shadowed(point: [f32; 2], light: [f32; 2], edges: &[OccluderEdge]) -> bool {
+    edges
+        .iter()
+        .any(|edge| edge.faces_away_from(light) && edge.blocks(point, light))
+}
+
+/// The edges each light has to test, stored back to back.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ShadowGeometry {
+    /// Start in `xy` and end in `zw`
+    pub edges: Vec<[f32; 4]>,
+    /// First edge and edge count for each light
+    pub ranges: Vec<(u32, u32)>,
+}
+
+impl ShadowGeometry {
+    /// Keeps the edges that are within the radius of a light and face away from it.
+    /// Lights stop getting edges once `max_edges` is reached.
+    pub fn build(
+        occluders: &[OccluderEdge],
+        lights: impl IntoIterator<Item = ([f32; 2], f32)>,
+        max_edges: usize,
+    ) -> Self {
+        let mut geometry = Self::default();
+
+        for (position, radius) in lights {
+            let first = geometry.edges.len();
+            for edge in occluders {
+                if geometry.edges.len() == max_edges {
+                    break;
+                }
+                if edge.faces_away_from(position) && edge.distance_to(position) <= radius {
+                    let [start_x, start_y] = edge.start_f32();
+                    let [end_x, end_y] = edge.end_f32();
+                    geometry.edges.push([start_x, start_y, end_x, end_y]);
+                }
+            }
+            geometry
+                .ranges
+                .push((first as u32, (geometry.edges.len() - first) as u32));
+        }
+
+        geometry
+    }
+
+    pub fn is_full(&self, max_edges: usize) -> bool {
+        self.edges.len() >= max_edges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(start: (i16, i16), end: (i16, i16)) -> OccluderEdge {
+        OccluderEdge::new(Vec2::new(start.0, start.1), Vec2::new(end.0, end.1))
+    }
+
+    fn assert_same_edges(mut edges: Vec<OccluderEdge>, expected: &[OccluderEdge]) {
+        let key = |edge: &OccluderEdge| (edge.start.x, edge.start.y, edge.end.x, edge.end.y);
+        let mut expected = expected.to_vec();
+        edges.sort_by_key(key);
+        expected.sort_by_key(key);
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn polygon_edges_are_closed_and_face_outwards() {
+        let points = [
+            Vec2::new(0, 0),
+            Vec2::new(10, 0),
+            Vec2::new(10, 10),
+            Vec2::new(0, 10),
+        ];
+        let edges = polygon_edges(&points);
+
+        assert_eq!(edges.len(), 4);
+        for (edge, next) in edges.iter().zip(edges.iter().cycle().skip(1)) {
+            assert_eq!(edge.end, next.start);
+        }
+        assert_eq!(edges[0], edge((0, 0), (10, 0)));
+        assert_eq!(edges[3], edge((0, 10), (0, 0)));
+
+        assert!(edges.iter().all(|edge| edge.faces_away_from([5.0, 5.0])));
+        assert!(!edges[0].faces_away_from([5.0, -5.0]));
+        assert!(!edges[1].faces_away_from([15.0, 5.0]));
+    }
+
+    #[test]
+    fn polygon_with_less_than_two_points_has_no_edges() {
+        assert!(polygon_edges(&[]).is_empty());
+        assert!(polygon_edges(&[Vec2::new(1, 2)]).is_empty());
+    }
+
+    #[test]
+    fn adjacent_tiles_share_no_interior_edges() {
+        let edges = tile_edges(
+            UVec2::new(3, 2),
+            UVec2::new(8, 4),
+            Vec2::new(-8, 4),
+            |_, _| true,
+        );
+
+        assert_same_edges(
+            edges,
+            &[
+                edge((-8, 4), (16, 4)),
+                edge((16, 4), (16, 12)),
+                edge((16, 12), (-8, 12)),
+                edge((-8, 12), (-8, 4)),
+            ],
+        );
+    }
+
+    #[test]
+    fn tile_edges_face_away_from_solid_tiles() {
+        // Solid tiles in an L: (0, 0), (1, 0) and (0, 1)
+        let edges = tile_edges(
+            UVec2::new(2, 2),
+            UVec2::new(10, 10),
+            Vec2::new(0, 0),
+            |x, y| x == 0 || y == 0,
+        );
+
+        assert_same_edges(
+            edges.clone(),
+            &[
+                edge((0, 0), (20, 0)),
+                edge((20, 0), (20, 10)),
+                edge((20, 10), (10, 10)),
+                edge((10, 10), (10, 20)),
+                edge((10, 20), (0, 20)),
+                edge((0, 20), (0, 0)),
+            ],
+        );
+        // Behind every edge is a solid tile and in front of it an empty one
+        let is_solid_at = |[x, y]: [f32; 2]| {
+            (0.0..20.0).contains(&x) && (0.0..20.0).contains(&y) && (x < 10.0 || y < 10.0)
+        };
+        for edge in &edges {
+            let [start_x, start_y] = edge.start_f32();
+            let [end_x, end_y] = edge.end_f32();
+            let middle = [(start_x + end_x) / 2.0, (start_y + end_y) / 2.0];
+            let normal = [(end_y - start_y).signum(), (start_x - end_x).signum()];
+            let front = [middle[0] + normal[0], middle[1] + normal[1]];
+            let back = [middle[0] - normal[0], middle[1] - normal[1]];
+            assert!(!edge.faces_away_from(front) && edge.faces_away_from(back));
+            assert!(!is_solid_at(front) && is_solid_at(back), "{edge:?}");
+        }
+    }
+
+    #[test]
+    fn empty_map_has_no_edges() {
+        let tile_size = UVec2::new(16, 16);
+        assert!(tile_edges(UVec2::new(4, 3), tile_size, Vec2::new(0, 0), |_, _| false).is_empty());
+        assert!(tile_edges(UVec2::new(0, 0), tile_size, Vec2::new(0, 0), |_, _| true).is_empty());
+    }
+}
@@ -0,0 +1,977 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+//! Bone animation from a subset of the Spine JSON format: bones, slots, region
+//! attachments, rotate, translate and scale keys, attachment keys and draw order keys.
+//! Both the array skins of Spine 4 and the object skins of Spine 3 are read. Bezier
+//! curves are played back as linear, and meshes, constraints and shear are ignored.
+
+use int_math::{URect, UVec2};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use swamp_wgpu_sprites::SpriteVertex;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BonePose {
+    /// In pixels, relative to the parent bone
+    pub x: f32,
+    pub y: f32,
+    /// Degrees, counter clockwise
+    pub rotation: f32,
+    pub scale_x: f32,
+    pub scale_y: f32,
+}
+
+impl Default for BonePose {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            rotation: 0.0,
+            scale_x: 1.0,
+            scale_y: 1.0,
+        }
+    }
+}
+
+/// A 2x3 matrix from bone space to skeleton space.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BoneTransform {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub x: f32,
+    pub y: f32,
+}
+
+impl BoneTransform {
+    pub const IDENTITY: Self = Self {
+        a: 1.0,
+        b: 0.0,
+        c: 0.0,
+        d: 1.0,
+        x: 0.0,
+        y: 0.0,
+    };
+
+    fn from_pose(pose: &BonePose) -> Self {
+        let (sin, cos) = pose.rotation.to_radians().sin_cos();
+        Self {
+            a: cos * pose.scale_x,
+            b: -sin * pose.scale_y,
+            c: sin * pose.scale_x,
+            d: cos * pose.scale_y,
+            x: pose.x,
+            y: pose.y,
+        }
+    }
+
+    /// `self` applied after `local`.
+    fn then(&self, local: &Self) -> Self {
+        Self {
+            a: self.a * local.a + self.b * local.c,
+            b: self.a * local.b + self.b * local.d,
+            c: self.c * local.a + self.d * local.c,
+            d: self.c * local.b + self.d * local.d,
+            x: self.a * local.x + self.b * local.y + self.x,
+            y: self.c * local.x + self.d * local.y + self.y,
+        }
+    }
+
+    pub fn apply(&self, point: [f32; 2]) -> [f32; 2] {
+        [
+            self.a * point[0] + self.b * point[1] + self.x,
+            self.c * point[0] + self.d * point[1] + self.y,
+        ]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoneData {
+    pub name: String,
+    /// Always before the bone itself
+    pub parent: Option<usize>,
+    pub setup: BonePose,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlotData {
+    pub name: String,
+    pub bone: usize,
+    /// Straight alpha sRGB
+    pub color: [u8; 4],
+    pub attachment: Option<String>,
+}
+
+/// An atlas region drawn as a quad centered on its offset from the bone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionAttachment {
+    /// Name of the atlas region, the `path` of the attachment or else its name
+    pub region: String,
+    pub offset: BonePose,
+    /// In pixels, before the scale
+    pub width: f32,
+    pub height: f32,
+    /// Straight alpha sRGB
+    pub color: [u8; 4],
+}
+
+/// Region attachments for each slot, by attachment name.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Skin {
+    pub attachments: Vec<HashMap<String, RegionAttachment>>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Key<T> {
+    time: f32,
+    value: T,
+    /// Holds the value until the next key
+    stepped: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct BoneTimeline {
+    bone: usize,
+    /// Degrees added to the setup rotation
+    rotate: Vec<Key<f32>>,
+    /// Pixels added to the setup position
+    translate: Vec<Key<[f32; 2]>>,
+    /// Multiplied with the setup scale
+    scale: Vec<Key<[f32; 2]>>,
+}
+
+/// Slot and the attachment name from each key time, `None` hides the slot.
+type AttachmentTimeline = (usize, Vec<(f32, Option<String>)>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    /// Seconds, the time of the last key
+    pub duration: f32,
+    bones: Vec<BoneTimeline>,
+    attachments: Vec<AttachmentTimeline>,
+    /// Slot indices from back to front, `None` restores the setup order
+    draw_order: Vec<(f32, Option<Vec<usize>>)>,
+}
+
+#[derive(Debug)]
+pub enum SkeletonError {
+    Json(serde_json::Error),
+    UnknownBone(String),
+    UnknownSlot(String),
+    InvalidColor(String),
+    InvalidDrawOrder,
+}
+
+impl Display for SkeletonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Json(err) => write!(f, "could not read skeleton json: {err}"),
+            Self::UnknownBone(name) => write!(f, "unknown bone '{name}'"),
+            Self::UnknownSlot(name) => write!(f, "unknown slot '{name}'"),
+            Self::InvalidColor(color) => write!(f, "invalid color '{color}'"),
+            Self::InvalidDrawOrder => {
+                write!(f, "draw order offsets are out of range or overlap")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SkeletonError {}
+
+impl From<serde_json::Error> for SkeletonError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+/// Everything read from the file, shared by all [`Skeleton`]s that use it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkeletonData {
+    pub bones: Vec<BoneData>,
+    /// In setup draw order, from back to front
+    pub slots: Vec<SlotData>,
+    pub skins: HashMap<String, Skin>,
+    pub animations: HashMap<String, Animation>,
+}
+
+impl SkeletonData {
+    pub fn from_json(json: &str) -> Result<Self, SkeletonError> {
+        let file: JsonSkeleton = serde_json::from_str(json)?;
+
+        let mut bones: Vec<BoneData> = Vec::with_capacity(file.bones.len());
+        for bone in &file.bones {
+            let parent = bone
+                .parent
+                .as_ref()
+                .map(|parent| find(&bones, parent, |bone| &bone.name))
+                .transpose()
+                .map_err(SkeletonError::UnknownBone)?;
+            bones.push(BoneData {
+                name: bone.name.clone(),
+                parent,
+                setup: BonePose {
+                    x: bone.x,
+                    y: bone.y,
+                    rotation: bone.rotation,
+                    scale_x: bone.scale_x,
+                    scale_y: bone.scale_y,
+                },
+            });
+        }
+
+        let slots = file
+            .slots
+            .iter()
+            .map(|slot| {
+                Ok(SlotData {
+                    name: slot.name.clone(),
+                    bone: find(&bones, &slot.bone, |bone| &bone.name)
+                        .map_err(SkeletonError::UnknownBone)?,
+                    color: parse_color(slot.color.as_deref())?,
+                    attachment: slot.attachment.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, SkeletonError>>()?;
+
+        let skin_list = match file.skins {
+            JsonSkins::List(skins) => skins
+                .into_iter()
+                .map(|skin| (skin.name, skin.attachments))
+                .collect(),
+            JsonSkins::Map(skins) => skins.into_iter().collect::<Vec<_>>(),
+        };
+        let mut skins = HashMap::new();
+        for (name, slot_attachments) in skin_list {
+            let mut skin = Skin {
+                attachments: vec![HashMap::new(); slots.len()],
+            };
+            for (slot_name, attachments) in slot_attachments {
+                let slot = find(&slots, &slot_name, |slot| &slot.name)
+                    .map_err(SkeletonError::UnknownSlot)?;
+                for (attachment_name, attachment) in attachments {
+                    if attachment
+                        .kind
+                        .as_deref()
+                        .is_some_and(|kind| kind != "region")
+                    {
+                        continue;
+                    }
+                    let region = attachment
+                        .path
+                        .or(attachment.name)
+                        .unwrap_or_else(|| attachment_name.clone());
+                    skin.attachments[slot].insert(
+                        attachment_name,
+                        RegionAttachment {
+                            region,
+                            offset: BonePose {
+                                x: attachment.x,
+                                y: attachment.y,
+                                rotation: attachment.rotation,
+                                scale_x: attachment.scale_x,
+                                scale_y: attachment.scale_y,
+                            },
+                            width: attachment.width,
+                            height: attachment.height,
+                            color: parse_color(attachment.color.as_deref())?,
+                        },
+                    );
+                }
+            }
+            skins.insert(name, skin);
+        }
+
+        let animations = file
+            .animations
+            .into_iter()
+            .map(|(name, animation)| Ok((name, read_animation(animation, &bones, &slots)?)))
+            .collect::<Result<_, SkeletonError>>()?;
+
+        Ok(Self {
+            bones,
+            slots,
+            skins,
+            animations,
+        })
+    }
+}
+
+/// A posed instance of [`SkeletonData`], drawn with [`crate::Render::render_skeleton`].
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+    data: Arc<SkeletonData>,
+    /// Attachments missing from this skin are looked up in the `default` skin
+    pub skin: String,
+    poses: Vec<BonePose>,
+    world: Vec<BoneTransform>,
+    attachments: Vec<Option<String>>,
+    draw_order: Vec<usize>,
+}
+
+impl Skeleton {
+    pub fn new(data: Arc<SkeletonData>) -> Self {
+        let mut skeleton = Self {
+            skin: "default".to_string(),
+            poses: Vec::new(),
+            world: vec![BoneTransform::IDENTITY; data.bones.len()],
+            attachments: Vec::new(),
+            draw_order: Vec::new(),
+            data,
+        };
+        skeleton.set_to_setup_pose();
+        skeleton.update_world_transforms();
+
+        skeleton
+    }
+
+    pub fn data(&self) -> &SkeletonData {
+        &self.data
+    }
+
+    pub fn set_to_setup_pose(&mut self) {
+        self.poses = self.data.bones.iter().map(|bone| bone.setup).collect();
+        self.attachments = self
+            .data
+            .slots
+            .iter()
+            .map(|slot| slot.attachment.clone())
+            .collect();
+        self.draw_order = (0..self.data.slots.len()).collect();
+    }
+
+    /// Poses the bones that have keys in the animation. Call
+    /// [`Skeleton::update_world_transforms`] afterwards.
+    pub fn apply(&mut self, animation: &Animation, time: f32, looping: bool) {
+        let time = if looping && animation.duration > 0.0 {
+            time.rem_euclid(animation.duration)
+        } else {
+            time.min(animation.duration)
+        };
+
+        for timeline in &animation.bones {
+            let setup = self.data.bones[timeline.bone].setup;
+            let pose = &mut self.poses[timeline.bone];
+            if let Some(rotation) = sample(&timeline.rotate, time, lerp) {
+                pose.rotation = setup.rotation + rotation;
+            }
+            if let Some([x, y]) = sample(&timeline.translate, time, lerp2) {
+                pose.x = setup.x + x;
+                pose.y = setup.y + y;
+            }
+            if let Some([scale_x, scale_y]) = sample(&timeline.scale, time, lerp2) {
+                pose.scale_x = setup.scale_x * scale_x;
+                pose.scale_y = setup.scale_y * scale_y;
+            }
+        }
+
+        for (slot, keys) in &animation.attachments {
+            if let Some((_, name)) = keys.iter().rev().find(|(key_time, _)| *key_time <= time) {
+                self.attachments[*slot] = name.clone();
+            }
+        }
+
+        if let Some((_, order)) = animation
+            .draw_order
+            .iter()
+            .rev()
+            .find(|(key_time, _)| *key_time <= time)
+        {
+            self.draw_order = order
+                .clone()
+                .unwrap_or_else(|| (0..self.data.slots.len()).collect());
+        }
+    }
+
+    pub fn update_world_transforms(&mut self) {
+        for (index, bone) in self.data.bones.iter().enumerate() {
+            let local = BoneTransform::from_pose(&self.poses[index]);
+            self.world[index] = match bone.parent {
+                Some(parent) => self.world[parent].then(&local),
+                None => local,
+            };
+        }
+    }
+
+    pub fn bone_pose_mut(&mut self, name: &str) -> Option<&mut BonePose> {
+        let index = self.data.bones.iter().position(|bone| bone.name == name)?;
+        Some(&mut self.poses[index])
+    }
+
+    /// In skeleton space, e.g. for attaching a weapon to a hand.
+    pub fn bone_transform(&self, name: &str) -> Option<BoneTransform> {
+        let index = self.data.bones.iter().position(|bone| bone.name == name)?;
+        Some(self.world[index])
+    }
+
+    fn attachment(&self, slot: usize, name: &str) -> Option<&RegionAttachment> {
+        [self.skin.as_str(), "default"]
+            .iter()
+            .filter_map(|skin| self.data.skins.get(*skin))
+            .find_map(|skin| skin.attachments[slot].get(name))
+    }
+
+    /// Appends one quad for each visible region attachment, in draw order. `regions`
+    /// looks up the atlas rect of a region name, attachments without one are skipped.
+    /// Texture coordinates span the whole `texture_size`.
+    pub fn build_mesh(
+        &self,
+        regions: impl Fn(&str) -> Option<URect>,
+        texture_size: UVec2,
+        vertices: &mut Vec<SpriteVertex>,
+        indices: &mut Vec<u16>,
+    ) {
+        let texture_width = f32::from(texture_size.x.max(1));
+        let texture_height = f32::from(texture_size.y.max(1));
+
+        for &slot in &self.draw_order {
+            let Some(name) = &self.attachments[slot] else {
+                continue;
+            };
+            let Some(attachment) = self.attachment(slot, name) else {
+                continue;
+            };
+            let Some(rect) = regions(&attachment.region) else {
+                continue;
+            };
+            let Ok(first) = u16::try_from(vertices.len()) else {
+                break;
+            };
+            if first > u16::MAX - 4 {
+                break;
+            }
+
+            let slot_data = &self.data.slots[slot];
+            let transform =
+                self.world[slot_data.bone].then(&BoneTransform::from_pose(&attachment.offset));
+            let color = multiply_colors(slot_data.color, attachment.color);
+
+            let left = f32::from(rect.position.x) / texture_width;
+            let top = f32::from(rect.position.y) / texture_height;
+            let right = left + f32::from(rect.size.x) / texture_width;
+            let bottom = top + f32::from(rect.size.y) / texture_height;
+            let half_width = attachment.width / 2.0;
+            let half_height = attachment.height / 2.0;
+
+            // Counter clockwise from the lower left corner, texture v points down
+            for (corner, tex_coords) in [
+                ([-half_width, -half_height], [left, bottom]),
+                ([half_width, -half_height], [right, bottom]),
+                ([half_width, half_height], [right, top]),
+                ([-half_width, half_height], [left, top]),
+            ] {
+                vertices.push(SpriteVertex {
+                    position: transform.apply(corner),
+                    tex_coords,
+                    color,
+                });
+            }
+            indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+    }
+}
+
+fn find<T>(items: &[T], name: &str, item_name: impl Fn(&T) -> &String) -> Result<usize, String> {
+    items
+        .iter()
+        .position(|item| item_name(item) == name)
+        .ok_or_else(|| name.to_string())
+}
+
+/// `RRGGBB` or `RRGGBBAA`, white when missing.
+fn parse_color(color: Option<&str>) -> Result<[u8; 4], SkeletonError> {
+    let Some(color) = color else {
+        return Ok([255; 4]);
+    };
+    let invalid = || SkeletonError::InvalidColor(color.to_string());
+    if !matches!(color.len(), 6 | 8) || !color.is_ascii() {
+        return Err(invalid());
+    }
+
+    let mut rgba = [255; 4];
+    for (index, channel) in rgba.iter_mut().enumerate().take(color.len() / 2) {
+        *channel =
+            u8::from_str_radix(&color[index * 2..index * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+
+    Ok(rgba)
+}
+
+fn multiply_colors(a: [u8; 4], b: [u8; 4]) -> [u8; 4] {
+    std::array::from_fn(|index| ((u16::from(a[index]) * u16::from(b[index])) / 255) as u8)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn lerp2(a: [f32; 2], b: [f32; 2], t: f32) -> [f32; 2] {
+    [lerp(a[0], b[0], t), lerp(a[1], b[1], t)]
+}
+
+/// The value at `time`, holding the first and the last key outside of the keys.
+fn sample<T: Copy>(keys: &[Key<T>], time: f32, lerp: impl Fn(T, T, f32) -> T) -> Option<T> {
+    let after = keys.partition_point(|key| key.time <= time);
+    let Some(before) = after.checked_sub(1) else {
+        return keys.first().map(|key| key.value);
+    };
+    let start = &keys[before];
+    match keys.get(after) {
+        Some(end) if !start.stepped && end.time > start.time => Some(lerp(
+            start.value,
+            end.value,
+            (time - start.time) / (end.time - start.time),
+        )),
+        _ => Some(start.value),
+    }
+}
+
+fn read_keys<T>(keys: Vec<JsonKey>, value: impl Fn(&JsonKey) -> T) -> Vec<Key<T>> {
+    keys.iter()
+        .map(|key| Key {
+            time: key.time,
+            value: value(key),
+            stepped: key
+                .curve
+                .as_ref()
+                .is_some_and(|curve| curve.as_str() == Some("stepped")),
+        })
+        .collect()
+}
+
+fn read_animation(
+    animation: JsonAnimation,
+    bones: &[BoneData],
+    slots: &[SlotData],
+) -> Result<Animation, SkeletonError> {
+    let mut duration: f32 = 0.0;
+    let mut last_time = |time: f32| duration = duration.max(time);
+
+    let mut bone_timelines = Vec::new();
+    for (name, timelines) in animation.bones {
+        let bone = find(bones, &name, |bone| &bone.name).map_err(SkeletonError::UnknownBone)?;
+        let timeline = BoneTimeline {
+            bone,
+            rotate: read_keys(timelines.rotate, |key| {
+                key.value.or(key.angle).unwrap_or(0.0)
+            }),
+            translate: read_keys(timelines.translate, |key| {
+                [key.x.unwrap_or(0.0), key.y.unwrap_or(0.0)]
+            }),
+            scale: read_keys(timelines.scale, |key| {
+                [key.x.unwrap_or(1.0), key.y.unwrap_or(1.0)]
+            }),
+        };
+        for time in timeline
+            .rotate
+            .iter()
+            .map(|key| key.time)
+            .chain(timeline.translate.iter().map(|key| key.time))
+            .chain(timeline.scale.iter().map(|key| key.time))
+        {
+            last_time(time);
+        }
+        bone_timelines.push(timeline);
+    }
+
+    let mut attachments = Vec::new();
+    for (name, timelines) in animation.slots {
+        let slot = find(slots, &name, |slot| &slot.name).map_err(SkeletonError::UnknownSlot)?;
+        let keys: Vec<_> = timelines
+            .attachment
+            .into_iter()
+            .map(|key| (key.time, key.name))
+            .collect();
+        for (time, _) in &keys {
+            last_time(*time);
+        }
+        attachments.push((slot, keys));
+    }
+
+    let mut draw_order = Vec::new();
+    for key in animation.draw_order {
+        last_time(key.time);
+        let order = key
+            .offsets
+            .map(|offsets| {
+                let offsets = offsets
+                    .into_iter()
+                    .map(|offset| {
+                        find(slots, &offset.slot, |slot| &slot.name)
+                            .map(|slot| (slot, offset.offset))
+                            .map_err(SkeletonError::UnknownSlot)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                apply_draw_order_offsets(slots.len(), offsets)
+            })
+            .transpose()?;
+        draw_order.push((key.time, order));
+    }
+
+    Ok(Animation {
+        duration,
+        bones: bone_timelines,
+        attachments,
+        draw_order,
+    })
+}
+
+/// Moves the listed slots by their offsets and fills the gaps with the other slots in
+/// setup order, the same way as the Spine runtimes. Two slots can not move to the same
+/// place.
+fn apply_draw_order_offsets(
+    slot_count: usize,
+    mut offsets: Vec<(usize, i32)>,
+) -> Result<Vec<usize>, SkeletonError> {
+    offsets.sort_by_key(|(slot, _)| *slot);
+
+    let mut order: Vec<Option<usize>> = vec![None; slot_count];
+    let mut unchanged = Vec::with_capacity(slot_count);
+    let mut original = 0;
+    for (slot, offset) in offsets {
+        // The same slot twice
+        if slot < original {
+            return Err(SkeletonError::InvalidDrawOrder);
+        }
+        while original < slot {
+            unchanged.push(original);
+            original += 1;
+        }
+        let target = usize::try_from(original as i64 + i64::from(offset))
+            .ok()
+            .filter(|target| *target < slot_count)
+            .ok_or(SkeletonError::InvalidDrawOrder)?;
+        if order[target].replace(original).is_some() {
+            return Err(SkeletonError::InvalidDrawOrder);
+        }
+        original += 1;
+    }
+    unchanged.extend(original..slot_count);
+
+    let mut unchanged = unchanged.into_iter().rev();
+    order
+        .into_iter()
+        .rev()
+        .map(|slot| slot.or_else(|| unchanged.next()))
+        .collect::<Option<Vec<_>>>()
+        .map(|mut order| {
+            order.reverse();
+            order
+        })
+        .ok_or(SkeletonError::InvalidDrawOrder)
+}
+
+// -------- Spine JSON
+
+fn one() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+struct JsonSkeleton {
+    #[serde(default)]
+    bones: Vec<JsonBone>,
+    #[serde(default)]
+    slots: Vec<JsonSlot>,
+    #[serde(default)]
+    skins: JsonSkins,
+    #[serde(default)]
+    animations: HashMap<String, JsonAnimation>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonBone {
+    name: String,
+    parent: Option<String>,
+    #[serde(default)]
+    x: f32,
+    #[serde(default)]
+    y: f32,
+    #[serde(default)]
+    rotation: f32,
+    #[serde(default = "one")]
+    scale_x: f32,
+    #[serde(default = "one")]
+    scale_y: f32,
+}
+
+#[derive(Deserialize)]
+struct JsonSlot {
+    name: String,
+    bone: String,
+    attachment: Option<String>,
+    color: Option<String>,
+}
+
+/// Slot name to attachment name to attachment.
+type JsonSlotAttachments = HashMap<String, HashMap<String, JsonAttachment>>;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonSkins {
+    /// Spine 4
+    List(Vec<JsonSkin>),
+    /// Spine 3, by skin name
+    Map(HashMap<String, JsonSlotAttachments>),
+}
+
+impl Default for JsonSkins {
+    fn default() -> Self {
+        Self::List(Vec::new())
+    }
+}
+
+#[derive(Deserialize)]
+struct JsonSkin {
+    name: String,
+    #[serde(default)]
+    attachments: JsonSlotAttachments,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonAttachment {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    name: Option<String>,
+    path: Option<String>,
+    #[serde(default)]
+    x: f32,
+    #[serde(default)]
+    y: f32,
+    #[serde(default)]
+    rotation: f32,
+    #[serde(default = "one")]
+    scale_x: f32,
+    #[serde(default = "one")]
+    scale_y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    color: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonAnimation {
+    #[serde(default)]
+    bones: HashMap<String, JsonBoneTimelines>,
+    #[serde(default)]
+    slots: HashMap<String, JsonSlotTimelines>,
+    #[serde(default, alias = "draworder")]
+    draw_order: Vec<JsonDrawOrderKey>,
+}
+
+#[derive(Deserialize)]
+struct JsonBoneTimelines {
+    #[serde(default)]
+    rotate: Vec<JsonKey>,
+    #[serde(default)]
+    translate: Vec<JsonKey>,
+    #[serde(default)]
+    scale: Vec<JsonKey>,
+}
+
+#[derive(Deserialize)]
+struct JsonKey {
+    #[serde(default)]
+    time: f32,
+    /// Spine 4 rotation
+    value: Option<f32>,
+    /// Spine 3 rotation
+    angle: Option<f32>,
+    x: Option<f32>,
+    y: Option<f32>,
+    /// `"stepped"`, or bezier control points that are played back as linear
+    curve: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct JsonSlotTimelines {
+    #[serde(default)]
+    attachment: Vec<JsonAttachmentKey>,
+}
+
+#[derive(Deserialize)]
+struct JsonAttachmentKey {
+    #[serde(default)]
+    time: f32,
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JsonDrawOrderKey {
+    #[serde(default)]
+    time: f32,
+    offsets: Option<Vec<JsonDrawOrderOffset>>,
+}
+
+#[derive(Deserialize)]
+struct JsonDrawOrderOffset {
+    slot: String,
+    offset: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SKELETON: &str = r#"{
+        "bones": [
+            { "name": "root", "x": 10 },
+            { "name": "arm", "parent": "root", "x": 5, "rotation": 90 },
+            { "name": "hand", "parent": "arm", "x": 2, "scaleX": 2 }
+        ],
+        "slots": [
+            { "name": "body", "bone": "root", "attachment": "body" },
+            { "name": "arm", "bone": "arm", "color": "ff000080" },
+            { "name": "hand", "bone": "hand" }
+        ],
+        "animations": {
+            "wave": {
+                "bones": {
+                    "arm": {
+                        "rotate": [{ "time": 0, "value": 0 }, { "time": 1, "value": 90 }],
+                        "translate": [
+                            { "time": 0, "x": 0, "y": 0, "curve": "stepped" },
+                            { "time": 1, "x": 10, "y": 4 }
+                        ]
+                    }
+                },
+                "drawOrder": [
+                    { "time": 0.5, "offsets": [{ "slot": "body", "offset": 2 }] },
+                    { "time": 2 }
+                ]
+            }
+        }
+    }"#;
+
+    fn assert_near(actual: [f32; 2], expected: [f32; 2]) {
+        assert!(
+            (actual[0] - expected[0]).abs() < 1e-4 && (actual[1] - expected[1]).abs() < 1e-4,
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    fn draw_order_json(offsets: &str) -> String {
+        format!(
+            r#"{{
+                "bones": [{{ "name": "root" }}],
+                "slots": [
+                    {{ "name": "a", "bone": "root" }},
+                    {{ "name": "b", "bone": "root" }},
+                    {{ "name": "c", "bone": "root" }}
+                ],
+                "animations": {{ "shuffle": {{ "drawOrder": [{{ "offsets": [{offsets}] }}] }} }}
+            }}"#
+        )
+    }
+
+    #[test]
+    fn parents_are_resolved() {
+        let data = SkeletonData::from_json(SKELETON).unwrap();
+        let parents: Vec<_> = data.bones.iter().map(|bone| bone.parent).collect();
+        assert_eq!(parents, [None, Some(0), Some(1)]);
+        assert_eq!(data.slots[1].bone, 1);
+        assert_eq!(data.slots[1].color, [255, 0, 0, 128]);
+
+        let skeleton = Skeleton::new(Arc::new(data));
+        let hand = skeleton.bone_transform("hand").unwrap();
+        assert_near(hand.apply([0.0, 0.0]), [15.0, 2.0]);
+        // Scaled by the hand and rotated by the arm
+        assert_near(hand.apply([1.0, 0.0]), [15.0, 4.0]);
+    }
+
+    #[test]
+    fn parent_must_come_first() {
+        let json = r#"{
+            "bones": [
+                { "name": "child", "parent": "root" },
+                { "name": "root" }
+            ]
+        }"#;
+        assert!(matches!(
+            SkeletonData::from_json(json),
+            Err(SkeletonError::UnknownBone(name)) if name == "root"
+        ));
+    }
+
+    #[test]
+    fn keys_are_interpolated() {
+        let data = Arc::new(SkeletonData::from_json(SKELETON).unwrap());
+        let animation = &data.animations["wave"];
+        assert_eq!(animation.duration, 2.0);
+
+        let mut skeleton = Skeleton::new(Arc::clone(&data));
+        skeleton.apply(animation, 0.5, false);
+        let arm = *skeleton.bone_pose_mut("arm").unwrap();
+        assert_eq!(arm.rotation, 90.0 + 45.0);
+        // Stepped keys hold until the next key
+        assert_eq!([arm.x, arm.y], [5.0, 0.0]);
+
+        skeleton.apply(animation, 1.5, false);
+        let arm = *skeleton.bone_pose_mut("arm").unwrap();
+        assert_eq!(arm.rotation, 90.0 + 90.0);
+        assert_eq!([arm.x, arm.y], [15.0, 4.0]);
+
+        skeleton.apply(animation, 2.5, true);
+        assert_eq!(skeleton.bone_pose_mut("arm").unwrap().rotation, 90.0 + 45.0);
+    }
+
+    #[test]
+    fn draw_order_keys() {
+        let data = Arc::new(SkeletonData::from_json(SKELETON).unwrap());
+        let animation = &data.animations["wave"];
+        assert_eq!(
+            animation.draw_order,
+            [(0.5, Some(vec![1, 2, 0])), (2.0, None)]
+        );
+
+        let mut skeleton = Skeleton::new(Arc::clone(&data));
+        skeleton.apply(animation, 0.25, false);
+        assert_eq!(skeleton.draw_order, [0, 1, 2]);
+        skeleton.apply(animation, 0.75, false);
+        assert_eq!(skeleton.draw_order, [1, 2, 0]);
+        skeleton.apply(animation, 2.0, false);
+        assert_eq!(skeleton.draw_order, [0, 1, 2]);
+    }
+
+    #[test]
+    fn draw_order_offsets_move_slots() {
+        let json =
+            draw_order_json(r#"{ "slot": "c", "offset": -2 }, { "slot": "a", "offset": 1 }"#);
+        let data = SkeletonData::from_json(&json).unwrap();
+        assert_eq!(
+            data.animations["shuffle"].draw_order,
+            [(0.0, Some(vec![2, 0, 1]))]
+        );
+    }
+
+    #[test]
+    fn invalid_draw_order_offsets() {
+        for offsets in [
+            // Out of range
+            r#"{ "slot": "a", "offset": -1 }"#,
+            r#"{ "slot": "c", "offset": 1 }"#,
+            // Both to the last place
+            r#"{ "slot": "a", "offset": 2 }, { "slot": "c", "offset": 0 }"#,
+            // The same slot twice
+            r#"{ "slot": "b", "offset": 1 }, { "slot": "b", "offset": -1 }"#,
+        ] {
+            assert!(
+                matches!(
+                    SkeletonData::from_json(&draw_order_json(offsets)),
+                    Err(SkeletonError::InvalidDrawOrder)
+                ),
+                "{offsets}"
+            );
+        }
+    }
+}
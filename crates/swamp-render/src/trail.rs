@@ -0,0 +1,316 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+use crate::particles::Curve;
+use int_math::URect;
+use std::collections::VecDeque;
+use swamp_wgpu_sprites::{BlendMode, SpriteVertex};
+
+/// Two vertices per point must fit in `u16` indices.
+const MAX_MESH_POINTS: usize = u16::MAX as usize / 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrailSettings {
+    /// Seconds until a point is removed
+    pub lifetime: f32,
+    /// In pixels. Closer positions move the newest point instead of adding one.
+    pub min_distance: f32,
+    pub max_points: usize,
+    /// In pixels, from the newest point (0.0) to the oldest (1.0)
+    pub width: Curve<f32>,
+    /// Straight alpha sRGB, from the newest point (0.0) to the oldest (1.0)
+    pub color: Curve<[u8; 4]>,
+    /// Atlas rect that is stretched along the trail, with `u` running from the newest point.
+    /// `None` uses the whole texture.
+    pub frame: Option<URect>,
+    pub blend: BlendMode,
+}
+
+impl Default for TrailSettings {
+    fn default() -> Self {
+        Self {
+            lifetime: 0.3,
+            min_distance: 4.0,
+            max_points: 64,
+            width: Curve::linear(8.0, 0.0),
+            color: Curve::linear([255, 255, 255, 255], [255, 255, 255, 0]),
+            frame: None,
+            blend: BlendMode::Alpha,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TrailPoint {
+    /// In pixels, like sprite positions
+    pub position: [f32; 2],
+    /// Seconds since the point was added
+    pub age: f32,
+}
+
+/// The recent positions of something that moves, e.g. a sword tip or a projectile, drawn
+/// as a textured strip with [`crate::Render::render_trail`].
+#[derive(Debug, Clone)]
+pub struct Trail {
+    pub settings: TrailSettings,
+    /// Newest first
+    points: VecDeque<TrailPoint>,
+}
+
+impl Trail {
+    pub fn new(settings: TrailSettings) -> Self {
+        Self {
+            settings,
+            points: VecDeque::new(),
+        }
+    }
+
+    /// Newest first.
+    pub fn points(&self) -> impl Iterator<Item = &TrailPoint> {
+        self.points.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// Call with the current position every frame, also when standing still.
+    pub fn push(&mut self, position: [f32; 2]) {
+        // The newest point follows the position until it is far enough away from the point
+        // before it
+        if let Some(previous) = self.points.get(1) {
+            if distance(previous.position, position) < self.settings.min_distance {
+                self.points[0] = TrailPoint { position, age: 0.0 };
+                return;
+            }
+        }
+
+        self.points.push_front(TrailPoint { position, age: 0.0 });
+        self.points.truncate(self.settings.max_points.max(2));
+    }
+
+    pub fn update(&mut self, delta_seconds: f32) {
+        for point in &mut self.points {
+            point.age += delta_seconds;
+        }
+        let lifetime = self.settings.lifetime;
+        while self
+            .points
+            .back()
+            .is_some_and(|point| point.age >= lifetime)
+        {
+            self.points.pop_back();
+        }
+    }
+
+    /// Length in pixels along all points.
+    pub fn length(&self) -> f32 {
+        self.points
+            .iter()
+            .zip(self.points.iter().skip(1))
+            .map(|(a, b)| distance(a.position, b.position))
+            .sum()
+    }
+
+    /// Appends a strip with two vertices per point, the indices count from the start of
+    /// `vertices`. `tex_rect` is the offset in `xy` and the size in `zw` of the frame,
+    /// in 0.0 to 1.0 texture coordinates.
+    pub fn build_mesh(
+        &self,
+        tex_rect: [f32; 4],
+        vertices: &mut Vec<SpriteVertex>,
+        indices: &mut Vec<u16>,
+    ) {
+        let count = self.points.len().min(MAX_MESH_POINTS);
+        if count < 2 {
+            return;
+        }
+
+        let first_vertex = vertices.len();
+        let total_length = self.length().max(f32::EPSILON);
+        let mut travelled = 0.0;
+        let mut normal = [0.0, 1.0];
+
+        for index in 0..count {
+            let position = self.points[index].position;
+            if index > 0 {
+                travelled += distance(self.points[index - 1].position, position);
+            }
+
+            let before = self.points[index.saturating_sub(1)].position;
+            let after = self.points[(index + 1).min(count - 1)].position;
+            let tangent = [after[0] - before[0], after[1] - before[1]];
+            let tangent_length = (tangent[0] * tangent[0] + tangent[1] * tangent[1]).sqrt();
+            // Points on top of each other keep the previous direction
+            if tangent_length > 0.0 {
+                normal = [-tangent[1] / tangent_length, tangent[0] / tangent_length];
+            }
+
+            let t = travelled / total_length;
+            let half_width = self.settings.width.sample(t) / 2.0;
+            let color = self.settings.color.sample(t);
+            let u = tex_rect[0] + t * tex_rect[2];
+
+            vertices.push(SpriteVertex {
+                position: [
+                    position[0] + normal[0] * half_width,
+                    position[1] + normal[1] * half_width,
+                ],
+                tex_coords: [u, tex_rect[1]],
+                color,
+            });
+            vertices.push(SpriteVertex {
+                position: [
+                    position[0] - normal[0] * half_width,
+                    position[1] - normal[1] * half_width,
+                ],
+                tex_coords: [u, tex_rect[1] + tex_rect[3]],
+                color,
+            });
+        }
+
+        let first = first_vertex as u16;
+        for segment in 0..count as u16 - 1 {
+            let left = first + segment * 2;
+            indices.extend_from_slice(&[left, left + 1, left + 2, left + 1, left + 3, left + 2]);
+        }
+    }
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trail(min_distance: f32, max_points: usize) -> Trail {
+        Trail::new(TrailSettings {
+            lifetime: 1.0,
+            min_distance,
+            max_points,
+            width: Curve::constant(2.0),
+            color: Curve::linear([255, 255, 255, 255], [255, 255, 255, 0]),
+            ..TrailSettings::default()
+        })
+    }
+
+    fn positions(trail: &Trail) -> Vec<[f32; 2]> {
+        trail.points().map(|point| point.position).collect()
+    }
+
+    #[test]
+    fn close_positions_move_the_newest_point() {
+        let mut trail = trail(4.0, 64);
+        trail.push([0.0, 0.0]);
+        trail.push([1.0, 0.0]);
+        trail.push([2.0, 0.0]);
+        assert_eq!(positions(&trail), [[2.0, 0.0], [0.0, 0.0]]);
+
+        trail.push([5.0, 0.0]);
+        assert_eq!(positions(&trail), [[5.0, 0.0], [2.0, 0.0], [0.0, 0.0]]);
+    }
+
+    #[test]
+    fn oldest_points_are_dropped_after_max_points() {
+        let mut trail = trail(0.0, 3);
+        for x in 0..5 {
+            trail.push([x as f32 * 10.0, 0.0]);
+        }
+
+        assert_eq!(positions(&trail), [[40.0, 0.0], [30.0, 0.0], [20.0, 0.0]]);
+    }
+
+    #[test]
+    fn points_expire_after_the_lifetime() {
+        let mut trail = trail(0.0, 64);
+        trail.push([0.0, 0.0]);
+        trail.update(0.6);
+        trail.push([10.0, 0.0]);
+        trail.update(0.3);
+        assert_eq!(trail.points().count(), 2);
+
+        trail.update(0.1);
+        assert_eq!(positions(&trail), [[10.0, 0.0]]);
+        trail.update(0.6);
+        assert!(trail.is_empty());
+    }
+
+    #[test]
+    fn mesh_has_two_vertices_per_point() {
+        let mut trail = trail(0.0, 64);
+        for position in [[0.0, 0.0], [10.0, 0.0], [30.0, 0.0]] {
+            trail.push(position);
+        }
+        let mut vertices = vec![SpriteVertex {
+            position: [0.0, 0.0],
+            tex_coords: [0.0, 0.0],
+            color: [0; 4],
+        }];
+        let mut indices = Vec::new();
+
+        trail.build_mesh([0.25, 0.5, 0.5, 0.25], &mut vertices, &mut indices);
+
+        assert_eq!(vertices.len(), 1 + 6);
+        assert_eq!(indices, [1, 2, 3, 2, 4, 3, 3, 4, 5, 4, 6, 5]);
+        let strip = &vertices[1..];
+        // Newest first, 20 of the 30 pixels from [30, 0] to [10, 0]
+        let u: Vec<f32> = strip.iter().map(|vertex| vertex.tex_coords[0]).collect();
+        assert_eq!(
+            u,
+            [
+                0.25,
+                0.25,
+                0.25 + 0.5 * 2.0 / 3.0,
+                0.25 + 0.5 * 2.0 / 3.0,
+                0.75,
+                0.75
+            ]
+        );
+        for pair in strip.chunks_exact(2) {
+            assert_eq!(pair[0].tex_coords[1], 0.5);
+            assert_eq!(pair[1].tex_coords[1], 0.75);
+            assert_eq!((pair[0].position[1] - pair[1].position[1]).abs(), 2.0);
+            assert_eq!(pair[0].position[0], pair[1].position[0]);
+        }
+        assert_eq!(strip[0].color[3], 255);
+        assert_eq!(strip[5].color[3], 0);
+    }
+
+    #[test]
+    fn single_point_has_no_mesh() {
+        let mut trail = trail(0.0, 64);
+        trail.push([0.0, 0.0]);
+        let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+
+        trail.build_mesh([0.0, 0.0, 1.0, 1.0], &mut vertices, &mut indices);
+
+        assert!(vertices.is_empty());
+        assert!(indices.is_empty());
+    }
+
+    #[test]
+    fn mesh_points_are_capped_to_fit_the_indices() {
+        let mut trail = trail(0.0, MAX_MESH_POINTS + 10);
+        for x in 0..MAX_MESH_POINTS + 10 {
+            trail.push([x as f32, 0.0]);
+        }
+        let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+
+        trail.build_mesh([0.0, 0.0, 1.0, 1.0], &mut vertices, &mut indices);
+
+        assert_eq!(vertices.len(), MAX_MESH_POINTS * 2);
+        assert_eq!(indices.len(), (MAX_MESH_POINTS - 1) * 6);
+        assert_eq!(
+            indices.iter().max(),
+            Some(&(MAX_MESH_POINTS as u16 * 2 - 1))
+        );
+    }
+}
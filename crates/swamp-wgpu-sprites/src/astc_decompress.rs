@@ -0,0 +1,641 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+//! CPU decoding of 2D LDR ASTC blocks, as described in the Khronos Data Format Specification.
+
+use crate::block_decompress::{field, BitReader};
+
+/// Texels of invalid blocks, and of partitions with HDR endpoints, get the error color.
+const ERROR_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+/// `(trits, quints, bits)` of each integer sequence range, from 2 to 256 levels.
+const RANGES: [(u32, u32, u32); 21] = [
+    (0, 0, 1),
+    (1, 0, 0),
+    (0, 0, 2),
+    (0, 1, 0),
+    (1, 0, 1),
+    (0, 0, 3),
+    (0, 1, 1),
+    (1, 0, 2),
+    (0, 0, 4),
+    (0, 1, 2),
+    (1, 0, 3),
+    (0, 0, 5),
+    (0, 1, 3),
+    (1, 0, 4),
+    (0, 0, 6),
+    (0, 1, 4),
+    (1, 0, 5),
+    (0, 0, 7),
+    (0, 1, 5),
+    (1, 0, 6),
+    (0, 0, 8),
+];
+
+/// Color endpoints need at least six levels.
+const MIN_COLOR_RANGE: usize = 4;
+
+/// Decodes one block to pixels, stored row major as `pixels[y * block_width + x]`.
+pub fn decode_block(
+    block: &[u8],
+    block_width: u32,
+    block_height: u32,
+    srgb: bool,
+    pixels: &mut [[u8; 4]],
+) {
+    let bits = u128::from_le_bytes(block.try_into().unwrap());
+    if decode(bits, block_width, block_height, srgb, pixels).is_none() {
+        pixels.fill(ERROR_COLOR);
+    }
+}
+
+fn decode(
+    bits: u128,
+    block_width: u32,
+    block_height: u32,
+    srgb: bool,
+    pixels: &mut [[u8; 4]],
+) -> Option<()> {
+    let block_mode = field(bits, 0, 11);
+    if block_mode & 0x1FF == 0x1FC {
+        return decode_void_extent(bits, srgb, pixels);
+    }
+
+    let mode = BlockMode::decode(block_mode)?;
+    if mode.grid_width > block_width || mode.grid_height > block_height {
+        return None;
+    }
+    let weight_count = mode.grid_width * mode.grid_height * if mode.dual_plane { 2 } else { 1 };
+    let weight_bits = sequence_bit_count(weight_count, mode.weight_range);
+    if weight_count > 64 || !(24..=96).contains(&weight_bits) {
+        return None;
+    }
+
+    let partition_count = field(bits, 11, 2) as usize + 1;
+    if mode.dual_plane && partition_count == 4 {
+        return None;
+    }
+
+    let mut below_weights = 128 - weight_bits;
+    let mut endpoint_modes = [0u32; 4];
+    let (partition_index, color_start) = if partition_count == 1 {
+        endpoint_modes[0] = field(bits, 13, 4);
+        (0, 17)
+    } else {
+        let low = field(bits, 23, 6);
+        if low & 3 == 0 {
+            endpoint_modes = [field(bits, 25, 4); 4];
+        } else {
+            let extra_bits = 3 * partition_count as u32 - 4;
+            below_weights = below_weights.checked_sub(extra_bits)?;
+            let encoded = low | (field(bits, below_weights, extra_bits) << 6);
+            let class = (encoded & 3) - 1;
+            for (i, endpoint_mode) in endpoint_modes[..partition_count].iter_mut().enumerate() {
+                let class_offset = (encoded >> (2 + i)) & 1;
+                let low_mode = (encoded >> (2 + partition_count + i * 2)) & 3;
+                *endpoint_mode = ((class + class_offset) << 2) | low_mode;
+            }
+        }
+        (field(bits, 13, 10), 29)
+    };
+
+    let plane2_component = if mode.dual_plane {
+        below_weights = below_weights.checked_sub(2)?;
+        Some(field(bits, below_weights, 2) as usize)
+    } else {
+        None
+    };
+
+    let color_value_count: u32 = endpoint_modes[..partition_count]
+        .iter()
+        .map(|mode| ((mode >> 2) + 1) * 2)
+        .sum();
+    if color_value_count > 18 {
+        return None;
+    }
+    let color_bits = below_weights.checked_sub(color_start)?;
+    let color_range = (MIN_COLOR_RANGE..RANGES.len())
+        .rev()
+        .find(|range| sequence_bit_count(color_value_count, *range) <= color_bits)?;
+    let color_values: Vec<i32> =
+        decode_sequence(bits >> color_start, color_range, color_value_count)
+            .into_iter()
+            .map(|value| unquantize_color(value, color_range))
+            .collect();
+
+    let mut endpoints = [None; 4];
+    let mut values = color_values.as_slice();
+    for (endpoint, mode) in endpoints.iter_mut().zip(&endpoint_modes[..partition_count]) {
+        let (used, rest) = values.split_at(((mode >> 2) as usize + 1) * 2);
+        *endpoint = decode_endpoints(*mode, used);
+        values = rest;
+    }
+
+    let weights: Vec<u32> = decode_sequence(bits.reverse_bits(), mode.weight_range, weight_count)
+        .into_iter()
+        .map(|value| unquantize_weight(value, mode.weight_range))
+        .collect();
+
+    let small_block = block_width * block_height < 31;
+    for y in 0..block_height {
+        for x in 0..block_width {
+            let partition = if partition_count == 1 {
+                0
+            } else {
+                select_partition(partition_index, x, y, partition_count, small_block)
+            };
+            let pixel = &mut pixels[(y * block_width + x) as usize];
+            let Some([e0, e1]) = endpoints[partition] else {
+                *pixel = ERROR_COLOR;
+                continue;
+            };
+            let plane_weights = infill_weights(&mode, &weights, block_width, block_height, x, y);
+            for (channel, value) in pixel.iter_mut().enumerate() {
+                let plane = usize::from(plane2_component == Some(channel));
+                *value = interpolate(
+                    e0[channel],
+                    e1[channel],
+                    plane_weights[plane],
+                    srgb && channel < 3,
+                );
+            }
+        }
+    }
+
+    Some(())
+}
+
+/// A block with a single color.
+fn decode_void_extent(bits: u128, srgb: bool, pixels: &mut [[u8; 4]]) -> Option<()> {
+    let hdr = field(bits, 9, 1) == 1;
+    if hdr || field(bits, 10, 2) != 3 {
+        return None;
+    }
+    let s_min = field(bits, 12, 13);
+    let s_max = field(bits, 25, 13);
+    let t_min = field(bits, 38, 13);
+    let t_max = field(bits, 51, 13);
+    let all_ones = [s_min, s_max, t_min, t_max].iter().all(|c| *c == 0x1FFF);
+    if !all_ones && (s_min >= s_max || t_min >= t_max) {
+        return None;
+    }
+
+    let color: [u8; 4] = std::array::from_fn(|channel| {
+        let value = field(bits, 64 + channel as u32 * 16, 16);
+        if srgb && channel < 3 {
+            (value >> 8) as u8
+        } else {
+            unorm16_to_unorm8(value)
+        }
+    });
+    pixels.fill(color);
+    Some(())
+}
+
+struct BlockMode {
+    grid_width: u32,
+    grid_height: u32,
+    dual_plane: bool,
+    /// Index into [`RANGES`]
+    weight_range: usize,
+}
+
+impl BlockMode {
+    fn decode(mode: u32) -> Option<Self> {
+        let a = (mode >> 5) & 3;
+        let mut high_precision = (mode >> 9) & 1 == 1;
+        let mut dual_plane = (mode >> 10) & 1 == 1;
+        let mut range = (mode >> 4) & 1;
+
+        let (grid_width, grid_height) = if mode & 3 != 0 {
+            range |= (mode & 3) << 1;
+            let b = (mode >> 7) & 3;
+            match (mode >> 2) & 3 {
+                0 => (b + 4, a + 2),
+                1 => (b + 8, a + 2),
+                2 => (a + 2, b + 8),
+                _ if mode & 0x100 != 0 => ((b & 1) + 2, a + 2),
+                _ => (a + 2, (b & 1) + 6),
+            }
+        } else {
+            range |= ((mode >> 2) & 3) << 1;
+            if (mode >> 2) & 3 == 0 {
+                return None;
+            }
+            let b = (mode >> 9) & 3;
+            match (mode >> 7) & 3 {
+                0 => (12, a + 2),
+                1 => (a + 2, 12),
+                2 => {
+                    high_precision = false;
+                    dual_plane = false;
+                    (a + 6, b + 6)
+                }
+                _ => match a {
+                    0 => (6, 10),
+                    1 => (10, 6),
+                    _ => return None,
+                },
+            }
+        };
+
+        Some(Self {
+            grid_width,
+            grid_height,
+            dual_plane,
+            weight_range: (range - 2 + if high_precision { 6 } else { 0 }) as usize,
+        })
+    }
+}
+
+fn sequence_bit_count(count: u32, range: usize) -> u32 {
+    let (trits, quints, bits) = RANGES[range];
+    count * bits + trits * (count * 8).div_ceil(5) + quints * (count * 7).div_ceil(3)
+}
+
+/// Decodes `count` values of the bounded integer sequence that starts at the lowest bit.
+fn decode_sequence(bits: u128, range: usize, count: u32) -> Vec<u32> {
+    let (trits, quints, bit_count) = RANGES[range];
+    let mut reader = BitReader::new(bits);
+    let mut values = Vec::with_capacity(count as usize);
+
+    if trits == 1 {
+        // Five values share eight bits of trits, interleaved with the values
+        const TRIT_BITS: [u32; 5] = [2, 2, 1, 2, 1];
+        while values.len() < count as usize {
+            let in_block = (count as usize - values.len()).min(5);
+            let mut low = [0u32; 5];
+            let mut packed = 0;
+            let mut shift = 0;
+            for i in 0..in_block {
+                low[i] = reader.read(bit_count);
+                packed |= reader.read(TRIT_BITS[i]) << shift;
+                shift += TRIT_BITS[i];
+            }
+            let decoded = decode_trits(packed);
+            values.extend((0..in_block).map(|i| (decoded[i] << bit_count) | low[i]));
+        }
+    } else if quints == 1 {
+        // Three values share seven bits of quints
+        const QUINT_BITS: [u32; 3] = [3, 2, 2];
+        while values.len() < count as usize {
+            let in_block = (count as usize - values.len()).min(3);
+            let mut low = [0u32; 3];
+            let mut packed = 0;
+            let mut shift = 0;
+            for i in 0..in_block {
+                low[i] = reader.read(bit_count);
+                packed |= reader.read(QUINT_BITS[i]) << shift;
+                shift += QUINT_BITS[i];
+            }
+            let decoded = decode_quints(packed);
+            values.extend((0..in_block).map(|i| (decoded[i] << bit_count) | low[i]));
+        }
+    } else {
+        values.extend((0..count).map(|_| reader.read(bit_count)));
+    }
+
+    values
+}
+
+fn bit(value: u32, index: u32) -> u32 {
+    (value >> index) & 1
+}
+
+fn decode_trits(packed: u32) -> [u32; 5] {
+    let t = packed;
+    let (c, t3, t4) = if (t >> 2) & 7 == 7 {
+        (((t >> 5) & 7) << 2 | (t & 3), 2, 2)
+    } else {
+        let c = t & 0x1F;
+        if (t >> 5) & 3 == 3 {
+            (c, bit(t, 7), 2)
+        } else {
+            (c, (t >> 5) & 3, bit(t, 7))
+        }
+    };
+    let (t0, t1, t2) = if c & 3 == 3 {
+        (
+            (bit(c, 3) << 1) | (bit(c, 2) & !bit(c, 3) & 1),
+            bit(c, 4),
+            2,
+        )
+    } else if (c >> 2) & 3 == 3 {
+        (c & 3, 2, 2)
+    } else {
+        (
+            (bit(c, 1) << 1) | (bit(c, 0) & !bit(c, 1) & 1),
+            (c >> 2) & 3,
+            bit(c, 4),
+        )
+    };
+    [t0, t1, t2, t3, t4]
+}
+
+fn decode_quints(packed: u32) -> [u32; 3] {
+    let q = packed;
+    if (q >> 1) & 3 == 3 && (q >> 5) & 3 == 0 {
+        let not_q0 = !q & 1;
+        let q2 = (bit(q, 0) << 2) | ((bit(q, 4) & not_q0) << 1) | (bit(q, 3) & not_q0);
+        return [4, 4, q2];
+    }
+    let (c, q2) = if (q >> 1) & 3 == 3 {
+        (((q >> 3) & 3) << 3 | ((!q >> 5) & 3) << 1 | (q & 1), 4)
+    } else {
+        (q & 0x1F, (q >> 5) & 3)
+    };
+    let (q0, q1) = if c & 7 == 5 {
+        ((c >> 3) & 3, 4)
+    } else {
+        (c & 7, (c >> 3) & 3)
+    };
+    [q0, q1, q2]
+}
+
+/// Scales a color endpoint value to 0..=255.
+fn unquantize_color(value: u32, range: usize) -> i32 {
+    let (trits, quints, bits) = RANGES[range];
+    if trits == 0 && quints == 0 {
+        return replicate(value, bits, 8) as i32;
+    }
+
+    let d = value >> bits;
+    let low = value & ((1 << bits) - 1);
+    let a = if low & 1 == 1 { 0x1FF } else { 0 };
+    let b_bits = low >> 1;
+    let (b, c) = if trits == 1 {
+        match bits {
+            1 => (0, 204),
+            2 => (
+                (b_bits << 8) | (b_bits << 4) | (b_bits << 2) | (b_bits << 1),
+                93,
+            ),
+            3 => ((b_bits << 7) | (b_bits << 2) | b_bits, 44),
+            4 => ((b_bits << 6) | b_bits, 22),
+            5 => ((b_bits << 5) | (b_bits >> 2), 11),
+            _ => ((b_bits << 4) | (b_bits >> 4), 5),
+        }
+    } else {
+        match bits {
+            1 => (0, 113),
+            2 => ((b_bits << 8) | (b_bits << 3) | (b_bits << 2), 54),
+            3 => ((b_bits << 7) | (b_bits << 1) | (b_bits >> 1), 26),
+            4 => ((b_bits << 6) | (b_bits >> 1), 13),
+            _ => ((b_bits << 5) | (b_bits >> 3), 6),
+        }
+    };
+    let t = (d * c + b) ^ a;
+    ((a & 0x80) | (t >> 2)) as i32
+}
+
+/// Scales a weight to 0..=64.
+fn unquantize_weight(value: u32, range: usize) -> u32 {
+    let (trits, quints, bits) = RANGES[range];
+    let unquantized = if trits == 0 && quints == 0 {
+        replicate(value, bits, 6)
+    } else if bits == 0 {
+        if trits == 1 {
+            [0, 32, 63][value as usize]
+        } else {
+            [0, 16, 32, 47, 63][value as usize]
+        }
+    } else {
+        let d = value >> bits;
+        let low = value & ((1 << bits) - 1);
+        let a = if low & 1 == 1 { 0x7F } else { 0 };
+        let b_bits = low >> 1;
+        let (b, c) = match (trits, bits) {
+            (1, 1) => (0, 50),
+            (1, 2) => ((b_bits << 6) | (b_bits << 2) | b_bits, 23),
+            (1, _) => ((b_bits << 5) | b_bits, 11),
+            (_, 1) => (0, 28),
+            _ => ((b_bits << 6) | (b_bits << 1), 13),
+        };
+        let t = (d * c + b) ^ a;
+        (a & 0x20) | (t >> 2)
+    };
+    if unquantized > 32 {
+        unquantized + 1
+    } else {
+        unquantized
+    }
+}
+
+/// Repeats the bits of `value` until they fill `target_bits`.
+fn replicate(value: u32, bits: u32, target_bits: u32) -> u32 {
+    let mut result = 0;
+    let mut filled = 0;
+    while filled < target_bits {
+        let shift = target_bits as i32 - filled as i32 - bits as i32;
+        result |= if shift >= 0 {
+            value << shift
+        } else {
+            value >> -shift
+        };
+        filled += bits;
+    }
+    result
+}
+
+/// Splits `a` into a signed offset and moves its top bit to `b`.
+fn bit_transfer_signed(a: i32, b: i32) -> (i32, i32) {
+    let b = (b >> 1) | (a & 0x80);
+    let a = (a >> 1) & 0x3F;
+    let a = if a & 0x20 != 0 { a - 0x40 } else { a };
+    (a, b)
+}
+
+fn blue_contract(color: [i32; 4]) -> [i32; 4] {
+    [
+        (color[0] + color[2]) >> 1,
+        (color[1] + color[2]) >> 1,
+        color[2],
+        color[3],
+    ]
+}
+
+/// Endpoints of an LDR color endpoint mode. HDR modes are invalid in LDR textures.
+fn decode_endpoints(mode: u32, v: &[i32]) -> Option<[[i32; 4]; 2]> {
+    let endpoints = match mode {
+        0 => [[v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]],
+        1 => {
+            let l0 = (v[0] >> 2) | (v[1] & 0xC0);
+            let l1 = (l0 + (v[1] & 0x3F)).min(255);
+            [[l0, l0, l0, 255], [l1, l1, l1, 255]]
+        }
+        4 => [[v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]],
+        5 => {
+            let (offset, base) = bit_transfer_signed(v[1], v[0]);
+            let (alpha_offset, alpha) = bit_transfer_signed(v[3], v[2]);
+            let l1 = base + offset;
+            [
+                [base, base, base, alpha],
+                [l1, l1, l1, alpha + alpha_offset],
+            ]
+        }
+        6 | 10 => {
+            let scale = |c: i32| (c * v[3]) >> 8;
+            let (a0, a1) = if mode == 10 { (v[4], v[5]) } else { (255, 255) };
+            [
+                [scale(v[0]), scale(v[1]), scale(v[2]), a0],
+                [v[0], v[1], v[2], a1],
+            ]
+        }
+        8 | 12 => {
+            let (a0, a1) = if mode == 12 { (v[6], v[7]) } else { (255, 255) };
+            let e0 = [v[0], v[2], v[4], a0];
+            let e1 = [v[1], v[3], v[5], a1];
+            if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+                [e0, e1]
+            } else {
+                [blue_contract(e1), blue_contract(e0)]
+            }
+        }
+        9 | 13 => {
+            let (r_offset, r) = bit_transfer_signed(v[1], v[0]);
+            let (g_offset, g) = bit_transfer_signed(v[3], v[2]);
+            let (b_offset, b) = bit_transfer_signed(v[5], v[4]);
+            let (a_offset, a) = if mode == 13 {
+                bit_transfer_signed(v[7], v[6])
+            } else {
+                (0, 255)
+            };
+            let base = [r, g, b, a];
+            let offset = [r + r_offset, g + g_offset, b + b_offset, a + a_offset];
+            if r_offset + g_offset + b_offset >= 0 {
+                [base, offset]
+            } else {
+                [blue_contract(offset), blue_contract(base)]
+            }
+        }
+        _ => return None,
+    };
+
+    Some(endpoints.map(|endpoint| endpoint.map(|c| c.clamp(0, 255))))
+}
+
+/// Selects the partition of a texel, with the hash from the specification.
+fn select_partition(seed: u32, x: u32, y: u32, partition_count: usize, small_block: bool) -> usize {
+    let (x, y) = if small_block {
+        (x << 1, y << 1)
+    } else {
+        (x, y)
+    };
+    let seed = seed + (partition_count as u32 - 1) * 1024;
+    let random = hash52(seed);
+
+    let mut seeds = [0u32; 8];
+    for (i, value) in seeds.iter_mut().enumerate() {
+        let nibble = (random >> (i * 4)) & 0xF;
+        *value = nibble * nibble;
+    }
+
+    let (shift1, shift2) = if seed & 1 == 1 {
+        (
+            if seed & 2 != 0 { 4 } else { 5 },
+            if partition_count == 3 { 6 } else { 5 },
+        )
+    } else {
+        (
+            if partition_count == 3 { 6 } else { 5 },
+            if seed & 2 != 0 { 4 } else { 5 },
+        )
+    };
+
+    let a = ((seeds[0] >> shift1) * x + (seeds[1] >> shift2) * y + (random >> 14)) & 0x3F;
+    let b = ((seeds[2] >> shift1) * x + (seeds[3] >> shift2) * y + (random >> 10)) & 0x3F;
+    let c = ((seeds[4] >> shift1) * x + (seeds[5] >> shift2) * y + (random >> 6)) & 0x3F;
+    let d = ((seeds[6] >> shift1) * x + (seeds[7] >> shift2) * y + (random >> 2)) & 0x3F;
+    let c = if partition_count < 3 { 0 } else { c };
+    let d = if partition_count < 4 { 0 } else { d };
+
+    if a >= b && a >= c && a >= d {
+        0
+    } else if b >= c && b >= d {
+        1
+    } else if c >= d {
+        2
+    } else {
+        3
+    }
+}
+
+fn hash52(input: u32) -> u32 {
+    let mut p = input;
+    p ^= p >> 15;
+    p = p.wrapping_sub(p << 17);
+    p = p.wrapping_add(p << 7);
+    p = p.wrapping_add(p << 4);
+    p ^= p >> 5;
+    p = p.wrapping_add(p << 16);
+    p ^= p >> 7;
+    p ^= p >> 3;
+    p ^= p << 6;
+    p ^= p >> 17;
+    p
+}
+
+/// Bilinearly samples the weight grid at a texel, for both planes.
+fn infill_weights(
+    mode: &BlockMode,
+    weights: &[u32],
+    block_width: u32,
+    block_height: u32,
+    x: u32,
+    y: u32,
+) -> [u32; 2] {
+    let ds = (1024 + block_width / 2) / (block_width - 1);
+    let dt = (1024 + block_height / 2) / (block_height - 1);
+    let gs = (ds * x * (mode.grid_width - 1) + 32) >> 6;
+    let gt = (dt * y * (mode.grid_height - 1) + 32) >> 6;
+    let (js, fs) = (gs >> 4, gs & 0xF);
+    let (jt, ft) = (gt >> 4, gt & 0xF);
+
+    let w11 = (fs * ft + 8) >> 4;
+    let w10 = ft - w11;
+    let w01 = fs - w11;
+    let w00 = 16 + w11 - fs - ft;
+
+    let planes = if mode.dual_plane { 2 } else { 1 };
+    let weight = |grid_x: u32, grid_y: u32, plane: usize| {
+        if grid_x >= mode.grid_width || grid_y >= mode.grid_height {
+            0
+        } else {
+            weights[(grid_y * mode.grid_width + grid_x) as usize * planes + plane]
+        }
+    };
+    let sample = |plane: usize| {
+        (weight(js, jt, plane) * w00
+            + weight(js + 1, jt, plane) * w01
+            + weight(js, jt + 1, plane) * w10
+            + weight(js + 1, jt + 1, plane) * w11
+            + 8)
+            >> 4
+    };
+
+    [sample(0), sample(planes - 1)]
+}
+
+fn unorm16_to_unorm8(value: u32) -> u8 {
+    ((value * 255 + 32767) / 65535) as u8
+}
+
+fn interpolate(e0: i32, e1: i32, weight: u32, srgb: bool) -> u8 {
+    let expand = |c: i32| {
+        let c = c as u32;
+        if srgb {
+            (c << 8) | 0x80
+        } else {
+            (c << 8) | c
+        }
+    };
+    let value = (expand(e0) * (64 - weight) + expand(e1) * weight + 32) >> 6;
+    if srgb {
+        (value >> 8) as u8
+    } else {
+        unorm16_to_unorm8(value)
+    }
+}
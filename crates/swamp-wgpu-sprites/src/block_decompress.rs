@@ -0,0 +1,1754 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+//! CPU decompression of block compressed textures, for devices that can not sample them.
+//!
+//! Decodes BC1 to BC7 (including BC4/BC5 SNORM and BC6H), ETC2 and LDR ASTC. ASTC HDR and EAC
+//! are not supported.
+
+use crate::astc_decompress;
+use wgpu::{AstcBlock, AstcChannel, TextureFormat};
+
+/// Decodes one block to pixels, stored row major as `pixels[y * block_width + x]`.
+type DecodeBlock = Box<dyn Fn(&[u8], &mut [[u8; 4]])>;
+
+/// Decompresses a block compressed mip level to RGBA8, in the octet layout of [`fallback_format`].
+///
+/// Returns `None` for formats that have no CPU decoder and when `blocks` is too short.
+pub fn decompress_to_rgba(
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    blocks: &[u8],
+) -> Option<Vec<u8>> {
+    let decode_block: DecodeBlock = match format {
+        TextureFormat::Bc1RgbaUnorm | TextureFormat::Bc1RgbaUnormSrgb => Box::new(decode_bc1),
+        TextureFormat::Bc2RgbaUnorm | TextureFormat::Bc2RgbaUnormSrgb => Box::new(decode_bc2),
+        TextureFormat::Bc3RgbaUnorm | TextureFormat::Bc3RgbaUnormSrgb => Box::new(decode_bc3),
+        TextureFormat::Bc4RUnorm => Box::new(decode_bc4),
+        TextureFormat::Bc4RSnorm => Box::new(decode_bc4_signed),
+        TextureFormat::Bc5RgUnorm => Box::new(decode_bc5),
+        TextureFormat::Bc5RgSnorm => Box::new(decode_bc5_signed),
+        TextureFormat::Bc6hRgbUfloat => Box::new(|block, pixels| decode_bc6h(block, false, pixels)),
+        TextureFormat::Bc6hRgbFloat => Box::new(|block, pixels| decode_bc6h(block, true, pixels)),
+        TextureFormat::Bc7RgbaUnorm | TextureFormat::Bc7RgbaUnormSrgb => Box::new(decode_bc7),
+        TextureFormat::Etc2Rgb8Unorm | TextureFormat::Etc2Rgb8UnormSrgb => {
+            Box::new(decode_etc2_rgb)
+        }
+        TextureFormat::Etc2Rgb8A1Unorm | TextureFormat::Etc2Rgb8A1UnormSrgb => {
+            Box::new(decode_etc2_rgb_a1)
+        }
+        TextureFormat::Etc2Rgba8Unorm | TextureFormat::Etc2Rgba8UnormSrgb => {
+            Box::new(decode_etc2_rgba)
+        }
+        TextureFormat::Astc {
+            block,
+            channel: channel @ (AstcChannel::Unorm | AstcChannel::UnormSrgb),
+        } => {
+            let (block_width, block_height) = astc_block_dimensions(block);
+            let srgb = channel == AstcChannel::UnormSrgb;
+            Box::new(move |block, pixels| {
+                astc_decompress::decode_block(block, block_width, block_height, srgb, pixels);
+            })
+        }
+        _ => return None,
+    };
+
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format.block_copy_size(None)? as usize;
+    let (block_width, block_height) = (block_width as usize, block_height as usize);
+    let blocks_x = (width as usize).div_ceil(block_width);
+    let blocks_y = (height as usize).div_ceil(block_height);
+    if blocks.len() < blocks_x * blocks_y * block_size {
+        return None;
+    }
+
+    let mut rgba = vec![0u8; width as usize * height as usize * 4];
+    let mut pixels = vec![[0u8; 4]; block_width * block_height];
+    for block_y in 0..blocks_y {
+        for block_x in 0..blocks_x {
+            let offset = (block_y * blocks_x + block_x) * block_size;
+            decode_block(&blocks[offset..offset + block_size], &mut pixels);
+
+            for y in 0..block_height {
+                let pixel_y = block_y * block_height + y;
+                if pixel_y >= height as usize {
+                    break;
+                }
+                for x in 0..block_width {
+                    let pixel_x = block_x * block_width + x;
+                    if pixel_x >= width as usize {
+                        break;
+                    }
+                    let target = (pixel_y * width as usize + pixel_x) * 4;
+                    rgba[target..target + 4].copy_from_slice(&pixels[y * block_width + x]);
+                }
+            }
+        }
+    }
+
+    Some(rgba)
+}
+
+/// The uncompressed format that [`decompress_to_rgba`] produces for a block compressed format.
+///
+/// BC6H is decoded to linear RGBA8, values outside of 0.0 to 1.0 are clamped.
+pub fn fallback_format(format: TextureFormat) -> TextureFormat {
+    match format {
+        TextureFormat::Bc4RSnorm | TextureFormat::Bc5RgSnorm => TextureFormat::Rgba8Snorm,
+        _ if format.is_srgb() => TextureFormat::Rgba8UnormSrgb,
+        _ => TextureFormat::Rgba8Unorm,
+    }
+}
+
+const fn astc_block_dimensions(block: AstcBlock) -> (u32, u32) {
+    match block {
+        AstcBlock::B4x4 => (4, 4),
+        AstcBlock::B5x4 => (5, 4),
+        AstcBlock::B5x5 => (5, 5),
+        AstcBlock::B6x5 => (6, 5),
+        AstcBlock::B6x6 => (6, 6),
+        AstcBlock::B8x5 => (8, 5),
+        AstcBlock::B8x6 => (8, 6),
+        AstcBlock::B8x8 => (8, 8),
+        AstcBlock::B10x5 => (10, 5),
+        AstcBlock::B10x6 => (10, 6),
+        AstcBlock::B10x8 => (10, 8),
+        AstcBlock::B10x10 => (10, 10),
+        AstcBlock::B12x10 => (12, 10),
+        AstcBlock::B12x12 => (12, 12),
+    }
+}
+
+/// Reads a block as a little endian bit stream, from the lowest bit.
+pub(crate) struct BitReader {
+    bits: u128,
+    position: u32,
+}
+
+impl BitReader {
+    pub(crate) fn new(bits: u128) -> Self {
+        Self { bits, position: 0 }
+    }
+
+    pub(crate) fn read(&mut self, count: u32) -> u32 {
+        let value = field(self.bits, self.position, count);
+        self.position += count;
+        value
+    }
+}
+
+/// `count` bits starting at bit `position`, zero above bit 127.
+pub(crate) fn field(bits: u128, position: u32, count: u32) -> u32 {
+    if count == 0 || position >= 128 {
+        return 0;
+    }
+    ((bits >> position) & ((1u128 << count) - 1)) as u32
+}
+
+fn expand_565(color: u16) -> [u8; 3] {
+    let r = ((color >> 11) & 0x1f) as u8;
+    let g = ((color >> 5) & 0x3f) as u8;
+    let b = (color & 0x1f) as u8;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    ]
+}
+
+fn mix(a: [u8; 3], b: [u8; 3], weight_a: u16, weight_b: u16) -> [u8; 3] {
+    let sum = weight_a + weight_b;
+    [0, 1, 2].map(|i| ((a[i] as u16 * weight_a + b[i] as u16 * weight_b) / sum) as u8)
+}
+
+/// The color part of BC1, BC2 and BC3. Only BC1 has the three color mode with transparency.
+fn decode_bc_color(block: &[u8], allow_transparent: bool, pixels: &mut [[u8; 4]]) {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+    let c0 = expand_565(color0);
+    let c1 = expand_565(color1);
+    let palette: [[u8; 4]; 4] = if color0 > color1 || !allow_transparent {
+        let c2 = mix(c0, c1, 2, 1);
+        let c3 = mix(c0, c1, 1, 2);
+        [
+            [c0[0], c0[1], c0[2], 255],
+            [c1[0], c1[1], c1[2], 255],
+            [c2[0], c2[1], c2[2], 255],
+            [c3[0], c3[1], c3[2], 255],
+        ]
+    } else {
+        let c2 = mix(c0, c1, 1, 1);
+        [
+            [c0[0], c0[1], c0[2], 255],
+            [c1[0], c1[1], c1[2], 255],
+            [c2[0], c2[1], c2[2], 255],
+            [0, 0, 0, 0],
+        ]
+    };
+
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = palette[((indices >> (i * 2)) & 0x3) as usize];
+    }
+}
+
+/// BC3 alpha and BC4/BC5 channels, eight or six interpolated values.
+fn decode_bc_channel(block: &[u8]) -> [u8; 16] {
+    let a0 = block[0] as u16;
+    let a1 = block[1] as u16;
+    let mut palette = [0u16; 8];
+    palette[0] = a0;
+    palette[1] = a1;
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as u16) * a0 + i as u16 * a1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as u16) * a0 + i as u16 * a1) / 5;
+        }
+        palette[6] = 0;
+        palette[7] = 255;
+    }
+
+    let mut bits = 0u64;
+    for (i, octet) in block[2..8].iter().enumerate() {
+        bits |= (*octet as u64) << (i * 8);
+    }
+
+    let mut values = [0u8; 16];
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = palette[((bits >> (i * 3)) & 0x7) as usize] as u8;
+    }
+    values
+}
+
+fn decode_bc1(block: &[u8], pixels: &mut [[u8; 4]]) {
+    decode_bc_color(block, true, pixels);
+}
+
+fn decode_bc2(block: &[u8], pixels: &mut [[u8; 4]]) {
+    decode_bc_color(&block[8..16], false, pixels);
+    let alpha = u64::from_le_bytes(block[0..8].try_into().unwrap());
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        pixel[3] = ((alpha >> (i * 4)) & 0xf) as u8 * 17;
+    }
+}
+
+fn decode_bc3(block: &[u8], pixels: &mut [[u8; 4]]) {
+    decode_bc_color(&block[8..16], false, pixels);
+    let alpha = decode_bc_channel(&block[0..8]);
+    for (pixel, alpha) in pixels.iter_mut().zip(alpha) {
+        pixel[3] = alpha;
+    }
+}
+
+fn decode_bc4(block: &[u8], pixels: &mut [[u8; 4]]) {
+    let red = decode_bc_channel(block);
+    for (pixel, red) in pixels.iter_mut().zip(red) {
+        *pixel = [red, 0, 0, 255];
+    }
+}
+
+fn decode_bc5(block: &[u8], pixels: &mut [[u8; 4]]) {
+    let red = decode_bc_channel(&block[0..8]);
+    let green = decode_bc_channel(&block[8..16]);
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = [red[i], green[i], 0, 255];
+    }
+}
+
+/// BC4/BC5 SNORM channel, as `Rgba8Snorm` octets.
+fn decode_bc_signed_channel(block: &[u8]) -> [u8; 16] {
+    let raw0 = block[0] as i8;
+    let raw1 = block[1] as i8;
+    // -128 and -127 both mean -1.0, but the mode is chosen from the stored values
+    let a0 = i32::from(raw0.max(-127));
+    let a1 = i32::from(raw1.max(-127));
+    let mut palette = [0i32; 8];
+    palette[0] = a0;
+    palette[1] = a1;
+    if raw0 > raw1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as i32) * a0 + i as i32 * a1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as i32) * a0 + i as i32 * a1) / 5;
+        }
+        palette[6] = -127;
+        palette[7] = 127;
+    }
+
+    let mut bits = 0u64;
+    for (i, octet) in block[2..8].iter().enumerate() {
+        bits |= (*octet as u64) << (i * 8);
+    }
+
+    let mut values = [0u8; 16];
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = palette[((bits >> (i * 3)) & 0x7) as usize] as i8 as u8;
+    }
+    values
+}
+
+const SNORM_ONE: u8 = 127;
+
+fn decode_bc4_signed(block: &[u8], pixels: &mut [[u8; 4]]) {
+    let red = decode_bc_signed_channel(block);
+    for (pixel, red) in pixels.iter_mut().zip(red) {
+        *pixel = [red, 0, 0, SNORM_ONE];
+    }
+}
+
+fn decode_bc5_signed(block: &[u8], pixels: &mut [[u8; 4]]) {
+    let red = decode_bc_signed_channel(&block[0..8]);
+    let green = decode_bc_signed_channel(&block[8..16]);
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = [red[i], green[i], 0, SNORM_ONE];
+    }
+}
+
+/// BC7 partitions of two subsets, bit `i` is the subset of pixel `i`. BC6H uses the first 32.
+const PARTITIONS_2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, 0xC800, 0xFFEC, 0xFE80, 0xE800,
+    0xFFE8, 0xFF00, 0xFFF0, 0xF000, 0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C, 0xAAAA, 0xF0F0, 0x5A5A, 0x33CC,
+    0x3C3C, 0x55AA, 0x9696, 0xA55A, 0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C, 0x9336, 0x9CC6, 0x817E, 0xE718,
+    0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+/// BC7 partitions of three subsets.
+const PARTITIONS_3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2],
+    [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0],
+    [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0],
+    [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2],
+    [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0],
+    [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0],
+    [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1],
+    [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1],
+    [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2],
+    [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2],
+    [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1],
+    [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+/// Pixel with the implicit high index bit of the second subset, for two subsets.
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+    2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2,
+    2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// Anchor pixels of the second and third subset, for three subsets.
+const ANCHORS_3: [[u8; 64]; 2] = [
+    [
+        3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, 3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6,
+        8, 5, 15, 15, 8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, 3, 15, 5, 5, 5, 8,
+        5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+    ],
+    [
+        15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, 15, 8, 15, 3, 15, 8, 15, 8, 3,
+        15, 6, 10, 15, 15, 10, 8, 15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, 15, 3, 15,
+        15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+    ],
+];
+
+const WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn bptc_weight(index: u32, index_bits: u32) -> u32 {
+    match index_bits {
+        2 => WEIGHTS_2[index as usize],
+        3 => WEIGHTS_3[index as usize],
+        _ => WEIGHTS_4[index as usize],
+    }
+}
+
+/// Subset of a pixel in a BC6H or BC7 partition.
+fn bptc_subset(subsets: usize, partition: usize, pixel: usize) -> usize {
+    match subsets {
+        1 => 0,
+        2 => ((PARTITIONS_2[partition] >> pixel) & 1) as usize,
+        _ => PARTITIONS_3[partition][pixel] as usize,
+    }
+}
+
+fn is_bptc_anchor(subsets: usize, partition: usize, pixel: usize) -> bool {
+    pixel == 0
+        || match subsets {
+            1 => false,
+            2 => pixel == ANCHORS_2[partition] as usize,
+            _ => {
+                pixel == ANCHORS_3[0][partition] as usize
+                    || pixel == ANCHORS_3[1][partition] as usize
+            }
+        }
+}
+
+/// Reads the 16 indices, the anchor pixels have one bit less.
+fn read_bptc_indices(
+    reader: &mut BitReader,
+    subsets: usize,
+    partition: usize,
+    index_bits: u32,
+) -> [u32; 16] {
+    let mut indices = [0; 16];
+    for (pixel, index) in indices.iter_mut().enumerate() {
+        let anchor = is_bptc_anchor(subsets, partition, pixel);
+        *index = reader.read(index_bits - u32::from(anchor));
+    }
+    indices
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_p_bits: bool,
+    shared_p_bits: bool,
+    index_bits: u32,
+    secondary_index_bits: u32,
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode::new(3, 4, 0, 0, 4, 0, true, false, 3, 0),
+    Bc7Mode::new(2, 6, 0, 0, 6, 0, false, true, 3, 0),
+    Bc7Mode::new(3, 6, 0, 0, 5, 0, false, false, 2, 0),
+    Bc7Mode::new(2, 6, 0, 0, 7, 0, true, false, 2, 0),
+    Bc7Mode::new(1, 0, 2, 1, 5, 6, false, false, 2, 3),
+    Bc7Mode::new(1, 0, 2, 0, 7, 8, false, false, 2, 2),
+    Bc7Mode::new(1, 0, 0, 0, 7, 7, true, false, 4, 0),
+    Bc7Mode::new(2, 6, 0, 0, 5, 5, true, false, 2, 0),
+];
+
+impl Bc7Mode {
+    #[allow(clippy::too_many_arguments)]
+    const fn new(
+        subsets: usize,
+        partition_bits: u32,
+        rotation_bits: u32,
+        index_selection_bits: u32,
+        color_bits: u32,
+        alpha_bits: u32,
+        endpoint_p_bits: bool,
+        shared_p_bits: bool,
+        index_bits: u32,
+        secondary_index_bits: u32,
+    ) -> Self {
+        Self {
+            subsets,
+            partition_bits,
+            rotation_bits,
+            index_selection_bits,
+            color_bits,
+            alpha_bits,
+            endpoint_p_bits,
+            shared_p_bits,
+            index_bits,
+            secondary_index_bits,
+        }
+    }
+}
+
+/// Expands a value of `precision` bits to 8 bits by replicating the high bits.
+fn expand_bits(value: u32, precision: u32) -> u32 {
+    let value = value << (8 - precision);
+    value | (value >> precision)
+}
+
+fn decode_bc7(block: &[u8], pixels: &mut [[u8; 4]]) {
+    let mode_index = block[0].trailing_zeros() as usize;
+    let Some(mode) = BC7_MODES.get(mode_index) else {
+        // Reserved mode
+        pixels.fill([0, 0, 0, 0]);
+        return;
+    };
+
+    let mut reader = BitReader::new(u128::from_le_bytes(block.try_into().unwrap()));
+    reader.read(mode_index as u32 + 1);
+    let partition = reader.read(mode.partition_bits) as usize;
+    let rotation = reader.read(mode.rotation_bits);
+    let index_selection = reader.read(mode.index_selection_bits);
+
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+    for channel in 0..3 {
+        for endpoint in &mut endpoints[..endpoint_count] {
+            endpoint[channel] = reader.read(mode.color_bits);
+        }
+    }
+    for endpoint in &mut endpoints[..endpoint_count] {
+        endpoint[3] = reader.read(mode.alpha_bits);
+    }
+
+    let has_p_bits = mode.endpoint_p_bits || mode.shared_p_bits;
+    if has_p_bits {
+        let mut p_bits = [0u32; 6];
+        if mode.endpoint_p_bits {
+            for p_bit in &mut p_bits[..endpoint_count] {
+                *p_bit = reader.read(1);
+            }
+        } else {
+            for subset in 0..mode.subsets {
+                let p_bit = reader.read(1);
+                p_bits[subset * 2] = p_bit;
+                p_bits[subset * 2 + 1] = p_bit;
+            }
+        }
+        for (endpoint, p_bit) in endpoints[..endpoint_count].iter_mut().zip(p_bits) {
+            for value in endpoint.iter_mut() {
+                *value = (*value << 1) | p_bit;
+            }
+        }
+    }
+
+    let color_precision = mode.color_bits + u32::from(has_p_bits);
+    let alpha_precision = mode.alpha_bits + u32::from(has_p_bits);
+    for endpoint in &mut endpoints[..endpoint_count] {
+        for value in &mut endpoint[..3] {
+            *value = expand_bits(*value, color_precision);
+        }
+        endpoint[3] = if mode.alpha_bits == 0 {
+            255
+        } else {
+            expand_bits(endpoint[3], alpha_precision)
+        };
+    }
+
+    let indices = read_bptc_indices(&mut reader, mode.subsets, partition, mode.index_bits);
+    let secondary_indices = if mode.secondary_index_bits == 0 {
+        indices
+    } else {
+        read_bptc_indices(&mut reader, 1, 0, mode.secondary_index_bits)
+    };
+    let (color_indices, color_bits, alpha_indices, alpha_bits) = if mode.secondary_index_bits == 0 {
+        (indices, mode.index_bits, indices, mode.index_bits)
+    } else if index_selection == 0 {
+        (
+            indices,
+            mode.index_bits,
+            secondary_indices,
+            mode.secondary_index_bits,
+        )
+    } else {
+        (
+            secondary_indices,
+            mode.secondary_index_bits,
+            indices,
+            mode.index_bits,
+        )
+    };
+
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let subset = bptc_subset(mode.subsets, partition, i);
+        let e0 = endpoints[subset * 2];
+        let e1 = endpoints[subset * 2 + 1];
+        let color_weight = bptc_weight(color_indices[i], color_bits);
+        let alpha_weight = bptc_weight(alpha_indices[i], alpha_bits);
+        let interpolate = |channel: usize, weight: u32| {
+            ((e0[channel] * (64 - weight) + e1[channel] * weight + 32) >> 6) as u8
+        };
+        *pixel = [
+            interpolate(0, color_weight),
+            interpolate(1, color_weight),
+            interpolate(2, color_weight),
+            interpolate(3, alpha_weight),
+        ];
+        match rotation {
+            1 => pixel.swap(0, 3),
+            2 => pixel.swap(1, 3),
+            3 => pixel.swap(2, 3),
+            _ => {}
+        }
+    }
+}
+
+// BC6H endpoint fields, w and x are the endpoints of the first subset, y and z of the second
+const RW: u8 = 0;
+const GW: u8 = 1;
+const BW: u8 = 2;
+const RX: u8 = 3;
+const GX: u8 = 4;
+const BX: u8 = 5;
+const RY: u8 = 6;
+const GY: u8 = 7;
+const BY: u8 = 8;
+const RZ: u8 = 9;
+const GZ: u8 = 10;
+const BZ: u8 = 11;
+const PARTITION: u8 = 12;
+
+struct Bc6hMode {
+    subsets: usize,
+    /// Endpoints are stored as deltas from the first endpoint
+    transformed: bool,
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    /// `(field, lowest bit, bit count)` in stream order, after the mode bits
+    layout: &'static [(u8, u8, u8)],
+}
+
+const BC6H_MODE_1: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 10,
+    delta_bits: [5, 5, 5],
+    layout: &[
+        (GY, 4, 1),
+        (BY, 4, 1),
+        (BZ, 4, 1),
+        (RW, 0, 10),
+        (GW, 0, 10),
+        (BW, 0, 10),
+        (RX, 0, 5),
+        (GZ, 4, 1),
+        (GY, 0, 4),
+        (GX, 0, 5),
+        (BZ, 0, 1),
+        (GZ, 0, 4),
+        (BX, 0, 5),
+        (BZ, 1, 1),
+        (BY, 0, 4),
+        (RY, 0, 5),
+        (BZ, 2, 1),
+        (RZ, 0, 5),
+        (BZ, 3, 1),
+        (PARTITION, 0, 5),
+    ],
+};
+
+const BC6H_MODE_2: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 7,
+    delta_bits: [6, 6, 6],
+    layout: &[
+        (GY, 5, 1),
+        (GZ, 4, 1),
+        (GZ, 5, 1),
+        (RW, 0, 7),
+        (BZ, 0, 1),
+        (BZ, 1, 1),
+        (BY, 4, 1),
+        (GW, 0, 7),
+        (BY, 5, 1),
+        (BZ, 2, 1),
+        (GY, 4, 1),
+        (BW, 0, 7),
+        (BZ, 3, 1),
+        (BZ, 5, 1),
+        (BZ, 4, 1),
+        (RX, 0, 6),
+        (GY, 0, 4),
+        (GX, 0, 6),
+        (GZ, 0, 4),
+        (BX, 0, 6),
+        (BY, 0, 4),
+        (RY, 0, 6),
+        (RZ, 0, 6),
+        (PARTITION, 0, 5),
+    ],
+};
+
+const BC6H_MODE_3: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 11,
+    delta_bits: [5, 4, 4],
+    layout: &[
+        (RW, 0, 10),
+        (GW, 0, 10),
+        (BW, 0, 10),
+        (RX, 0, 5),
+        (RW, 10, 1),
+        (GY, 0, 4),
+        (GX, 0, 4),
+        (GW, 10, 1),
+        (BZ, 0, 1),
+        (GZ, 0, 4),
+        (BX, 0, 4),
+        (BW, 10, 1),
+        (BZ, 1, 1),
+        (BY, 0, 4),
+        (RY, 0, 5),
+        (BZ, 2, 1),
+        (RZ, 0, 5),
+        (BZ, 3, 1),
+        (PARTITION, 0, 5),
+    ],
+};
+
+const BC6H_MODE_4: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 11,
+    delta_bits: [4, 5, 4],
+    layout: &[
+        (RW, 0, 10),
+        (GW, 0, 10),
+        (BW, 0, 10),
+        (RX, 0, 4),
+        (RW, 10, 1),
+        (GZ, 4, 1),
+        (GY, 0, 4),
+        (GX, 0, 5),
+        (GW, 10, 1),
+        (GZ, 0, 4),
+        (BX, 0, 4),
+        (BW, 10, 1),
+        (BZ, 1, 1),
+        (BY, 0, 4),
+        (RY, 0, 4),
+        (BZ, 0, 1),
+        (BZ, 2, 1),
+        (RZ, 0, 4),
+        (GY, 4, 1),
+        (BZ, 3, 1),
+        (PARTITION, 0, 5),
+    ],
+};
+
+const BC6H_MODE_5: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 11,
+    delta_bits: [4, 4, 5],
+    layout: &[
+        (RW, 0, 10),
+        (GW, 0, 10),
+        (BW, 0, 10),
+        (RX, 0, 4),
+        (RW, 10, 1),
+        (BY, 4, 1),
+        (GY, 0, 4),
+        (GX, 0, 4),
+        (GW, 10, 1),
+        (BZ, 0, 1),
+        (GZ, 0, 4),
+        (BX, 0, 5),
+        (BW, 10, 1),
+        (BY, 0, 4),
+        (RY, 0, 4),
+        (BZ, 1, 1),
+        (BZ, 2, 1),
+        (RZ, 0, 4),
+        (BZ, 4, 1),
+        (BZ, 3, 1),
+        (PARTITION, 0, 5),
+    ],
+};
+
+const BC6H_MODE_6: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 9,
+    delta_bits: [5, 5, 5],
+    layout: &[
+        (RW, 0, 9),
+        (BY, 4, 1),
+        (GW, 0, 9),
+        (GY, 4, 1),
+        (BW, 0, 9),
+        (BZ, 4, 1),
+        (RX, 0, 5),
+        (GZ, 4, 1),
+        (GY, 0, 4),
+        (GX, 0, 5),
+        (BZ, 0, 1),
+        (GZ, 0, 4),
+        (BX, 0, 5),
+        (BZ, 1, 1),
+        (BY, 0, 4),
+        (RY, 0, 5),
+        (BZ, 2, 1),
+        (RZ, 0, 5),
+        (BZ, 3, 1),
+        (PARTITION, 0, 5),
+    ],
+};
+
+const BC6H_MODE_7: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 8,
+    delta_bits: [6, 5, 5],
+    layout: &[
+        (RW, 0, 8),
+        (GZ, 4, 1),
+        (BY, 4, 1),
+        (GW, 0, 8),
+        (BZ, 2, 1),
+        (GY, 4, 1),
+        (BW, 0, 8),
+        (BZ, 3, 1),
+        (BZ, 4, 1),
+        (RX, 0, 6),
+        (GY, 0, 4),
+        (GX, 0, 5),
+        (BZ, 0, 1),
+        (GZ, 0, 4),
+        (BX, 0, 5),
+        (BZ, 1, 1),
+        (BY, 0, 4),
+        (RY, 0, 6),
+        (RZ, 0, 6),
+        (PARTITION, 0, 5),
+    ],
+};
+
+const BC6H_MODE_8: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 8,
+    delta_bits: [5, 6, 5],
+    layout: &[
+        (RW, 0, 8),
+        (BZ, 0, 1),
+        (BY, 4, 1),
+        (GW, 0, 8),
+        (GY, 5, 1),
+        (GY, 4, 1),
+        (BW, 0, 8),
+        (GZ, 5, 1),
+        (BZ, 4, 1),
+        (RX, 0, 5),
+        (GZ, 4, 1),
+        (GY, 0, 4),
+        (GX, 0, 6),
+        (GZ, 0, 4),
+        (BX, 0, 5),
+        (BZ, 1, 1),
+        (BY, 0, 4),
+        (RY, 0, 5),
+        (BZ, 2, 1),
+        (RZ, 0, 5),
+        (BZ, 3, 1),
+        (PARTITION, 0, 5),
+    ],
+};
+
+const BC6H_MODE_9: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 8,
+    delta_bits: [5, 5, 6],
+    layout: &[
+        (RW, 0, 8),
+        (BZ, 1, 1),
+        (BY, 4, 1),
+        (GW, 0, 8),
+        (BY, 5, 1),
+        (GY, 4, 1),
+        (BW, 0, 8),
+        (BZ, 5, 1),
+        (BZ, 4, 1),
+        (RX, 0, 5),
+        (GZ, 4, 1),
+        (GY, 0, 4),
+        (GX, 0, 5),
+        (BZ, 0, 1),
+        (GZ, 0, 4),
+        (BX, 0, 6),
+        (BY, 0, 4),
+        (RY, 0, 5),
+        (BZ, 2, 1),
+        (RZ, 0, 5),
+        (BZ, 3, 1),
+        (PARTITION, 0, 5),
+    ],
+};
+
+const BC6H_MODE_10: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: false,
+    endpoint_bits: 6,
+    delta_bits: [6, 6, 6],
+    layout: &[
+        (RW, 0, 6),
+        (GZ, 4, 1),
+        (BZ, 0, 1),
+        (BZ, 1, 1),
+        (BY, 4, 1),
+        (GW, 0, 6),
+        (GY, 5, 1),
+        (BY, 5, 1),
+        (BZ, 2, 1),
+        (GY, 4, 1),
+        (BW, 0, 6),
+        (GZ, 5, 1),
+        (BZ, 3, 1),
+        (BZ, 5, 1),
+        (BZ, 4, 1),
+        (RX, 0, 6),
+        (GY, 0, 4),
+        (GX, 0, 6),
+        (GZ, 0, 4),
+        (BX, 0, 6),
+        (BY, 0, 4),
+        (RY, 0, 6),
+        (RZ, 0, 6),
+        (PARTITION, 0, 5),
+    ],
+};
+
+const BC6H_MODE_11: Bc6hMode = Bc6hMode {
+    subsets: 1,
+    transformed: false,
+    endpoint_bits: 10,
+    delta_bits: [10, 10, 10],
+    layout: &[
+        (RW, 0, 10),
+        (GW, 0, 10),
+        (BW, 0, 10),
+        (RX, 0, 10),
+        (GX, 0, 10),
+        (BX, 0, 10),
+    ],
+};
+
+const BC6H_MODE_12: Bc6hMode = Bc6hMode {
+    subsets: 1,
+    transformed: true,
+    endpoint_bits: 11,
+    delta_bits: [9, 9, 9],
+    layout: &[
+        (RW, 0, 10),
+        (GW, 0, 10),
+        (BW, 0, 10),
+        (RX, 0, 9),
+        (RW, 10, 1),
+        (GX, 0, 9),
+        (GW, 10, 1),
+        (BX, 0, 9),
+        (BW, 10, 1),
+    ],
+};
+
+// The high bits of the first endpoint are stored in reverse order in the last two modes
+const BC6H_MODE_13: Bc6hMode = Bc6hMode {
+    subsets: 1,
+    transformed: true,
+    endpoint_bits: 12,
+    delta_bits: [8, 8, 8],
+    layout: &[
+        (RW, 0, 10),
+        (GW, 0, 10),
+        (BW, 0, 10),
+        (RX, 0, 8),
+        (RW, 11, 1),
+        (RW, 10, 1),
+        (GX, 0, 8),
+        (GW, 11, 1),
+        (GW, 10, 1),
+        (BX, 0, 8),
+        (BW, 11, 1),
+        (BW, 10, 1),
+    ],
+};
+
+const BC6H_MODE_14: Bc6hMode = Bc6hMode {
+    subsets: 1,
+    transformed: true,
+    endpoint_bits: 16,
+    delta_bits: [4, 4, 4],
+    layout: &[
+        (RW, 0, 10),
+        (GW, 0, 10),
+        (BW, 0, 10),
+        (RX, 0, 4),
+        (RW, 15, 1),
+        (RW, 14, 1),
+        (RW, 13, 1),
+        (RW, 12, 1),
+        (RW, 11, 1),
+        (RW, 10, 1),
+        (GX, 0, 4),
+        (GW, 15, 1),
+        (GW, 14, 1),
+        (GW, 13, 1),
+        (GW, 12, 1),
+        (GW, 11, 1),
+        (GW, 10, 1),
+        (BX, 0, 4),
+        (BW, 15, 1),
+        (BW, 14, 1),
+        (BW, 13, 1),
+        (BW, 12, 1),
+        (BW, 11, 1),
+        (BW, 10, 1),
+    ],
+};
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    (value << shift) >> shift
+}
+
+/// Scales an endpoint to 16 bits.
+fn bc6h_unquantize(value: i32, bits: u32, signed: bool) -> i32 {
+    if !signed {
+        if bits >= 15 || value == 0 {
+            value
+        } else if value == (1 << bits) - 1 {
+            0xFFFF
+        } else {
+            ((value << 16) + 0x8000) >> bits
+        }
+    } else if bits >= 16 {
+        value
+    } else {
+        let magnitude = value.abs();
+        let unquantized = if magnitude == 0 {
+            0
+        } else if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7FFF
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+        unquantized * value.signum()
+    }
+}
+
+/// Converts an interpolated value to the bits of a half float.
+fn bc6h_finish_unquantize(value: i32, signed: bool) -> u16 {
+    if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        0x8000 | (((-value) * 31) >> 5) as u16
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 == 0 { 1.0 } else { -1.0 };
+    let exponent = i32::from((half >> 10) & 0x1F);
+    let mantissa = f32::from(half & 0x3FF);
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => sign * f32::INFINITY,
+        31 => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+fn decode_bc6h(block: &[u8], signed: bool, pixels: &mut [[u8; 4]]) {
+    let mut reader = BitReader::new(u128::from_le_bytes(block.try_into().unwrap()));
+    let mode = match reader.read(2) {
+        0b00 => &BC6H_MODE_1,
+        0b01 => &BC6H_MODE_2,
+        low => match (reader.read(3) << 2) | low {
+            0b00010 => &BC6H_MODE_3,
+            0b00110 => &BC6H_MODE_4,
+            0b01010 => &BC6H_MODE_5,
+            0b01110 => &BC6H_MODE_6,
+            0b10010 => &BC6H_MODE_7,
+            0b10110 => &BC6H_MODE_8,
+            0b11010 => &BC6H_MODE_9,
+            0b11110 => &BC6H_MODE_10,
+            0b00011 => &BC6H_MODE_11,
+            0b00111 => &BC6H_MODE_12,
+            0b01011 => &BC6H_MODE_13,
+            0b01111 => &BC6H_MODE_14,
+            _ => {
+                // Reserved mode
+                pixels.fill([0, 0, 0, 255]);
+                return;
+            }
+        },
+    };
+
+    let mut fields = [0i32; 13];
+    for &(field, low_bit, count) in mode.layout {
+        fields[field as usize] |= (reader.read(u32::from(count)) as i32) << low_bit;
+    }
+    let subsets = mode.subsets;
+    let partition = fields[PARTITION as usize] as usize;
+
+    let mut endpoints = [[0i32; 3]; 4];
+    for (index, endpoint) in endpoints[..subsets * 2].iter_mut().enumerate() {
+        endpoint.copy_from_slice(&fields[index * 3..index * 3 + 3]);
+    }
+    let endpoint_bits = mode.endpoint_bits;
+    if signed {
+        for value in &mut endpoints[0] {
+            *value = sign_extend(*value, endpoint_bits);
+        }
+    }
+    let first = endpoints[0];
+    for endpoint in &mut endpoints[1..subsets * 2] {
+        for (channel, value) in endpoint.iter_mut().enumerate() {
+            if mode.transformed || signed {
+                *value = sign_extend(*value, mode.delta_bits[channel]);
+            }
+            if mode.transformed {
+                *value = (*value + first[channel]) & ((1 << endpoint_bits) - 1);
+                if signed {
+                    *value = sign_extend(*value, endpoint_bits);
+                }
+            }
+        }
+    }
+    for endpoint in &mut endpoints[..subsets * 2] {
+        for value in endpoint.iter_mut() {
+            *value = bc6h_unquantize(*value, endpoint_bits, signed);
+        }
+    }
+
+    let index_bits = if subsets == 1 { 4 } else { 3 };
+    let indices = read_bptc_indices(&mut reader, subsets, partition, index_bits);
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let subset = bptc_subset(subsets, partition, i);
+        let weight = bptc_weight(indices[i], index_bits) as i32;
+        let e0 = endpoints[subset * 2];
+        let e1 = endpoints[subset * 2 + 1];
+        let channel = |c: usize| {
+            let value = (e0[c] * (64 - weight) + e1[c] * weight + 32) >> 6;
+            let linear = half_to_f32(bc6h_finish_unquantize(value, signed));
+            (linear.clamp(0.0, 1.0) * 255.0).round() as u8
+        };
+        *pixel = [channel(0), channel(1), channel(2), 255];
+    }
+}
+
+const ETC1_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+const ETC2_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+fn bits(value: u64, high: u32, count: u32) -> u32 {
+    ((value >> (high + 1 - count)) & ((1 << count) - 1)) as u32
+}
+
+fn extend_4(value: u32) -> i32 {
+    (value * 17) as i32
+}
+
+fn extend_5(value: u32) -> i32 {
+    ((value << 3) | (value >> 2)) as i32
+}
+
+fn extend_6(value: u32) -> i32 {
+    ((value << 2) | (value >> 4)) as i32
+}
+
+fn extend_7(value: u32) -> i32 {
+    ((value << 1) | (value >> 6)) as i32
+}
+
+fn clamp_rgb(color: [i32; 3], offset: i32) -> [u8; 4] {
+    [
+        (color[0] + offset).clamp(0, 255) as u8,
+        (color[1] + offset).clamp(0, 255) as u8,
+        (color[2] + offset).clamp(0, 255) as u8,
+        255,
+    ]
+}
+
+/// Two bit index of a pixel. ETC stores the pixels column major.
+fn etc_pixel_index(block: u64, x: usize, y: usize) -> usize {
+    let k = x * 4 + y;
+    let msb = (block >> (k + 16)) & 1;
+    let lsb = (block >> k) & 1;
+    ((msb << 1) | lsb) as usize
+}
+
+/// Decodes an ETC2 color block. When `punchthrough` is set, bit 33 is the opaque flag
+/// instead of the differential flag, as used by the RGB8A1 format.
+fn decode_etc2_color(block: u64, punchthrough: bool, pixels: &mut [[u8; 4]]) {
+    let flag = bits(block, 33, 1) == 1;
+    let (differential, opaque) = if punchthrough {
+        (true, flag)
+    } else {
+        (flag, true)
+    };
+
+    if !differential {
+        let base1 = [
+            extend_4(bits(block, 63, 4)),
+            extend_4(bits(block, 55, 4)),
+            extend_4(bits(block, 47, 4)),
+        ];
+        let base2 = [
+            extend_4(bits(block, 59, 4)),
+            extend_4(bits(block, 51, 4)),
+            extend_4(bits(block, 43, 4)),
+        ];
+        decode_etc_sub_blocks(block, base1, base2, true, pixels);
+        return;
+    }
+
+    let red = bits(block, 63, 5) as i32;
+    let green = bits(block, 55, 5) as i32;
+    let blue = bits(block, 47, 5) as i32;
+    let delta = |high: u32| ((bits(block, high, 3) as i32) << 29) >> 29;
+    let red2 = red + delta(58);
+    let green2 = green + delta(50);
+    let blue2 = blue + delta(42);
+
+    if !(0..32).contains(&red2) {
+        decode_etc2_t(block, opaque, pixels);
+    } else if !(0..32).contains(&green2) {
+        decode_etc2_h(block, opaque, pixels);
+    } else if !(0..32).contains(&blue2) {
+        decode_etc2_planar(block, pixels);
+    } else {
+        let base1 = [red, green, blue].map(|c| extend_5(c as u32));
+        let base2 = [red2, green2, blue2].map(|c| extend_5(c as u32));
+        decode_etc_sub_blocks(block, base1, base2, opaque, pixels);
+    }
+}
+
+fn decode_etc_sub_blocks(
+    block: u64,
+    base1: [i32; 3],
+    base2: [i32; 3],
+    opaque: bool,
+    pixels: &mut [[u8; 4]],
+) {
+    let table1 = ETC1_MODIFIERS[bits(block, 39, 3) as usize];
+    let table2 = ETC1_MODIFIERS[bits(block, 36, 3) as usize];
+    let flip = bits(block, 32, 1) == 1;
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let second = if flip { y >= 2 } else { x >= 2 };
+            let (base, table) = if second {
+                (base2, table2)
+            } else {
+                (base1, table1)
+            };
+            let index = etc_pixel_index(block, x, y);
+            pixels[y * 4 + x] = if !opaque && index == 2 {
+                [0, 0, 0, 0]
+            } else {
+                let modifier = match index {
+                    0 if !opaque => 0,
+                    0 => table[0],
+                    1 => table[1],
+                    2 => -table[0],
+                    _ => -table[1],
+                };
+                clamp_rgb(base, modifier)
+            };
+        }
+    }
+}
+
+fn write_paint_colors(block: u64, paint: [[u8; 4]; 4], opaque: bool, pixels: &mut [[u8; 4]]) {
+    for y in 0..4 {
+        for x in 0..4 {
+            let index = etc_pixel_index(block, x, y);
+            pixels[y * 4 + x] = if !opaque && index == 2 {
+                [0, 0, 0, 0]
+            } else {
+                paint[index]
+            };
+        }
+    }
+}
+
+fn decode_etc2_t(block: u64, opaque: bool, pixels: &mut [[u8; 4]]) {
+    let color1 = [
+        extend_4((bits(block, 60, 2) << 2) | bits(block, 57, 2)),
+        extend_4(bits(block, 55, 4)),
+        extend_4(bits(block, 51, 4)),
+    ];
+    let color2 = [
+        extend_4(bits(block, 47, 4)),
+        extend_4(bits(block, 43, 4)),
+        extend_4(bits(block, 39, 4)),
+    ];
+    let distance = ETC2_DISTANCES[((bits(block, 35, 2) << 1) | bits(block, 32, 1)) as usize];
+
+    let paint = [
+        clamp_rgb(color1, 0),
+        clamp_rgb(color2, distance),
+        clamp_rgb(color2, 0),
+        clamp_rgb(color2, -distance),
+    ];
+    write_paint_colors(block, paint, opaque, pixels);
+}
+
+fn decode_etc2_h(block: u64, opaque: bool, pixels: &mut [[u8; 4]]) {
+    let color1_444 = [
+        bits(block, 62, 4),
+        (bits(block, 58, 3) << 1) | bits(block, 52, 1),
+        (bits(block, 51, 1) << 3) | bits(block, 49, 3),
+    ];
+    let color2_444 = [bits(block, 46, 4), bits(block, 42, 4), bits(block, 38, 4)];
+    let packed = |c: [u32; 3]| (c[0] << 8) | (c[1] << 4) | c[2];
+    let ordering = u32::from(packed(color1_444) >= packed(color2_444));
+    let distance =
+        ETC2_DISTANCES[((bits(block, 34, 1) << 2) | (bits(block, 32, 1) << 1) | ordering) as usize];
+
+    let color1 = color1_444.map(extend_4);
+    let color2 = color2_444.map(extend_4);
+    let paint = [
+        clamp_rgb(color1, distance),
+        clamp_rgb(color1, -distance),
+        clamp_rgb(color2, distance),
+        clamp_rgb(color2, -distance),
+    ];
+    write_paint_colors(block, paint, opaque, pixels);
+}
+
+fn decode_etc2_planar(block: u64, pixels: &mut [[u8; 4]]) {
+    let origin = [
+        extend_6(bits(block, 62, 6)),
+        extend_7((bits(block, 56, 1) << 6) | bits(block, 54, 6)),
+        extend_6((bits(block, 48, 1) << 5) | (bits(block, 44, 2) << 3) | bits(block, 41, 3)),
+    ];
+    let horizontal = [
+        extend_6((bits(block, 38, 5) << 1) | bits(block, 32, 1)),
+        extend_7(bits(block, 31, 7)),
+        extend_6(bits(block, 24, 6)),
+    ];
+    let vertical = [
+        extend_6(bits(block, 18, 6)),
+        extend_7(bits(block, 12, 7)),
+        extend_6(bits(block, 5, 6)),
+    ];
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let channel = |i: usize| {
+                ((x as i32 * (horizontal[i] - origin[i])
+                    + y as i32 * (vertical[i] - origin[i])
+                    + 4 * origin[i]
+                    + 2)
+                    >> 2)
+                    .clamp(0, 255) as u8
+            };
+            pixels[y * 4 + x] = [channel(0), channel(1), channel(2), 255];
+        }
+    }
+}
+
+fn decode_etc2_rgb(block: &[u8], pixels: &mut [[u8; 4]]) {
+    decode_etc2_color(u64::from_be_bytes(block.try_into().unwrap()), false, pixels);
+}
+
+fn decode_etc2_rgb_a1(block: &[u8], pixels: &mut [[u8; 4]]) {
+    decode_etc2_color(u64::from_be_bytes(block.try_into().unwrap()), true, pixels);
+}
+
+fn decode_etc2_rgba(block: &[u8], pixels: &mut [[u8; 4]]) {
+    decode_etc2_color(
+        u64::from_be_bytes(block[8..16].try_into().unwrap()),
+        false,
+        pixels,
+    );
+
+    let alpha = u64::from_be_bytes(block[0..8].try_into().unwrap());
+    let base = bits(alpha, 63, 8) as i32;
+    let multiplier = bits(alpha, 55, 4) as i32;
+    let table = EAC_MODIFIERS[bits(alpha, 51, 4) as usize];
+    for y in 0..4 {
+        for x in 0..4 {
+            let k = (x * 4 + y) as u32;
+            let index = bits(alpha, 47 - k * 3, 3) as usize;
+            pixels[y * 4 + x][3] = (base + table[index] * multiplier).clamp(0, 255) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Expected pixels of the BC6H, BC7, ETC2 and ASTC blocks were checked against a GPU decoder.
+
+    const BC1_RED_BLUE: [u8; 8] = [0x00, 0xf8, 0x1f, 0x00, 0xe4, 0x00, 0x00, 0x00];
+    const BC1_GREEN: [u8; 8] = [0xe0, 0x07, 0xe0, 0x07, 0x00, 0x00, 0x00, 0x00];
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    fn decode(format: TextureFormat, block: &[u8]) -> Vec<[u8; 4]> {
+        let (width, height) = format.block_dimensions();
+        decompress_to_rgba(format, width, height, block)
+            .unwrap()
+            .chunks(4)
+            .map(|pixel| pixel.try_into().unwrap())
+            .collect()
+    }
+
+    /// The first four pixels of a block, the rest use the same color as the first.
+    fn first_row(pixels: &[[u8; 4]]) -> [[u8; 4]; 4] {
+        assert!(pixels[4..].iter().all(|pixel| *pixel == pixels[0]));
+        pixels[..4].try_into().unwrap()
+    }
+
+    #[test]
+    fn bc1_four_colors() {
+        let pixels = decode(TextureFormat::Bc1RgbaUnorm, &BC1_RED_BLUE);
+        assert_eq!(
+            first_row(&pixels),
+            [RED, BLUE, [170, 0, 85, 255], [85, 0, 170, 255]]
+        );
+    }
+
+    #[test]
+    fn bc1_three_colors_and_transparent() {
+        let block = [0x1f, 0x00, 0x00, 0xf8, 0xe4, 0x00, 0x00, 0x00];
+        let pixels = decode(TextureFormat::Bc1RgbaUnorm, &block);
+        assert_eq!(
+            first_row(&pixels),
+            [BLUE, RED, [127, 0, 127, 255], [0, 0, 0, 0]]
+        );
+    }
+
+    #[test]
+    fn bc2_explicit_alpha() {
+        let mut block = [0x10, 0x32, 0x54, 0x76, 0x98, 0xba, 0xdc, 0xfe].to_vec();
+        block.extend_from_slice(&BC1_GREEN);
+        let pixels = decode(TextureFormat::Bc2RgbaUnorm, &block);
+        for (i, pixel) in pixels.iter().enumerate() {
+            assert_eq!(*pixel, [0, 255, 0, i as u8 * 17]);
+        }
+    }
+
+    #[test]
+    fn bc3_interpolated_alpha() {
+        let mut block = [0xff, 0x00, 0x88, 0x0e, 0x00, 0x00, 0x00, 0x00].to_vec();
+        block.extend_from_slice(&BC1_RED_BLUE);
+        let pixels = decode(TextureFormat::Bc3RgbaUnorm, &block);
+        assert_eq!(
+            first_row(&pixels),
+            [RED, [0, 0, 255, 0], [170, 0, 85, 218], [85, 0, 170, 36]]
+        );
+    }
+
+    #[test]
+    fn bc4_six_values() {
+        let block = [0x00, 0xff, 0x88, 0x7c, 0x00, 0x00, 0x00, 0x00];
+        let pixels = decode(TextureFormat::Bc4RUnorm, &block);
+        assert_eq!(
+            pixels[..5],
+            [
+                [0, 0, 0, 255],
+                [255, 0, 0, 255],
+                [51, 0, 0, 255],
+                [0, 0, 0, 255],
+                [255, 0, 0, 255]
+            ]
+        );
+    }
+
+    #[test]
+    fn bc5_two_channels() {
+        let block = [
+            0xff, 0x00, 0x88, 0x0e, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x88, 0x7c, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+        let pixels = decode(TextureFormat::Bc5RgUnorm, &block);
+        assert_eq!(
+            pixels[..5],
+            [
+                [255, 0, 0, 255],
+                [0, 255, 0, 255],
+                [218, 51, 0, 255],
+                [36, 0, 0, 255],
+                [255, 255, 0, 255]
+            ]
+        );
+    }
+
+    #[test]
+    fn bc4_signed() {
+        let block = [0x7f, 0x80, 0x88, 0x0e, 0x00, 0x00, 0x00, 0x00];
+        let pixels = decode(TextureFormat::Bc4RSnorm, &block);
+        let red: Vec<i8> = pixels.iter().map(|pixel| pixel[0] as i8).collect();
+        assert_eq!(red[..4], [127, -127, 90, -90]);
+        assert!(pixels.iter().all(|pixel| pixel[3] == SNORM_ONE));
+    }
+
+    #[test]
+    fn bc5_signed_six_values() {
+        let block = [
+            0x7f, 0x80, 0x88, 0x0e, 0x00, 0x00, 0x00, 0x00, 0x80, 0x7f, 0x88, 0x0e, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+        let pixels = decode(TextureFormat::Bc5RgSnorm, &block);
+        let green: Vec<i8> = pixels.iter().map(|pixel| pixel[1] as i8).collect();
+        assert_eq!(green[..4], [-127, 127, -76, 127]);
+    }
+
+    #[test]
+    fn bc6h_unsigned() {
+        let block = [
+            0x03, 0x00, 0x32, 0xf4, 0x79, 0x0f, 0x32, 0x96, 0x10, 0x32, 0x54, 0x76, 0x98, 0xba,
+            0xdc, 0xfe,
+        ];
+        let pixels = decode(TextureFormat::Bc6hRgbUfloat, &block);
+        assert_eq!(pixels[0], [0, 0, 2, 255]);
+        assert_eq!(pixels[9], [4, 3, 3, 255]);
+        assert_eq!(pixels[13], [60, 15, 4, 255]);
+        assert_eq!(pixels[15], [255, 36, 4, 255]);
+    }
+
+    #[test]
+    fn bc6h_signed_clamps_negative() {
+        let block = [
+            0x83, 0x73, 0x00, 0xc8, 0xb8, 0x07, 0x8f, 0x84, 0x11, 0x32, 0x54, 0x76, 0x98, 0xba,
+            0xdc, 0xfe,
+        ];
+        let pixels = decode(TextureFormat::Bc6hRgbFloat, &block);
+        assert_eq!(pixels[0], [0, 0, 1, 255]);
+        assert_eq!(pixels[12], [14, 0, 0, 255]);
+        assert_eq!(pixels[15], [253, 1, 0, 255]);
+    }
+
+    #[test]
+    fn bc7_single_subset() {
+        let block = [
+            0x40, 0xc0, 0x1f, 0xf0, 0x07, 0xfc, 0x01, 0x7f, 0x11, 0x32, 0x54, 0x76, 0x98, 0xba,
+            0xdc, 0xfe,
+        ];
+        let pixels = decode(TextureFormat::Bc7RgbaUnorm, &block);
+        let values: Vec<u8> = pixels.iter().map(|pixel| pixel[0]).collect();
+        assert_eq!(
+            values,
+            [0, 16, 36, 52, 68, 84, 104, 120, 135, 151, 171, 187, 203, 219, 239, 255]
+        );
+        assert!(pixels
+            .iter()
+            .all(|pixel| pixel.iter().all(|c| *c == pixel[0])));
+    }
+
+    #[test]
+    fn bc7_two_subsets() {
+        let block = [
+            0x36, 0x8a, 0xfc, 0x17, 0x14, 0x0a, 0xfc, 0x9e, 0x17, 0x32, 0x31, 0x87, 0x57, 0x31,
+            0x87, 0x57,
+        ];
+        let pixels = decode(TextureFormat::Bc7RgbaUnorm, &block);
+        assert_eq!(pixels[0], [42, 82, 122, 255]);
+        assert_eq!(pixels[5], [203, 163, 122, 255]);
+        assert_eq!(pixels[8], [253, 0, 133, 255]);
+        assert_eq!(pixels[13], [20, 253, 48, 255]);
+        assert_eq!(pixels[15], [220, 36, 121, 255]);
+        assert_eq!(decode(TextureFormat::Bc7RgbaUnormSrgb, &block), pixels);
+    }
+
+    #[test]
+    fn bc7_reserved_mode_is_transparent() {
+        let pixels = decode(TextureFormat::Bc7RgbaUnorm, &[0; 16]);
+        assert!(pixels.iter().all(|pixel| *pixel == [0, 0, 0, 0]));
+    }
+
+    const ETC2_DIFFERENTIAL: [u8; 8] = [0x5a, 0x82, 0xc3, 0x12, 0x34, 0x56, 0x78, 0x9a];
+
+    #[test]
+    fn etc2_differential() {
+        let pixels = decode(TextureFormat::Etc2Rgb8Unorm, &ETC2_DIFFERENTIAL);
+        assert_eq!(pixels[0], [92, 134, 200, 255]);
+        assert_eq!(pixels[3], [47, 88, 162, 255]);
+        assert_eq!(pixels[10], [89, 130, 204, 255]);
+        assert_eq!(pixels[11], [167, 208, 255, 255]);
+        assert_eq!(
+            decode(TextureFormat::Etc2Rgb8A1Unorm, &ETC2_DIFFERENTIAL),
+            pixels
+        );
+    }
+
+    #[test]
+    fn etc2_t_mode() {
+        let block = [0xf8, 0x07, 0x1e, 0xfd, 0xe4, 0x1b, 0xa5, 0x5a];
+        let pixels = decode(TextureFormat::Etc2Rgb8Unorm, &block);
+        assert_eq!(pixels[0], [208, 0, 0, 255]);
+        assert_eq!(pixels[2], [255, 183, 200, 255]);
+        assert_eq!(pixels[9], [255, 255, 255, 255]);
+        assert_eq!(pixels[11], [89, 72, 191, 255]);
+    }
+
+    #[test]
+    fn etc2_eac_alpha() {
+        let mut block = [0x7f, 0xc3, 0x92, 0x49, 0x24, 0xb6, 0xdb, 0x6d].to_vec();
+        block.extend_from_slice(&ETC2_DIFFERENTIAL);
+        let pixels = decode(TextureFormat::Etc2Rgba8Unorm, &block);
+        assert_eq!(pixels[0], [92, 134, 200, 139]);
+        assert_eq!(pixels[2], [125, 166, 240, 163]);
+        assert_eq!(pixels[15], [125, 166, 240, 163]);
+    }
+
+    const ASTC_4X4_UNORM: TextureFormat = TextureFormat::Astc {
+        block: AstcBlock::B4x4,
+        channel: AstcChannel::Unorm,
+    };
+
+    #[test]
+    fn astc_void_extent() {
+        let block = [
+            0xfc, 0xfd, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x80, 0x34, 0x12,
+            0xff, 0xff,
+        ];
+        let pixels = decode(ASTC_4X4_UNORM, &block);
+        assert!(pixels.iter().all(|pixel| *pixel == [255, 128, 18, 255]));
+    }
+
+    #[test]
+    fn astc_reserved_block_mode_is_error_color() {
+        let pixels = decode(ASTC_4X4_UNORM, &[0; 16]);
+        assert!(pixels.iter().all(|pixel| *pixel == [255, 0, 255, 255]));
+    }
+
+    #[test]
+    fn astc_6x6_block() {
+        let block = [
+            0x22, 0x04, 0x0b, 0xe5, 0x50, 0xcc, 0x93, 0x44, 0xfa, 0x87, 0x22, 0xf2, 0x4d, 0xdb,
+            0xb8, 0x6d,
+        ];
+        let format = |channel| TextureFormat::Astc {
+            block: AstcBlock::B6x6,
+            channel,
+        };
+        let pixels = decode(format(AstcChannel::Unorm), &block);
+        assert_eq!(pixels.len(), 36);
+        assert_eq!(pixels[0], [120, 168, 60, 255]);
+        assert_eq!(pixels[5], [127, 102, 73, 255]);
+        assert_eq!(pixels[18], [114, 230, 44, 255]);
+        assert_eq!(pixels[35], [133, 40, 60, 255]);
+
+        let srgb = decode(format(AstcChannel::UnormSrgb), &block);
+        assert_eq!(srgb[0], [120, 168, 60, 255]);
+        assert_eq!(srgb[26], [130, 67, 53, 255]);
+    }
+
+    #[test]
+    fn size_not_a_multiple_of_the_block_size() {
+        let mut blocks = BC1_RED_BLUE.to_vec();
+        blocks.extend_from_slice(&BC1_GREEN);
+        let rgba = decompress_to_rgba(TextureFormat::Bc1RgbaUnorm, 5, 3, &blocks).unwrap();
+        assert_eq!(rgba.len(), 5 * 3 * 4);
+        let pixel = |x: usize, y: usize| &rgba[(y * 5 + x) * 4..(y * 5 + x) * 4 + 4];
+        assert_eq!(pixel(1, 0), BLUE);
+        assert_eq!(pixel(3, 0), [85, 0, 170, 255]);
+        assert_eq!(pixel(4, 0), [0, 255, 0, 255]);
+        assert_eq!(pixel(0, 2), RED);
+        assert_eq!(pixel(4, 2), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn short_input_is_none() {
+        assert!(decompress_to_rgba(TextureFormat::Bc1RgbaUnorm, 5, 3, &BC1_RED_BLUE).is_none());
+        assert!(decompress_to_rgba(ASTC_4X4_UNORM, 4, 4, &[0; 15]).is_none());
+        assert!(decompress_to_rgba(TextureFormat::Bc7RgbaUnorm, 4, 4, &[]).is_none());
+    }
+
+    #[test]
+    fn unsupported_format_is_none() {
+        let hdr = TextureFormat::Astc {
+            block: AstcBlock::B4x4,
+            channel: AstcChannel::Hdr,
+        };
+        assert!(decompress_to_rgba(hdr, 4, 4, &[0; 16]).is_none());
+        assert!(decompress_to_rgba(TextureFormat::Rgba8Unorm, 1, 1, &[0; 4]).is_none());
+    }
+
+    #[test]
+    fn fallback_formats() {
+        assert_eq!(
+            fallback_format(TextureFormat::Bc5RgSnorm),
+            TextureFormat::Rgba8Snorm
+        );
+        assert_eq!(
+            fallback_format(TextureFormat::Bc7RgbaUnormSrgb),
+            TextureFormat::Rgba8UnormSrgb
+        );
+        assert_eq!(
+            fallback_format(TextureFormat::Bc6hRgbUfloat),
+            TextureFormat::Rgba8Unorm
+        );
+    }
+}
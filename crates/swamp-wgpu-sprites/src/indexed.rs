@@ -0,0 +1,444 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+use log::warn;
+use std::fmt::{Display, Formatter};
+use std::io::Cursor;
+use swamp_wgpu::shader_validation::{BindingKind, ExpectedBinding};
+use wgpu::TextureFormat;
+
+/// Index texture format, read with `textureLoad` since indices can not be filtered.
+pub const INDEX_TEXTURE_FORMAT: TextureFormat = TextureFormat::R8Uint;
+
+/// Palette texture format, one palette per row and one color per texel.
+pub const PALETTE_TEXTURE_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+/// Bindings of [`crate::SPRITE_INDEXED_FRAGMENT_SHADER_SOURCE`], the camera is in group 0
+/// like for regular sprites.
+pub const INDEXED_BINDINGS: &[ExpectedBinding] = &[
+    ExpectedBinding::new(0, 0, BindingKind::UniformBuffer),
+    ExpectedBinding::new(1, 0, BindingKind::Other),
+    ExpectedBinding::new(1, 1, BindingKind::Texture2d),
+];
+
+/// The most colors a palette row can have, since indices are eight bits.
+pub const MAX_PALETTE_COLORS: usize = 256;
+
+/// Eight bit palette indices and the palette stored in the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedImage {
+    pub width: u32,
+    pub height: u32,
+    /// One index per pixel, rows from the top
+    pub indices: Vec<u8>,
+    /// RGBA, alpha is taken from the `tRNS` chunk if there is one
+    pub palette: Vec<[u8; 4]>,
+}
+
+impl IndexedImage {
+    /// The file palette as a single row, see [`create_palette_texture`].
+    pub fn palette_image(&self) -> image::RgbaImage {
+        palette_rows_image(&[&self.palette])
+    }
+}
+
+/// Each palette becomes a row, shorter palettes are padded with transparent black.
+pub fn palette_rows_image(palettes: &[&[[u8; 4]]]) -> image::RgbaImage {
+    let width = palettes
+        .iter()
+        .map(|palette| palette.len())
+        .max()
+        .unwrap_or(0)
+        .clamp(1, MAX_PALETTE_COLORS);
+
+    image::RgbaImage::from_fn(width as u32, palettes.len().max(1) as u32, |x, y| {
+        palettes
+            .get(y as usize)
+            .and_then(|palette| palette.get(x as usize))
+            .map_or(image::Rgba([0, 0, 0, 0]), |color| image::Rgba(*color))
+    })
+}
+
+#[derive(Debug)]
+pub enum IndexedImageError {
+    Decode(png::DecodingError),
+    NotIndexed(png::ColorType),
+    MissingPalette,
+}
+
+impl Display for IndexedImageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Decode(err) => write!(f, "could not decode indexed png: {err}"),
+            Self::NotIndexed(color_type) => {
+                write!(f, "png has color type {color_type:?}, expected indexed")
+            }
+            Self::MissingPalette => write!(f, "indexed png has no palette"),
+        }
+    }
+}
+
+impl std::error::Error for IndexedImageError {}
+
+impl From<png::DecodingError> for IndexedImageError {
+    fn from(err: png::DecodingError) -> Self {
+        Self::Decode(err)
+    }
+}
+
+/// Keeps the palette indices, unlike [`crate::decode_png`] which expands them to RGBA.
+/// Indices with less than eight bits per pixel are unpacked to one octet each.
+pub fn decode_indexed_png(octets: &[u8]) -> Result<IndexedImage, IndexedImageError> {
+    let mut decoder = png::Decoder::new(Cursor::new(octets));
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let mut reader = decoder.read_info()?;
+
+    let info = reader.info();
+    if info.color_type != png::ColorType::Indexed {
+        return Err(IndexedImageError::NotIndexed(info.color_type));
+    }
+    let rgb = info
+        .palette
+        .as_deref()
+        .ok_or(IndexedImageError::MissingPalette)?;
+    let alpha = info.trns.as_deref().unwrap_or(&[]);
+    let palette = rgb
+        .chunks_exact(3)
+        .enumerate()
+        .map(|(index, color)| {
+            [
+                color[0],
+                color[1],
+                color[2],
+                alpha.get(index).copied().unwrap_or(255),
+            ]
+        })
+        .collect();
+
+    let buffer_size = reader
+        .output_buffer_size()
+        .ok_or(png::DecodingError::LimitsExceeded)?;
+    let mut buffer = vec![0; buffer_size];
+    let frame = reader.next_frame(&mut buffer)?;
+    let bits_per_index = frame.bit_depth as usize;
+
+    let width = frame.width as usize;
+    let mut indices = Vec::with_capacity(width * frame.height as usize);
+    for row in buffer
+        .chunks_exact(frame.line_size)
+        .take(frame.height as usize)
+    {
+        if bits_per_index == 8 {
+            indices.extend_from_slice(&row[..width]);
+            continue;
+        }
+        let indices_per_octet = 8 / bits_per_index;
+        let mask = (1u8 << bits_per_index) - 1;
+        indices.extend((0..width).map(|x| {
+            // The leftmost pixel is in the high bits
+            let shift = 8 - bits_per_index * (x % indices_per_octet + 1);
+            (row[x / indices_per_octet] >> shift) & mask
+        }));
+    }
+
+    Ok(IndexedImage {
+        width: frame.width,
+        height: frame.height,
+        indices,
+        palette,
+    })
+}
+
+pub fn create_index_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &IndexedImage,
+    label: &str,
+) -> wgpu::Texture {
+    let texture = swamp_wgpu::create_texture_with_format(
+        device,
+        label,
+        image.width,
+        image.height,
+        INDEX_TEXTURE_FORMAT,
+    );
+    if let Err(err) = swamp_wgpu::write_texture_region(
+        queue,
+        &texture,
+        (0, 0),
+        (image.width, image.height),
+        &image.indices,
+    ) {
+        warn!("could not write the indices of '{label}': {err}");
+    }
+
+    texture
+}
+
+/// `palettes` has one palette per row, see [`palette_rows_image`].
+pub fn create_palette_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    palettes: &image::RgbaImage,
+    label: &str,
+) -> wgpu::Texture {
+    crate::create_texture_from_rgba(device, queue, palettes, label, PALETTE_TEXTURE_FORMAT)
+}
+
+pub fn create_indexed_bind_group_layout(
+    device: &wgpu::Device,
+    label: &str,
+) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some(label),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Uint,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+pub fn create_indexed_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    index_texture: &wgpu::Texture,
+    palette_texture: &wgpu::Texture,
+    label: &str,
+) -> wgpu::BindGroup {
+    let index_view = index_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let palette_view = palette_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(label),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&index_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&palette_view),
+            },
+        ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PALETTE: [[u8; 3]; 4] = [[0, 0, 0], [255, 0, 0], [0, 255, 0], [0, 0, 255]];
+
+    fn encode(
+        width: u32,
+        height: u32,
+        color_type: png::ColorType,
+        depth: png::BitDepth,
+        trns: Option<&[u8]>,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut octets = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut octets, width, height);
+            encoder.set_color(color_type);
+            encoder.set_depth(depth);
+            if color_type == png::ColorType::Indexed {
+                let colors = 1 << depth as usize;
+                encoder.set_palette(PALETTE.as_flattened()[..3 * colors.min(4)].to_vec());
+            }
+            if let Some(trns) = trns {
+                encoder.set_trns(trns.to_vec());
+            }
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(data).unwrap();
+        }
+        octets
+    }
+
+    /// Rows start on an octet, the leftmost pixel in the high bits.
+    fn pack(indices: &[u8], width: usize, bits: usize) -> Vec<u8> {
+        indices
+            .chunks_exact(width)
+            .flat_map(|row| {
+                row.chunks(8 / bits).map(move |pixels| {
+                    pixels
+                        .iter()
+                        .enumerate()
+                        .fold(0, |octet, (x, index)| octet | index << (8 - bits * (x + 1)))
+                })
+            })
+            .collect()
+    }
+
+    fn remove_chunk(octets: &mut Vec<u8>, chunk_type: &[u8; 4]) {
+        let mut offset = 8;
+        while offset < octets.len() {
+            let length = u32::from_be_bytes(octets[offset..offset + 4].try_into().unwrap());
+            let end = offset + 12 + length as usize;
+            if &octets[offset + 4..offset + 8] == chunk_type {
+                octets.drain(offset..end);
+                return;
+            }
+            offset = end;
+        }
+        panic!("no {chunk_type:?} chunk");
+    }
+
+    #[test]
+    fn eight_bit_indices_and_transparency() {
+        let indices = [0, 1, 2, 3, 2, 1];
+        let octets = encode(
+            3,
+            2,
+            png::ColorType::Indexed,
+            png::BitDepth::Eight,
+            Some(&[0, 128]),
+            &indices,
+        );
+
+        let image = decode_indexed_png(&octets).unwrap();
+        assert_eq!((image.width, image.height), (3, 2));
+        assert_eq!(image.indices, indices);
+        assert_eq!(
+            image.palette,
+            [
+                [0, 0, 0, 0],
+                [255, 0, 0, 128],
+                [0, 255, 0, 255],
+                [0, 0, 255, 255]
+            ]
+        );
+    }
+
+    #[test]
+    fn packed_indices_are_unpacked() {
+        // Five pixels do not fill the last octet of a row for any of the depths
+        let (width, height) = (5, 2);
+        for (depth, bits) in [
+            (png::BitDepth::One, 1),
+            (png::BitDepth::Two, 2),
+            (png::BitDepth::Four, 4),
+        ] {
+            let colors = (1u8 << bits).min(4);
+            let indices: Vec<u8> = (0..width * height)
+                .map(|index| (index * 3 % 7) as u8 % colors)
+                .collect();
+            let octets = encode(
+                width as u32,
+                height as u32,
+                png::ColorType::Indexed,
+                depth,
+                None,
+                &pack(&indices, width, bits),
+            );
+
+            let image = decode_indexed_png(&octets).unwrap();
+            assert_eq!(image.indices, indices, "{bits} bits per index");
+            assert_eq!(image.palette.len(), usize::from(colors));
+            assert!(image.palette.iter().all(|color| color[3] == 255));
+        }
+    }
+
+    #[test]
+    fn rgb_png_is_not_indexed() {
+        let octets = encode(
+            1,
+            1,
+            png::ColorType::Rgb,
+            png::BitDepth::Eight,
+            None,
+            &[1, 2, 3],
+        );
+
+        assert!(matches!(
+            decode_indexed_png(&octets),
+            Err(IndexedImageError::NotIndexed(png::ColorType::Rgb))
+        ));
+    }
+
+    #[test]
+    fn indexed_png_without_palette_is_an_error() {
+        let mut octets = encode(
+            2,
+            1,
+            png::ColorType::Indexed,
+            png::BitDepth::Eight,
+            None,
+            &[0, 1],
+        );
+        remove_chunk(&mut octets, b"PLTE");
+
+        assert!(matches!(
+            decode_indexed_png(&octets),
+            Err(IndexedImageError::MissingPalette)
+        ));
+    }
+
+    #[test]
+    fn invalid_png_is_a_decode_error() {
+        assert!(matches!(
+            decode_indexed_png(b"not a png"),
+            Err(IndexedImageError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn palette_rows_are_padded_with_transparent_black() {
+        let short: &[[u8; 4]] = &[[1, 2, 3, 4]];
+        let long: &[[u8; 4]] = &[[5, 6, 7, 8], [9, 10, 11, 12], [13, 14, 15, 16]];
+
+        let image = palette_rows_image(&[short, long]);
+        assert_eq!(image.dimensions(), (3, 2));
+        assert_eq!(image.get_pixel(0, 0).0, [1, 2, 3, 4]);
+        assert_eq!(image.get_pixel(1, 0).0, [0, 0, 0, 0]);
+        assert_eq!(image.get_pixel(2, 0).0, [0, 0, 0, 0]);
+        assert_eq!(image.get_pixel(2, 1).0, [13, 14, 15, 16]);
+    }
+
+    #[test]
+    fn palette_rows_image_is_never_empty_or_wider_than_the_indices() {
+        assert_eq!(palette_rows_image(&[]).dimensions(), (1, 1));
+        assert_eq!(palette_rows_image(&[&[]]).get_pixel(0, 0).0, [0, 0, 0, 0]);
+
+        let huge = vec![[255; 4]; MAX_PALETTE_COLORS + 10];
+        assert_eq!(
+            palette_rows_image(&[&huge]).dimensions(),
+            (MAX_PALETTE_COLORS as u32, 1)
+        );
+    }
+
+    #[test]
+    fn palette_image_is_a_single_row() {
+        let image = IndexedImage {
+            width: 1,
+            height: 1,
+            indices: vec![0],
+            palette: vec![[1, 2, 3, 4], [5, 6, 7, 8]],
+        };
+
+        let palette = image.palette_image();
+        assert_eq!(palette.dimensions(), (2, 1));
+        assert_eq!(palette.get_pixel(1, 0).0, [5, 6, 7, 8]);
+    }
+}
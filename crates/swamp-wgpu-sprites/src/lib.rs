@@ -3,14 +3,73 @@
  * Licensed under the MIT License. See LICENSE in the project root for license information.
  */
 
+mod astc_decompress;
+mod block_decompress;
+pub mod indexed;
+pub mod texture_formats;
+
 use bytemuck::{Pod, Zeroable};
 use std::ops::{Add, Index, Mul};
+use swamp_wgpu::shader_validation::{
+    BindingKind, ExpectedBinding, ShaderError, ShaderInterface, ShaderStage,
+};
+use texture_formats::TextureLoadError;
 use wgpu::util::DeviceExt;
 use wgpu::{
     BindGroupLayout, Buffer, PipelineLayout, RenderPipeline, Sampler, ShaderModule, TextureFormat,
 };
 
-#[derive(Copy, Clone)]
+pub const SPRITE_VERTEX_SHADER_SOURCE: &str = include_str!("shaders/sprite_vertex.wgsl");
+pub const SPRITE_FRAGMENT_SHADER_SOURCE: &str = include_str!("shaders/sprite_fragment.wgsl");
+/// Fragment shader for [`indexed`] materials, used with the sprite vertex shader.
+pub const SPRITE_INDEXED_FRAGMENT_SHADER_SOURCE: &str =
+    include_str!("shaders/sprite_indexed_fragment.wgsl");
+/// Vertex shader for [`SpriteVertex`] meshes, used with the sprite fragment shaders.
+pub const SPRITE_MESH_VERTEX_SHADER_SOURCE: &str = include_str!("shaders/sprite_mesh_vertex.wgsl");
+
+/// Bindings of the sprite pipeline, see [`SpriteInfo`].
+pub const SPRITE_BINDINGS: &[ExpectedBinding] = &[
+    ExpectedBinding::new(0, 0, BindingKind::UniformBuffer),
+    ExpectedBinding::new(1, 0, BindingKind::Texture2d),
+    ExpectedBinding::new(1, 1, BindingKind::Sampler),
+];
+
+/// Vertex (0..=1) and instance (2..=13) locations of the sprite vertex layout.
+pub const SPRITE_VERTEX_LOCATIONS: &[u32] = &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13];
+
+pub fn sprite_vertex_interface() -> ShaderInterface<'static> {
+    ShaderInterface {
+        entry_point: "vs_main",
+        stage: ShaderStage::Vertex,
+        bindings: SPRITE_BINDINGS,
+        vertex_locations: Some(SPRITE_VERTEX_LOCATIONS),
+    }
+}
+
+/// Locations of [`SpriteVertex`].
+pub const SPRITE_MESH_VERTEX_LOCATIONS: &[u32] = &[0, 1, 2];
+
+pub fn sprite_mesh_vertex_interface() -> ShaderInterface<'static> {
+    ShaderInterface {
+        entry_point: "vs_main",
+        stage: ShaderStage::Vertex,
+        bindings: SPRITE_BINDINGS,
+        vertex_locations: Some(SPRITE_MESH_VERTEX_LOCATIONS),
+    }
+}
+
+/// `bindings` are usually [`SPRITE_BINDINGS`], custom materials add their own group 2 bindings.
+pub fn sprite_fragment_interface(bindings: &[ExpectedBinding]) -> ShaderInterface<'_> {
+    ShaderInterface {
+        entry_point: "fs_main",
+        stage: ShaderStage::Fragment,
+        bindings,
+        vertex_locations: None,
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
 pub struct Mx4([FVec4; 4]);
 impl Mx4 {
     #[inline]
@@ -32,6 +91,55 @@ impl Mx4 {
             [x, y, z, 1.0],
         ])
     }
+
+    /// Counter clockwise around the z axis, with `y` pointing up.
+    #[inline]
+    pub fn from_rotation_z(radians: f32) -> Self {
+        let (sin, cos) = radians.sin_cos();
+        Self::from([
+            [cos, sin, 0.0, 0.0],
+            [-sin, cos, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Transforms a point in the `z = 0` plane.
+    #[inline]
+    pub fn transform_point2(&self, point: [f32; 2]) -> [f32; 2] {
+        let position = self[0] * point[0] + self[1] * point[1] + self[3];
+        [position[0], position[1]]
+    }
+
+    /// Orthographic projection to the wgpu clip space, where depth is in the range 0..1.
+    #[inline]
+    pub fn from_orthographic(
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        near: f32,
+        far: f32,
+    ) -> Self {
+        let width = right - left;
+        let height = top - bottom;
+        let depth = near - far;
+        Self::from([
+            [2.0 / width, 0.0, 0.0, 0.0],
+            [0.0, 2.0 / height, 0.0, 0.0],
+            [0.0, 0.0, 1.0 / depth, 0.0],
+            [
+                -(right + left) / width,
+                -(top + bottom) / height,
+                near / depth,
+                1.0,
+            ],
+        ])
+    }
+
+    pub fn to_cols_array_2d(&self) -> [[f32; 4]; 4] {
+        [self.0[0].0, self.0[1].0, self.0[2].0, self.0[3].0]
+    }
 }
 
 impl From<[f32; 4]> for FVec4 {
@@ -71,7 +179,7 @@ impl Add<Self> for FVec4 {
             self.0[0] + rhs.0[0],
             self.0[1] + rhs.0[1],
             self.0[2] + rhs.0[2],
-            self.0[3],
+            self.0[3] + rhs.0[3],
         ])
     }
 }
@@ -94,7 +202,8 @@ impl Mul<Self> for Mx4 {
     }
 }
 
-#[derive(Copy, Clone)]
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
 pub struct FVec4(pub [f32; 4]);
 
 impl Index<usize> for FVec4 {
@@ -106,19 +215,89 @@ impl Index<usize> for FVec4 {
     }
 }
 
+/// Per sprite data, stored in an instance buffer so all sprites in a batch
+/// are drawn with a single draw call.
 #[repr(C)]
-#[derive(Copy, Clone)]
-pub struct SpriteUniform {
+#[derive(Debug, Copy, Clone)]
+pub struct SpriteInstanceUniform {
     model: Mx4, // Transformation matrix
+    /// Texture coordinate offset in `xy` and scale in `zw`
     tex_coords: FVec4,
+    /// Repeat count in `xy` and offset in tiles in `zw`, see [`SpriteInstanceUniform::NO_TILING`]
+    tile: FVec4,
+    /// Palette texture row of indexed materials
+    palette_row: u32,
+    effects: SpriteEffects,
+    /// Straight alpha sRGB, multiplied with the texture color
+    tint: [u8; 4],
+}
+
+/// Outline, drop shadow and silhouette of the built-in sprite fragment shader.
+/// Colors are straight alpha sRGB, an alpha of zero turns the effect off.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct SpriteEffects {
+    /// The size of one texel in unit quad coordinates
+    pub texel_size: [f32; 2],
+    /// In texels, with `y` pointing down like texture coordinates
+    pub shadow_offset: [f32; 2],
+    pub outline_color: [u8; 4],
+    pub shadow_color: [u8; 4],
+    pub silhouette_color: [u8; 4],
 }
 
-unsafe impl Pod for SpriteUniform {}
-unsafe impl Zeroable for SpriteUniform {}
+unsafe impl Pod for SpriteEffects {}
+unsafe impl Zeroable for SpriteEffects {}
+
+unsafe impl Pod for SpriteInstanceUniform {}
+unsafe impl Zeroable for SpriteInstanceUniform {}
+
+impl SpriteInstanceUniform {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 12] = wgpu::vertex_attr_array![
+        2 => Float32x4,
+        3 => Float32x4,
+        4 => Float32x4,
+        5 => Float32x4,
+        6 => Float32x4,
+        7 => Float32x4,
+        8 => Uint32,
+        9 => Float32x4,
+        10 => Unorm8x4,
+        11 => Unorm8x4,
+        12 => Unorm8x4,
+        13 => Unorm8x4
+    ];
+
+    /// The texture rect is shown once, stretched over the quad.
+    pub const NO_TILING: FVec4 = FVec4([1.0, 1.0, 0.0, 0.0]);
+
+    pub fn new(model: Mx4, tex_coords: FVec4, tile: FVec4, palette_row: u32) -> Self {
+        Self {
+            model,
+            tex_coords,
+            tile,
+            palette_row,
+            effects: SpriteEffects::default(),
+            tint: [255; 4],
+        }
+    }
+
+    pub fn with_tint(mut self, tint: [u8; 4]) -> Self {
+        self.tint = tint;
+        self
+    }
 
-impl SpriteUniform {
-    pub fn new(model: Mx4, tex_coords: FVec4) -> Self {
-        Self { model, tex_coords }
+    pub fn with_effects(mut self, effects: SpriteEffects) -> Self {
+        self.effects = effects;
+        self
+    }
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
     }
 }
 
@@ -245,28 +424,52 @@ impl Vertex {
     }
 }
 
-// wgpu has, for very unknown reasons, put coordinate texture origo at top-left(!)
-//const RIGHT: f32 = 128.0 / 512.0;
-//const DOWN: f32 = 128.0 / 1024.0;
+/// A vertex of a mesh that is drawn without instances, e.g. trails and deformed sprites.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct SpriteVertex {
+    /// In pixels
+    pub position: [f32; 2],
+    /// 0.0 to 1.0 over the whole texture, with `v` pointing down
+    pub tex_coords: [f32; 2],
+    /// Straight alpha sRGB, multiplied with the texture color
+    pub color: [u8; 4],
+}
+
+unsafe impl Zeroable for SpriteVertex {}
+unsafe impl Pod for SpriteVertex {}
 
-const RIGHT: f32 = 32.0 / 128.0;
-const DOWN: f32 = 32.0 / 64.0;
+impl SpriteVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Unorm8x4];
 
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+// wgpu has, for very unknown reasons, put coordinate texture origo at top-left(!)
+// The quad is a unit square with the origin in the bottom left corner, it is scaled
+// to the sprite size by the model matrix in the instance data.
 const VERTICES: &[Vertex] = &[
     Vertex {
-        position: [-0.5, -0.5],
-        tex_coords: [0.0, DOWN],
+        position: [0.0, 0.0],
+        tex_coords: [0.0, 1.0],
     }, // Bottom left
     Vertex {
-        position: [0.5, -0.5],
-        tex_coords: [RIGHT, DOWN],
+        position: [1.0, 0.0],
+        tex_coords: [1.0, 1.0],
     }, // Bottom right
     Vertex {
-        position: [0.5, 0.5],
-        tex_coords: [RIGHT, 0.0],
+        position: [1.0, 1.0],
+        tex_coords: [1.0, 0.0],
     }, // Top right
     Vertex {
-        position: [-0.5, 0.5],
+        position: [0.0, 1.0],
         tex_coords: [0.0, 0.0],
     }, // Top left
 ];
@@ -274,11 +477,19 @@ const VERTICES: &[Vertex] = &[
 // u16 is the smallest index buffer supported by wgpu // IndexFormat
 pub const INDICES: &[u16] = &[0, 1, 2, 0, 2, 3];
 
+/// The sprite pipeline uses the bind groups:
+/// - group 0: camera uniform with the view projection matrix
+/// - group 1: texture (binding 0) and sampler (binding 1) of the material
+/// - group 2: optional, used by custom materials
 #[derive(Debug)]
 pub struct SpriteInfo {
     pub pipeline: RenderPipeline,
+    pub camera_bind_group_layout: BindGroupLayout,
     pub bind_group_layout: BindGroupLayout,
     pub sampler: Sampler,
+    pub vertex_shader: ShaderModule,
+    pub fragment_shader: ShaderModule,
+    pub surface_texture_format: TextureFormat,
 }
 
 impl SpriteInfo {
@@ -287,17 +498,27 @@ impl SpriteInfo {
         surface_texture_format: TextureFormat,
         vertex_shader_source: &str,
         fragment_shader_source: &str,
-    ) -> Self {
-        let vertex_shader =
-            swamp_wgpu::create_shader_module(device, "sprite vertex", vertex_shader_source);
-        let fragment_shader =
-            swamp_wgpu::create_shader_module(device, "sprite fragment", fragment_shader_source);
+    ) -> Result<Self, ShaderError> {
+        let vertex_shader = swamp_wgpu::shader_validation::create_shader_module_checked(
+            device,
+            "sprite vertex",
+            vertex_shader_source,
+            &sprite_vertex_interface(),
+        )?;
+        let fragment_shader = swamp_wgpu::shader_validation::create_shader_module_checked(
+            device,
+            "sprite fragment",
+            fragment_shader_source,
+            &sprite_fragment_interface(SPRITE_BINDINGS),
+        )?;
 
+        let camera_bind_group_layout =
+            swamp_wgpu::create_uniform_bind_group_layout(device, "sprite camera bind group layout");
         let bind_group_layout = create_sprite_bind_group_layout(device, "sprite bind group layout");
-        let default_layout = swamp_wgpu::create_pipeline_layout(
+        let default_layout = swamp_wgpu::create_pipeline_layout_with_groups(
             device,
             "sprite pipeline layout",
-            &bind_group_layout,
+            &[&camera_bind_group_layout, &bind_group_layout],
         );
 
         let pipeline = create_sprite_pipeline(
@@ -310,11 +531,15 @@ impl SpriteInfo {
 
         let sampler = swamp_wgpu::create_nearest_sampler(device, "sprite nearest sampler");
 
-        Self {
+        Ok(Self {
             pipeline,
+            camera_bind_group_layout,
             bind_group_layout,
             sampler,
-        }
+            vertex_shader,
+            fragment_shader,
+            surface_texture_format,
+        })
     }
 }
 
@@ -324,12 +549,61 @@ pub fn load_texture_from_memory(
     octets: &[u8],
     label: &str,
 ) -> wgpu::Texture {
-    let img = image::load_from_memory_with_format(octets, image::ImageFormat::Png)
-        .expect("Failed to load image");
-    let img = img.to_rgba8();
+    try_load_texture_from_memory(device, queue, octets, label).expect("Failed to load image")
+}
+
+/// Detects the image file format, see [`texture_formats::load_texture`].
+pub fn try_load_texture_from_memory(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    octets: &[u8],
+    label: &str,
+) -> Result<wgpu::Texture, TextureLoadError> {
+    texture_formats::load_texture(device, queue, octets, None, label)
+}
+
+/// Loads a texture that holds data instead of colors (e.g. lookup tables),
+/// so the values are not converted from sRGB when sampled.
+pub fn load_data_texture_from_memory(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    octets: &[u8],
+    label: &str,
+) -> wgpu::Texture {
+    try_load_data_texture_from_memory(device, queue, octets, label).expect("Failed to load image")
+}
+
+/// Like [`load_data_texture_from_memory`], for PNG files that may be invalid.
+pub fn try_load_data_texture_from_memory(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    octets: &[u8],
+    label: &str,
+) -> Result<wgpu::Texture, image::ImageError> {
+    let img = decode_png(octets)?;
+    Ok(create_texture_from_rgba(
+        device,
+        queue,
+        &img,
+        label,
+        TextureFormat::Rgba8Unorm,
+    ))
+}
+
+pub fn decode_png(octets: &[u8]) -> Result<image::RgbaImage, image::ImageError> {
+    let img = image::load_from_memory_with_format(octets, image::ImageFormat::Png)?;
+    Ok(img.to_rgba8())
+}
+
+pub fn create_texture_from_rgba(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    img: &image::RgbaImage,
+    label: &str,
+    format: TextureFormat,
+) -> wgpu::Texture {
     let (width, height) = img.dimensions();
 
-    // Create the texture and upload the data (same as before)
     let texture = device.create_texture(&wgpu::TextureDescriptor {
         label: Some(label),
         size: wgpu::Extent3d {
@@ -340,19 +614,28 @@ pub fn load_texture_from_memory(
         mip_level_count: 1,
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
-        format: TextureFormat::Rgba8UnormSrgb,
+        format,
         usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-        view_formats: &[TextureFormat::Rgba8UnormSrgb],
+        view_formats: &[format],
     });
 
+    write_rgba_to_texture(queue, &texture, img);
+
+    texture
+}
+
+/// Uploads the whole image, which must have the same size as the texture.
+pub fn write_rgba_to_texture(queue: &wgpu::Queue, texture: &wgpu::Texture, img: &image::RgbaImage) {
+    let (width, height) = img.dimensions();
+
     queue.write_texture(
         wgpu::ImageCopyTexture {
-            texture: &texture,
+            texture,
             mip_level: 0,
             origin: wgpu::Origin3d::ZERO,
             aspect: wgpu::TextureAspect::All,
         },
-        &img,
+        img,
         wgpu::ImageDataLayout {
             offset: 0,
             bytes_per_row: Some(4 * width),
@@ -364,8 +647,6 @@ pub fn load_texture_from_memory(
             depth_or_array_layers: 1,
         },
     );
-
-    texture
 }
 
 pub fn create_sprite_vertex_buffer(device: &wgpu::Device, label: &str) -> Buffer {
@@ -376,6 +657,48 @@ pub fn create_sprite_vertex_buffer(device: &wgpu::Device, label: &str) -> Buffer
     })
 }
 
+pub fn create_sprite_instance_buffer(
+    device: &wgpu::Device,
+    label: &str,
+    capacity: usize,
+) -> Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: (capacity.max(1) * size_of::<SpriteInstanceUniform>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Rewritten every frame, `capacity` is in vertices.
+pub fn create_sprite_mesh_vertex_buffer(
+    device: &wgpu::Device,
+    label: &str,
+    capacity: usize,
+) -> Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: (capacity.max(1) * size_of::<SpriteVertex>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Rewritten every frame, `capacity` is in `u16` indices and rounded up to whole copy units.
+pub fn create_sprite_mesh_index_buffer(
+    device: &wgpu::Device,
+    label: &str,
+    capacity: usize,
+) -> Buffer {
+    let size = (capacity.max(1) * size_of::<u16>()) as wgpu::BufferAddress;
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: size.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT),
+        usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
 pub fn create_sprite_index_buffer(device: &wgpu::Device, label: &str) -> Buffer {
     device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some(label),
@@ -390,16 +713,6 @@ pub fn create_sprite_bind_group_layout(device: &wgpu::Device, label: &str) -> Bi
         entries: &[
             wgpu::BindGroupLayoutEntry {
                 binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 1,
                 visibility: wgpu::ShaderStages::FRAGMENT,
                 ty: wgpu::BindingType::Texture {
                     multisampled: false,
@@ -409,7 +722,7 @@ pub fn create_sprite_bind_group_layout(device: &wgpu::Device, label: &str) -> Bi
                 count: None,
             },
             wgpu::BindGroupLayoutEntry {
-                binding: 2,
+                binding: 1,
                 visibility: wgpu::ShaderStages::FRAGMENT,
                 ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                 count: None,
@@ -418,21 +731,133 @@ pub fn create_sprite_bind_group_layout(device: &wgpu::Device, label: &str) -> Bi
     })
 }
 
-fn create_sprite_pipeline(
+/// How sprite colors are combined with what is already drawn.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    #[default]
+    Alpha,
+    /// Brightens, e.g. for fire, sparks and magic
+    Additive,
+    /// Darkens, e.g. for smoke shadows and stains.
+    /// Transparent texels should be black, which is what most image editors write.
+    Multiply,
+}
+
+impl BlendMode {
+    pub fn blend_state(self) -> wgpu::BlendState {
+        match self {
+            Self::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+            Self::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+            Self::Multiply => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Dst,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+        }
+    }
+}
+
+/// Also used for custom materials, which share the sprite vertex shader and vertex layout
+/// but have their own fragment shader and an extra bind group.
+pub fn create_sprite_pipeline(
+    device: &wgpu::Device,
+    format: TextureFormat,
+    pipeline_layout: &PipelineLayout,
+    vertex_shader: &ShaderModule,
+    fragment_shader: &ShaderModule,
+) -> RenderPipeline {
+    create_sprite_pipeline_with_blend(
+        device,
+        format,
+        pipeline_layout,
+        vertex_shader,
+        fragment_shader,
+        BlendMode::Alpha,
+    )
+}
+
+pub fn create_sprite_pipeline_with_blend(
+    device: &wgpu::Device,
+    format: TextureFormat,
+    pipeline_layout: &PipelineLayout,
+    vertex_shader: &ShaderModule,
+    fragment_shader: &ShaderModule,
+    blend: BlendMode,
+) -> RenderPipeline {
+    create_pipeline(
+        device,
+        &format!("Sprite {blend:?} Blend Pipeline"),
+        format,
+        pipeline_layout,
+        vertex_shader,
+        &[Vertex::desc(), SpriteInstanceUniform::desc()],
+        fragment_shader,
+        blend,
+        Some(wgpu::Face::Back),
+    )
+}
+
+/// For [`SpriteVertex`] meshes drawn with [`SPRITE_MESH_VERTEX_SHADER_SOURCE`]. Both sides
+/// of the triangles are drawn, since trails and deformed meshes can fold over.
+pub fn create_sprite_mesh_pipeline(
     device: &wgpu::Device,
     format: TextureFormat,
     pipeline_layout: &PipelineLayout,
     vertex_shader: &ShaderModule,
     fragment_shader: &ShaderModule,
+    blend: BlendMode,
+) -> RenderPipeline {
+    create_pipeline(
+        device,
+        &format!("Sprite Mesh {blend:?} Blend Pipeline"),
+        format,
+        pipeline_layout,
+        vertex_shader,
+        &[SpriteVertex::desc()],
+        fragment_shader,
+        blend,
+        None,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    format: TextureFormat,
+    pipeline_layout: &PipelineLayout,
+    vertex_shader: &ShaderModule,
+    buffers: &[wgpu::VertexBufferLayout],
+    fragment_shader: &ShaderModule,
+    blend: BlendMode,
+    cull_mode: Option<wgpu::Face>,
 ) -> RenderPipeline {
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("Sprite Alpha Blend Pipeline"),
+        label: Some(label),
         layout: Some(pipeline_layout),
         vertex: wgpu::VertexState {
             module: vertex_shader,
             entry_point: Some("vs_main"),
             compilation_options: Default::default(),
-            buffers: &[Vertex::desc()],
+            buffers,
         },
         fragment: Some(wgpu::FragmentState {
             module: fragment_shader,
@@ -440,7 +865,7 @@ fn create_sprite_pipeline(
             compilation_options: Default::default(),
             targets: &[Some(wgpu::ColorTargetState {
                 format,
-                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                blend: Some(blend.blend_state()),
                 write_mask: wgpu::ColorWrites::ALL,
             })],
         }),
@@ -448,7 +873,7 @@ fn create_sprite_pipeline(
             topology: wgpu::PrimitiveTopology::TriangleList,
             strip_index_format: None,
             front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
+            cull_mode,
             unclipped_depth: false,
             polygon_mode: wgpu::PolygonMode::Fill,
             ..Default::default()
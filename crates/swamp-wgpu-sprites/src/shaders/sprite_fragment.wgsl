@@ -0,0 +1,75 @@
+@group(1) @binding(0) var texture: texture_2d<f32>;
+@group(1) @binding(1) var texture_sampler: sampler;
+
+struct FragmentInput {
+    @location(1) tex_coords: vec2<f32>,
+    @location(3) @interpolate(flat) tile_rect: vec4<f32>,
+    @location(5) unit_coords: vec2<f32>,
+    @location(6) @interpolate(flat) tile: vec4<f32>,
+    @location(7) @interpolate(flat) effect: vec4<f32>,
+    @location(8) @interpolate(flat) outline_color: vec4<f32>,
+    @location(9) @interpolate(flat) shadow_color: vec4<f32>,
+    @location(10) @interpolate(flat) silhouette_color: vec4<f32>,
+    @location(12) tint: vec4<f32>,
+};
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+// Straight alpha `top` over `bottom`
+fn over(top: vec4<f32>, bottom: vec4<f32>) -> vec4<f32> {
+    let alpha = top.a + bottom.a * (1.0 - top.a);
+    if (alpha <= 0.0) {
+        return vec4<f32>(0.0);
+    }
+    let rgb = (top.rgb * top.a + bottom.rgb * bottom.a * (1.0 - top.a)) / alpha;
+    return vec4<f32>(rgb, alpha);
+}
+
+// Samples the sprite at unit quad coordinates, wrapping inside the tile rect so neighbouring
+// texels never come from adjacent atlas frames. Transparent outside of the sprite.
+fn sample_sprite(input: FragmentInput, unit_coords: vec2<f32>, grad_x: vec2<f32>, grad_y: vec2<f32>) -> vec4<f32> {
+    let tile_coords = input.tile.zw + unit_coords * input.tile.xy;
+    let wrapped = input.tile_rect.xy + fract(tile_coords) * input.tile_rect.zw;
+    let color = textureSampleGrad(texture, texture_sampler, wrapped, grad_x, grad_y);
+    let inside = all(unit_coords >= vec2<f32>(0.0)) && all(unit_coords <= vec2<f32>(1.0));
+    return select(vec4<f32>(0.0), color, inside);
+}
+
+@fragment
+fn fs_main(input: FragmentInput) -> @location(0) vec4<f32> {
+    // The gradients come from the unwrapped coordinates to avoid seams at the tile edges
+    let grad_x = dpdx(input.tex_coords);
+    let grad_y = dpdy(input.tex_coords);
+    let texel = input.effect.xy;
+
+    // Sample the texture with nearest filtering for hard pixel edges
+    let tint = vec4<f32>(srgb_to_linear(input.tint.rgb), input.tint.a);
+    let base = sample_sprite(input, input.unit_coords, grad_x, grad_y) * tint;
+    var color = base;
+
+    if (input.silhouette_color.a > 0.0) {
+        color = vec4<f32>(srgb_to_linear(input.silhouette_color.rgb), base.a * input.silhouette_color.a);
+    }
+
+    if (input.outline_color.a > 0.0) {
+        let right = sample_sprite(input, input.unit_coords + vec2<f32>(texel.x, 0.0), grad_x, grad_y).a;
+        let left = sample_sprite(input, input.unit_coords - vec2<f32>(texel.x, 0.0), grad_x, grad_y).a;
+        let down = sample_sprite(input, input.unit_coords + vec2<f32>(0.0, texel.y), grad_x, grad_y).a;
+        let up = sample_sprite(input, input.unit_coords - vec2<f32>(0.0, texel.y), grad_x, grad_y).a;
+        let coverage = max(max(right, left), max(down, up));
+        let outline = vec4<f32>(srgb_to_linear(input.outline_color.rgb), coverage * input.outline_color.a);
+        color = over(color, outline);
+    }
+
+    if (input.shadow_color.a > 0.0) {
+        let coverage = sample_sprite(input, input.unit_coords - input.effect.zw * texel, grad_x, grad_y).a;
+        let shadow = vec4<f32>(srgb_to_linear(input.shadow_color.rgb), coverage * input.shadow_color.a);
+        color = over(color, shadow);
+    }
+
+    return color;
+}
@@ -0,0 +1,25 @@
+@group(1) @binding(0) var index_texture: texture_2d<u32>;
+@group(1) @binding(1) var palette_texture: texture_2d<f32>; // One palette per row
+
+@fragment
+fn fs_main(
+    @location(2) tile_coords: vec2<f32>,
+    @location(3) @interpolate(flat) tile_rect: vec4<f32>,
+    @location(4) @interpolate(flat) palette_row: u32,
+    @location(12) tint: vec4<f32>,
+) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(index_texture));
+    let wrapped = tile_rect.xy + fract(tile_coords) * tile_rect.zw;
+    let texel = clamp(vec2<i32>(floor(wrapped * size)), vec2<i32>(0), vec2<i32>(size) - 1);
+
+    // Indices can not be filtered, so both textures are read without a sampler
+    let index = textureLoad(index_texture, texel, 0).r;
+    let palette_size = vec2<i32>(textureDimensions(palette_texture));
+    let row = min(i32(palette_row), palette_size.y - 1);
+    if (i32(index) >= palette_size.x) {
+        return vec4<f32>(0.0);
+    }
+
+    // The tint is applied without converting from sRGB, which is close enough for team colors
+    return textureLoad(palette_texture, vec2<i32>(i32(index), row), 0) * tint;
+}
@@ -0,0 +1,49 @@
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,   // In pixels
+    @location(1) tex_coords: vec2<f32>, // Texture coordinates, 0..1 over the whole texture
+    @location(2) color: vec4<f32>,      // sRGB, multiplied with the texture color
+};
+
+// Same outputs as the sprite vertex shader, so the sprite fragment shaders can be reused.
+// The whole texture is a single tile and all effects are off.
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) tile_coords: vec2<f32>,
+    @location(3) @interpolate(flat) tile_rect: vec4<f32>,
+    @location(4) @interpolate(flat) palette_row: u32,
+    @location(5) unit_coords: vec2<f32>,
+    @location(6) @interpolate(flat) tile: vec4<f32>,
+    @location(7) @interpolate(flat) effect: vec4<f32>,
+    @location(8) @interpolate(flat) outline_color: vec4<f32>,
+    @location(9) @interpolate(flat) shadow_color: vec4<f32>,
+    @location(10) @interpolate(flat) silhouette_color: vec4<f32>,
+    @location(11) world_position: vec2<f32>,
+    @location(12) tint: vec4<f32>,
+};
+
+@vertex
+fn vs_main(vertex: VertexInput) -> VertexOutput {
+    var output: VertexOutput;
+    output.position = camera.view_proj * vec4<f32>(vertex.position, 0.0, 1.0);
+    output.tex_coords = vertex.tex_coords;
+    output.tile_coords = vertex.tex_coords;
+    output.tile_rect = vec4<f32>(0.0, 0.0, 1.0, 1.0);
+    output.palette_row = 0u;
+    output.unit_coords = vertex.tex_coords;
+    output.tile = vec4<f32>(1.0, 1.0, 0.0, 0.0);
+    output.effect = vec4<f32>(0.0);
+    output.outline_color = vec4<f32>(0.0);
+    output.shadow_color = vec4<f32>(0.0);
+    output.silhouette_color = vec4<f32>(0.0);
+    output.world_position = vertex.position;
+    output.tint = vertex.color;
+
+    return output;
+}
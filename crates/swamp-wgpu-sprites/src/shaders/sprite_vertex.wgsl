@@ -0,0 +1,73 @@
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,   // Unit quad position
+    @location(1) tex_coords: vec2<f32>, // Unit quad texture coordinates
+};
+
+struct InstanceInput {
+    @location(2) model_0: vec4<f32>,
+    @location(3) model_1: vec4<f32>,
+    @location(4) model_2: vec4<f32>,
+    @location(5) model_3: vec4<f32>,
+    @location(6) tex_coords: vec4<f32>,        // Offset in xy, scale in zw
+    @location(7) tile: vec4<f32>,              // Repeat count in xy, offset in tiles in zw
+    @location(8) palette_row: u32,             // Only used by indexed materials
+    @location(9) effect: vec4<f32>,            // Texel size in unit quad coordinates in xy, shadow offset in texels in zw
+    @location(10) outline_color: vec4<f32>,    // sRGB, alpha zero when there is no outline
+    @location(11) shadow_color: vec4<f32>,     // sRGB, alpha zero when there is no drop shadow
+    @location(12) silhouette_color: vec4<f32>, // sRGB, alpha zero when the texture colors are shown
+    @location(13) tint: vec4<f32>,             // sRGB, multiplied with the texture color
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,                       // Clip space position
+    @location(1) tex_coords: vec2<f32>,                           // Texture coordinates, not wrapped
+    @location(2) tile_coords: vec2<f32>,                          // Position in tiles
+    @location(3) @interpolate(flat) tile_rect: vec4<f32>,         // Texture rect of one tile
+    @location(4) @interpolate(flat) palette_row: u32,
+    @location(5) unit_coords: vec2<f32>,                          // Outside of 0..1 in the effect margin
+    @location(6) @interpolate(flat) tile: vec4<f32>,
+    @location(7) @interpolate(flat) effect: vec4<f32>,
+    @location(8) @interpolate(flat) outline_color: vec4<f32>,
+    @location(9) @interpolate(flat) shadow_color: vec4<f32>,
+    @location(10) @interpolate(flat) silhouette_color: vec4<f32>,
+    @location(11) world_position: vec2<f32>,                      // In pixels, used for lighting
+    @location(12) tint: vec4<f32>,
+};
+
+@vertex
+fn vs_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+
+    // The quad grows to make room for the outline and the drop shadow
+    let outline_texels = select(0.0, 1.0, instance.outline_color.a > 0.0);
+    let shadow_texels = select(vec2<f32>(0.0), abs(instance.effect.zw), instance.shadow_color.a > 0.0);
+    let margin = max(vec2<f32>(outline_texels), shadow_texels) * instance.effect.xy;
+    let position = vertex.position * (1.0 + 2.0 * margin) - margin;
+    let unit_coords = vertex.tex_coords * (1.0 + 2.0 * margin) - margin;
+    let tile_coords = instance.tile.zw + unit_coords * instance.tile.xy;
+
+    let world_position = model * vec4<f32>(position, 0.0, 1.0);
+
+    var output: VertexOutput;
+    output.position = camera.view_proj * world_position;
+    output.tex_coords = instance.tex_coords.xy + tile_coords * instance.tex_coords.zw;
+    output.tile_coords = tile_coords;
+    output.tile_rect = instance.tex_coords;
+    output.palette_row = instance.palette_row;
+    output.unit_coords = unit_coords;
+    output.tile = instance.tile;
+    output.effect = instance.effect;
+    output.outline_color = instance.outline_color;
+    output.shadow_color = instance.shadow_color;
+    output.silhouette_color = instance.silhouette_color;
+    output.world_position = world_position.xy;
+    output.tint = instance.tint;
+
+    return output;
+}
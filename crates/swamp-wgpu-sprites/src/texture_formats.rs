@@ -0,0 +1,480 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+use crate::block_decompress::{decompress_to_rgba, fallback_format};
+use ddsfile::{D3DFormat, Dds, DxgiFormat};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use wgpu::{AstcBlock, AstcChannel, TextureFormat};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImageFileFormat {
+    Png,
+    Qoi,
+    Tga,
+    Bmp,
+    WebP,
+    Dds,
+    Ktx2,
+}
+
+const KTX2_MAGIC: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+impl ImageFileFormat {
+    /// Detects the format from the magic octets at the start of the file.
+    ///
+    /// Only TGA 2.0 files, with the footer, can be detected as TGA.
+    pub fn detect(octets: &[u8]) -> Option<Self> {
+        if octets.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if octets.starts_with(b"qoif") {
+            Some(Self::Qoi)
+        } else if octets.starts_with(b"DDS ") {
+            Some(Self::Dds)
+        } else if octets.starts_with(&KTX2_MAGIC) {
+            Some(Self::Ktx2)
+        } else if octets.starts_with(b"RIFF") && octets.get(8..12) == Some(b"WEBP") {
+            Some(Self::WebP)
+        } else if octets.starts_with(b"BM") {
+            Some(Self::Bmp)
+        } else if octets.ends_with(b"TRUEVISION-XFILE.\0") {
+            Some(Self::Tga)
+        } else {
+            None
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "qoi" => Some(Self::Qoi),
+            "tga" => Some(Self::Tga),
+            "bmp" => Some(Self::Bmp),
+            "webp" => Some(Self::WebP),
+            "dds" => Some(Self::Dds),
+            "ktx2" => Some(Self::Ktx2),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_extension(path.extension()?.to_str()?)
+    }
+
+    fn image_format(self) -> Option<image::ImageFormat> {
+        match self {
+            Self::Png => Some(image::ImageFormat::Png),
+            Self::Qoi => Some(image::ImageFormat::Qoi),
+            Self::Tga => Some(image::ImageFormat::Tga),
+            Self::Bmp => Some(image::ImageFormat::Bmp),
+            Self::WebP => Some(image::ImageFormat::WebP),
+            Self::Dds | Self::Ktx2 => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureLoadError {
+    UnknownFormat,
+    Decode(image::ImageError),
+    Dds(ddsfile::Error),
+    Ktx2(ktx2::ParseError),
+    /// The container is valid, but the pixel format, layout or supercompression is not handled
+    Unsupported(String),
+    /// The device can not sample the format and there is no CPU decoder for it
+    NoFallbackDecoder(TextureFormat),
+}
+
+impl Display for TextureLoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownFormat => write!(f, "unknown image file format"),
+            Self::Decode(err) => write!(f, "could not decode image: {err}"),
+            Self::Dds(err) => write!(f, "could not read DDS: {err}"),
+            Self::Ktx2(err) => write!(f, "could not read KTX2: {err}"),
+            Self::Unsupported(description) => write!(f, "unsupported texture: {description}"),
+            Self::NoFallbackDecoder(format) => write!(
+                f,
+                "{format:?} is not supported by the device and can not be decompressed on the CPU"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TextureLoadError {}
+
+impl From<image::ImageError> for TextureLoadError {
+    fn from(err: image::ImageError) -> Self {
+        Self::Decode(err)
+    }
+}
+
+impl From<ddsfile::Error> for TextureLoadError {
+    fn from(err: ddsfile::Error) -> Self {
+        Self::Dds(err)
+    }
+}
+
+impl From<ktx2::ParseError> for TextureLoadError {
+    fn from(err: ktx2::ParseError) -> Self {
+        Self::Ktx2(err)
+    }
+}
+
+/// Loads a color texture from an image file in memory. The format is detected if `format` is `None`.
+///
+/// Block compressed DDS and KTX2 textures are uploaded as is when the device supports the format,
+/// otherwise all mip levels are decompressed to RGBA on the CPU.
+pub fn load_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    octets: &[u8],
+    format: Option<ImageFileFormat>,
+    label: &str,
+) -> Result<wgpu::Texture, TextureLoadError> {
+    let format = format
+        .or_else(|| ImageFileFormat::detect(octets))
+        .ok_or(TextureLoadError::UnknownFormat)?;
+
+    match format {
+        ImageFileFormat::Dds => load_dds(device, queue, octets, label),
+        ImageFileFormat::Ktx2 => load_ktx2(device, queue, octets, label),
+        _ => {
+            let img = decode_image(octets, Some(format))?;
+            Ok(crate::create_texture_from_rgba(
+                device,
+                queue,
+                &img,
+                label,
+                TextureFormat::Rgba8UnormSrgb,
+            ))
+        }
+    }
+}
+
+/// Decodes one of the uncompressed formats to RGBA. The format is detected if `format` is `None`.
+pub fn decode_image(
+    octets: &[u8],
+    format: Option<ImageFileFormat>,
+) -> Result<image::RgbaImage, TextureLoadError> {
+    let format = format
+        .or_else(|| ImageFileFormat::detect(octets))
+        .ok_or(TextureLoadError::UnknownFormat)?;
+    let image_format = format.image_format().ok_or_else(|| {
+        TextureLoadError::Unsupported(format!("{format:?} can not be decoded to RGBA"))
+    })?;
+
+    Ok(image::load_from_memory_with_format(octets, image_format)?.to_rgba8())
+}
+
+/// Mip levels of a texture in a format that wgpu understands, as stored in the file.
+struct EncodedTexture<'a> {
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    levels: Vec<&'a [u8]>,
+}
+
+fn level_octet_size(format: TextureFormat, width: u32, height: u32, level: u32) -> u32 {
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format.block_copy_size(None).unwrap_or(4);
+    let level_width = (width >> level).max(1);
+    let level_height = (height >> level).max(1);
+
+    level_width.div_ceil(block_width) * level_height.div_ceil(block_height) * block_size
+}
+
+fn load_dds(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    octets: &[u8],
+    label: &str,
+) -> Result<wgpu::Texture, TextureLoadError> {
+    let dds = Dds::read(octets)?;
+    let format = if let Some(dxgi_format) = dds.get_dxgi_format() {
+        dxgi_texture_format(dxgi_format)
+            .ok_or_else(|| TextureLoadError::Unsupported(format!("DXGI format {dxgi_format:?}")))?
+    } else if let Some(d3d_format) = dds.get_d3d_format() {
+        d3d_texture_format(d3d_format)
+            .ok_or_else(|| TextureLoadError::Unsupported(format!("D3D format {d3d_format:?}")))?
+    } else {
+        return Err(TextureLoadError::Unsupported(
+            "DDS pixel format".to_string(),
+        ));
+    };
+
+    if dds.get_depth() > 1 || dds.get_num_array_layers() > 1 {
+        return Err(TextureLoadError::Unsupported(
+            "DDS volume, cube map or array texture".to_string(),
+        ));
+    }
+
+    let width = dds.get_width();
+    let height = dds.get_height();
+    let mut data = dds.get_data(0)?;
+    let mut levels = Vec::new();
+    for level in 0..dds.get_num_mipmap_levels().max(1) {
+        let size = level_octet_size(format, width, height, level) as usize;
+        if data.len() < size {
+            return Err(TextureLoadError::Dds(ddsfile::Error::ShortFile));
+        }
+        let (level_data, rest) = data.split_at(size);
+        levels.push(level_data);
+        data = rest;
+    }
+
+    create_encoded_texture(
+        device,
+        queue,
+        &EncodedTexture {
+            format,
+            width,
+            height,
+            levels,
+        },
+        label,
+    )
+}
+
+fn load_ktx2(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    octets: &[u8],
+    label: &str,
+) -> Result<wgpu::Texture, TextureLoadError> {
+    let reader = ktx2::Reader::new(octets)?;
+    let header = reader.header();
+
+    if let Some(scheme) = header.supercompression_scheme {
+        return Err(TextureLoadError::Unsupported(format!(
+            "KTX2 supercompression {scheme:?}"
+        )));
+    }
+    let ktx_format = header.format.ok_or_else(|| {
+        TextureLoadError::Unsupported("KTX2 without a Vulkan format (Basis Universal)".to_string())
+    })?;
+    let format = ktx2_texture_format(ktx_format)
+        .ok_or_else(|| TextureLoadError::Unsupported(format!("KTX2 format {ktx_format:?}")))?;
+
+    if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+        return Err(TextureLoadError::Unsupported(
+            "KTX2 volume, cube map or array texture".to_string(),
+        ));
+    }
+
+    let width = header.pixel_width;
+    let height = header.pixel_height.max(1);
+    let mut levels = Vec::new();
+    for (level, level_data) in reader.levels().enumerate() {
+        if level_data.len() < level_octet_size(format, width, height, level as u32) as usize {
+            return Err(TextureLoadError::Ktx2(ktx2::ParseError::UnexpectedEnd));
+        }
+        levels.push(level_data);
+    }
+
+    create_encoded_texture(
+        device,
+        queue,
+        &EncodedTexture {
+            format,
+            width,
+            height,
+            levels,
+        },
+        label,
+    )
+}
+
+fn create_encoded_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    encoded: &EncodedTexture,
+    label: &str,
+) -> Result<wgpu::Texture, TextureLoadError> {
+    let format = encoded.format;
+    let (block_width, block_height) = format.block_dimensions();
+    let is_supported = device.features().contains(format.required_features())
+        && encoded.width.is_multiple_of(block_width)
+        && encoded.height.is_multiple_of(block_height);
+
+    if !is_supported {
+        let decompressed = encoded
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(level, level_data)| {
+                let level = level as u32;
+                decompress_to_rgba(
+                    format,
+                    (encoded.width >> level).max(1),
+                    (encoded.height >> level).max(1),
+                    level_data,
+                )
+                .ok_or(TextureLoadError::NoFallbackDecoder(format))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        return create_encoded_texture(
+            device,
+            queue,
+            &EncodedTexture {
+                format: fallback_format(format),
+                width: encoded.width,
+                height: encoded.height,
+                levels: decompressed.iter().map(Vec::as_slice).collect(),
+            },
+            label,
+        );
+    }
+
+    let size = wgpu::Extent3d {
+        width: encoded.width,
+        height: encoded.height,
+        depth_or_array_layers: 1,
+    };
+    let mip_level_count =
+        (encoded.levels.len() as u32).min(size.max_mips(wgpu::TextureDimension::D2));
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size,
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    let block_size = format.block_copy_size(None).unwrap_or(4);
+    for (level, level_data) in encoded.levels[..mip_level_count as usize]
+        .iter()
+        .enumerate()
+    {
+        let level_size = size
+            .mip_level_size(level as u32, wgpu::TextureDimension::D2)
+            .physical_size(format);
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: level as u32,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            level_data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(level_size.width / block_width * block_size),
+                rows_per_image: Some(level_size.height / block_height),
+            },
+            level_size,
+        );
+    }
+
+    Ok(texture)
+}
+
+fn dxgi_texture_format(format: DxgiFormat) -> Option<TextureFormat> {
+    Some(match format {
+        DxgiFormat::R8G8B8A8_UNorm => TextureFormat::Rgba8Unorm,
+        DxgiFormat::R8G8B8A8_UNorm_sRGB => TextureFormat::Rgba8UnormSrgb,
+        DxgiFormat::B8G8R8A8_UNorm => TextureFormat::Bgra8Unorm,
+        DxgiFormat::B8G8R8A8_UNorm_sRGB => TextureFormat::Bgra8UnormSrgb,
+        DxgiFormat::BC1_UNorm => TextureFormat::Bc1RgbaUnorm,
+        DxgiFormat::BC1_UNorm_sRGB => TextureFormat::Bc1RgbaUnormSrgb,
+        DxgiFormat::BC2_UNorm => TextureFormat::Bc2RgbaUnorm,
+        DxgiFormat::BC2_UNorm_sRGB => TextureFormat::Bc2RgbaUnormSrgb,
+        DxgiFormat::BC3_UNorm => TextureFormat::Bc3RgbaUnorm,
+        DxgiFormat::BC3_UNorm_sRGB => TextureFormat::Bc3RgbaUnormSrgb,
+        DxgiFormat::BC4_UNorm => TextureFormat::Bc4RUnorm,
+        DxgiFormat::BC4_SNorm => TextureFormat::Bc4RSnorm,
+        DxgiFormat::BC5_UNorm => TextureFormat::Bc5RgUnorm,
+        DxgiFormat::BC5_SNorm => TextureFormat::Bc5RgSnorm,
+        DxgiFormat::BC6H_UF16 => TextureFormat::Bc6hRgbUfloat,
+        DxgiFormat::BC6H_SF16 => TextureFormat::Bc6hRgbFloat,
+        DxgiFormat::BC7_UNorm => TextureFormat::Bc7RgbaUnorm,
+        DxgiFormat::BC7_UNorm_sRGB => TextureFormat::Bc7RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+/// Legacy DDS files do not say if they are sRGB. They are treated as color textures.
+fn d3d_texture_format(format: D3DFormat) -> Option<TextureFormat> {
+    Some(match format {
+        D3DFormat::A8B8G8R8 => TextureFormat::Rgba8UnormSrgb,
+        D3DFormat::A8R8G8B8 => TextureFormat::Bgra8UnormSrgb,
+        D3DFormat::DXT1 => TextureFormat::Bc1RgbaUnormSrgb,
+        D3DFormat::DXT3 => TextureFormat::Bc2RgbaUnormSrgb,
+        D3DFormat::DXT5 => TextureFormat::Bc3RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+fn ktx2_texture_format(format: ktx2::Format) -> Option<TextureFormat> {
+    use ktx2::Format;
+
+    Some(match format {
+        Format::R8G8B8A8_UNORM => TextureFormat::Rgba8Unorm,
+        Format::R8G8B8A8_SRGB => TextureFormat::Rgba8UnormSrgb,
+        Format::B8G8R8A8_UNORM => TextureFormat::Bgra8Unorm,
+        Format::B8G8R8A8_SRGB => TextureFormat::Bgra8UnormSrgb,
+        Format::BC1_RGB_UNORM_BLOCK | Format::BC1_RGBA_UNORM_BLOCK => TextureFormat::Bc1RgbaUnorm,
+        Format::BC1_RGB_SRGB_BLOCK | Format::BC1_RGBA_SRGB_BLOCK => TextureFormat::Bc1RgbaUnormSrgb,
+        Format::BC2_UNORM_BLOCK => TextureFormat::Bc2RgbaUnorm,
+        Format::BC2_SRGB_BLOCK => TextureFormat::Bc2RgbaUnormSrgb,
+        Format::BC3_UNORM_BLOCK => TextureFormat::Bc3RgbaUnorm,
+        Format::BC3_SRGB_BLOCK => TextureFormat::Bc3RgbaUnormSrgb,
+        Format::BC4_UNORM_BLOCK => TextureFormat::Bc4RUnorm,
+        Format::BC4_SNORM_BLOCK => TextureFormat::Bc4RSnorm,
+        Format::BC5_UNORM_BLOCK => TextureFormat::Bc5RgUnorm,
+        Format::BC5_SNORM_BLOCK => TextureFormat::Bc5RgSnorm,
+        Format::BC6H_UFLOAT_BLOCK => TextureFormat::Bc6hRgbUfloat,
+        Format::BC6H_SFLOAT_BLOCK => TextureFormat::Bc6hRgbFloat,
+        Format::BC7_UNORM_BLOCK => TextureFormat::Bc7RgbaUnorm,
+        Format::BC7_SRGB_BLOCK => TextureFormat::Bc7RgbaUnormSrgb,
+        Format::ETC2_R8G8B8_UNORM_BLOCK => TextureFormat::Etc2Rgb8Unorm,
+        Format::ETC2_R8G8B8_SRGB_BLOCK => TextureFormat::Etc2Rgb8UnormSrgb,
+        Format::ETC2_R8G8B8A1_UNORM_BLOCK => TextureFormat::Etc2Rgb8A1Unorm,
+        Format::ETC2_R8G8B8A1_SRGB_BLOCK => TextureFormat::Etc2Rgb8A1UnormSrgb,
+        Format::ETC2_R8G8B8A8_UNORM_BLOCK => TextureFormat::Etc2Rgba8Unorm,
+        Format::ETC2_R8G8B8A8_SRGB_BLOCK => TextureFormat::Etc2Rgba8UnormSrgb,
+        _ => return ktx2_astc_texture_format(format),
+    })
+}
+
+/// The LDR ASTC formats are numbered in pairs of UNORM and SRGB, in this block order.
+fn ktx2_astc_texture_format(format: ktx2::Format) -> Option<TextureFormat> {
+    const BLOCKS: [AstcBlock; 14] = [
+        AstcBlock::B4x4,
+        AstcBlock::B5x4,
+        AstcBlock::B5x5,
+        AstcBlock::B6x5,
+        AstcBlock::B6x6,
+        AstcBlock::B8x5,
+        AstcBlock::B8x6,
+        AstcBlock::B8x8,
+        AstcBlock::B10x5,
+        AstcBlock::B10x6,
+        AstcBlock::B10x8,
+        AstcBlock::B10x10,
+        AstcBlock::B12x10,
+        AstcBlock::B12x12,
+    ];
+
+    let offset = format
+        .0
+        .get()
+        .checked_sub(ktx2::Format::ASTC_4x4_UNORM_BLOCK.0.get())? as usize;
+    let block = *BLOCKS.get(offset / 2)?;
+    let channel = if offset.is_multiple_of(2) {
+        AstcChannel::Unorm
+    } else {
+        AstcChannel::UnormSrgb
+    };
+
+    Some(TextureFormat::Astc { block, channel })
+}
@@ -5,10 +5,54 @@
 
 use log::info;
 use std::default::Default;
+use std::fmt::{Display, Formatter};
 use std::sync::Arc;
+use swamp_wgpu::gpu_timer::{GpuTimer, PassTiming};
+use swamp_wgpu::render_graph::{
+    ColorTarget, RenderGraph, RenderGraphError, RenderPassDesc, SurfaceTarget, TransientTexturePool,
+};
 use wgpu::{DeviceDescriptor, Features, MemoryHints, RenderPass, RequestDeviceError, SurfaceError};
 use winit::window::Window;
 
+/// Passes beyond this count in a frame are not timed.
+const MAX_TIMED_PASSES: u32 = 32;
+
+pub const DEFAULT_CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.3,
+    b: 0.1,
+    a: 1.0,
+};
+
+#[derive(Debug)]
+pub enum RenderError {
+    Surface(SurfaceError),
+    Graph(RenderGraphError),
+}
+
+impl Display for RenderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Surface(err) => write!(f, "surface error: {err}"),
+            Self::Graph(err) => write!(f, "render graph error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+impl From<SurfaceError> for RenderError {
+    fn from(err: SurfaceError) -> Self {
+        Self::Surface(err)
+    }
+}
+
+impl From<RenderGraphError> for RenderError {
+    fn from(err: RenderGraphError) -> Self {
+        Self::Graph(err)
+    }
+}
+
 #[derive(Debug)]
 pub struct WgpuWindow<'a> {
     //instance: wgpu::Instance,
@@ -18,6 +62,9 @@ pub struct WgpuWindow<'a> {
 
     config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
+    transient_textures: TransientTexturePool,
+    /// Only when the adapter supports timestamp queries
+    gpu_timer: Option<GpuTimer>,
 }
 
 impl<'a> WgpuWindow<'a> {
@@ -35,6 +82,13 @@ impl WgpuWindow<'_> {
         &self.config
     }
 
+    /// GPU time of each pass in a recent frame, empty when timestamp queries are not supported.
+    pub fn gpu_timings(&self) -> &[PassTiming] {
+        self.gpu_timer
+            .as_ref()
+            .map_or(&[], |gpu_timer| gpu_timer.timings())
+    }
+
     pub async fn new(window: Arc<Window>) -> Result<Self, RequestDeviceError> {
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
             #[cfg(not(target_arch = "wasm32"))]
@@ -57,7 +111,8 @@ impl WgpuWindow<'_> {
 
         let device_descriptor = DeviceDescriptor {
             label: None,
-            required_features: Features::empty(), // Specify features as needed
+            // Timestamps are only used for the pass timings, so they are optional
+            required_features: adapter.features() & Features::TIMESTAMP_QUERY,
             required_limits: if cfg!(target_arch = "wasm32") {
                 wgpu::Limits::downlevel_webgl2_defaults()
             } else {
@@ -101,12 +156,17 @@ impl WgpuWindow<'_> {
             surface_format, present_mode, alpha_mode
         );
 
+        let gpu_timer = GpuTimer::is_supported(&device)
+            .then(|| GpuTimer::new(&device, &queue, MAX_TIMED_PASSES));
+
         Ok(Self {
             surface,
             device: device.into(),
             queue: queue.into(),
             config,
             size: window_size,
+            transient_textures: TransientTexturePool::new(),
+            gpu_timer,
         })
     }
 
@@ -118,14 +178,30 @@ impl WgpuWindow<'_> {
         self.config.width = new_size.width;
         self.config.height = new_size.height;
         self.surface.configure(&self.device, &self.config);
+        self.transient_textures.clear();
     }
 
     pub fn render(
         &mut self,
-        mut render_fn: impl FnMut(&mut RenderPass, &wgpu::Device, &wgpu::Queue),
-    ) -> Result<(), SurfaceError> {
+        render_fn: impl FnOnce(&mut RenderPass, &wgpu::Device, &wgpu::Queue),
+    ) -> Result<(), RenderError> {
+        let mut graph = RenderGraph::new();
+        let mut desc = RenderPassDesc::new("Render Pass", ColorTarget::Surface);
+        desc.clear = Some(DEFAULT_CLEAR_COLOR);
+        graph.add_render_pass(desc, |render_pass, context| {
+            render_fn(render_pass, context.device, context.queue)
+        });
+
+        self.render_graph(graph)
+    }
+
+    /// Executes all passes in the graph and presents the surface.
+    pub fn render_graph(&mut self, graph: RenderGraph) -> Result<(), RenderError> {
         // Gets a new texture from the swap chain
         let surface_texture = self.surface.get_current_texture()?;
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.begin_frame(&self.device);
+        }
         let texture_view = surface_texture
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
@@ -136,35 +212,29 @@ impl WgpuWindow<'_> {
                 label: Some("Render Encoder"),
             });
 
-        // THIS SCOPE IS ABSOLUTELY NEEDED FOR THE RENDER PASS - DO NOT REMOVE
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[
-                    // This is what @location(0) in the fragment shader targets
-                    Some(wgpu::RenderPassColorAttachment {
-                        view: &texture_view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color {
-                                r: 0.1,
-                                g: 0.3,
-                                b: 0.1,
-                                a: 1.0,
-                            }),
-                            store: wgpu::StoreOp::Store,
-                        },
-                    }),
-                ],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
+        let surface = SurfaceTarget {
+            view: &texture_view,
+            format: self.config.format,
+            width: self.config.width,
+            height: self.config.height,
+        };
 
-            render_fn(&mut render_pass, &self.device, &self.queue);
+        graph.record_timed(
+            &mut encoder,
+            &self.device,
+            &self.queue,
+            &surface,
+            &mut self.transient_textures,
+            self.gpu_timer.as_mut(),
+        )?;
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.resolve(&mut encoder);
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.after_submit();
+        }
 
         surface_texture.present();
 
@@ -0,0 +1,210 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+//! GPU pass timings from timestamp queries. The timings of a frame are read back a frame
+//! or two later, without waiting for the GPU.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use wgpu::{Buffer, CommandEncoder, QuerySet};
+
+/// Octets of a single timestamp.
+const TIMESTAMP_OCTETS: u64 = 8;
+
+// States of the readback mapping, set by the `map_async` callback
+const MAP_PENDING: u8 = 0;
+const MAP_DONE: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassTiming {
+    pub label: String,
+    pub milliseconds: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Readback {
+    Idle,
+    /// Copied to the readback buffer by the encoder that is about to be submitted
+    Copied,
+    /// Waiting for the readback buffer to be mapped
+    Mapping,
+}
+
+#[derive(Debug)]
+pub struct GpuTimer {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    max_passes: u32,
+    /// Nanoseconds per timestamp tick
+    period: f32,
+    labels: Vec<String>,
+    readback_labels: Vec<String>,
+    readback: Readback,
+    map_state: Arc<AtomicU8>,
+    timings: Vec<PassTiming>,
+}
+
+impl GpuTimer {
+    /// Timestamps in passes need [`wgpu::Features::TIMESTAMP_QUERY`] when requesting the device.
+    pub fn is_supported(device: &wgpu::Device) -> bool {
+        device.features().contains(wgpu::Features::TIMESTAMP_QUERY)
+    }
+
+    /// Passes beyond `max_passes` in a frame are not timed.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, max_passes: u32) -> Self {
+        let query_count = max_passes.max(1) * 2;
+        let octet_size = u64::from(query_count) * TIMESTAMP_OCTETS;
+
+        Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("gpu timer query set"),
+                ty: wgpu::QueryType::Timestamp,
+                count: query_count,
+            }),
+            resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("gpu timer resolve buffer"),
+                size: octet_size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("gpu timer readback buffer"),
+                size: octet_size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            max_passes: max_passes.max(1),
+            period: queue.get_timestamp_period(),
+            labels: Vec::new(),
+            readback_labels: Vec::new(),
+            readback: Readback::Idle,
+            map_state: Arc::new(AtomicU8::new(MAP_PENDING)),
+            timings: Vec::new(),
+        }
+    }
+
+    /// The latest timings that have been read back, in execution order.
+    pub fn timings(&self) -> &[PassTiming] {
+        &self.timings
+    }
+
+    /// Picks up finished timings, call before recording the passes of a frame.
+    pub fn begin_frame(&mut self, device: &wgpu::Device) {
+        self.labels.clear();
+        if self.readback != Readback::Mapping {
+            return;
+        }
+
+        device.poll(wgpu::Maintain::Poll);
+        match self.map_state.swap(MAP_PENDING, Ordering::Acquire) {
+            MAP_DONE => {}
+            MAP_FAILED => {
+                self.readback_labels.clear();
+                self.readback = Readback::Idle;
+                return;
+            }
+            _ => return,
+        }
+
+        {
+            let octets = self
+                .readback_buffer
+                .slice(..self.readback_octet_size())
+                .get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&octets);
+            self.timings = self
+                .readback_labels
+                .drain(..)
+                .zip(timestamps.chunks_exact(2))
+                .map(|(label, pair)| PassTiming {
+                    label,
+                    milliseconds: pair[1].saturating_sub(pair[0]) as f32 * self.period
+                        / 1_000_000.0,
+                })
+                .collect();
+        }
+        self.readback_buffer.unmap();
+        self.readback = Readback::Idle;
+    }
+
+    /// Start and end timestamps for the next render pass.
+    pub fn render_pass_writes(
+        &mut self,
+        label: &str,
+    ) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        let (start, end) = self.next_queries(label)?;
+        Some(wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(start),
+            end_of_pass_write_index: Some(end),
+        })
+    }
+
+    /// Start and end timestamps for the next compute pass.
+    pub fn compute_pass_writes(
+        &mut self,
+        label: &str,
+    ) -> Option<wgpu::ComputePassTimestampWrites<'_>> {
+        let (start, end) = self.next_queries(label)?;
+        Some(wgpu::ComputePassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(start),
+            end_of_pass_write_index: Some(end),
+        })
+    }
+
+    fn next_queries(&mut self, label: &str) -> Option<(u32, u32)> {
+        let index = self.labels.len() as u32;
+        if index >= self.max_passes {
+            return None;
+        }
+        self.labels.push(label.to_string());
+
+        Some((index * 2, index * 2 + 1))
+    }
+
+    /// Copies the timestamps of this frame for reading back, after all passes are recorded.
+    /// Skipped while the timings of an earlier frame are still being read back.
+    pub fn resolve(&mut self, encoder: &mut CommandEncoder) {
+        if self.labels.is_empty() || self.readback != Readback::Idle {
+            return;
+        }
+
+        let query_count = self.labels.len() as u32 * 2;
+        let octet_size = u64::from(query_count) * TIMESTAMP_OCTETS;
+        encoder.resolve_query_set(&self.query_set, 0..query_count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            octet_size,
+        );
+        self.readback_labels = std::mem::take(&mut self.labels);
+        self.readback = Readback::Copied;
+    }
+
+    /// Starts reading back, call after the encoder from [`GpuTimer::resolve`] is submitted.
+    pub fn after_submit(&mut self) {
+        if self.readback != Readback::Copied {
+            return;
+        }
+
+        let map_state = Arc::clone(&self.map_state);
+        self.readback_buffer
+            .slice(..self.readback_octet_size())
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let state = if result.is_ok() { MAP_DONE } else { MAP_FAILED };
+                map_state.store(state, Ordering::Release);
+            });
+        self.readback = Readback::Mapping;
+    }
+
+    fn readback_octet_size(&self) -> u64 {
+        self.readback_labels.len() as u64 * 2 * TIMESTAMP_OCTETS
+    }
+}
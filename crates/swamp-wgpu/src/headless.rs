@@ -0,0 +1,166 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+//! Rendering without a window, e.g. for tools that save frames as images.
+
+use crate::render_graph::SurfaceTarget;
+use std::fmt::{Display, Formatter};
+use wgpu::{Buffer, CommandEncoder, Texture, TextureFormat, TextureView};
+
+#[derive(Debug)]
+pub enum HeadlessError {
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+}
+
+impl Display for HeadlessError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoAdapter => write!(f, "no graphics adapter found"),
+            Self::RequestDevice(err) => write!(f, "could not request device: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for HeadlessError {}
+
+/// A device on any adapter, without a surface to present to.
+pub async fn request_device() -> Result<(wgpu::Device, wgpu::Queue), HeadlessError> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions::default())
+        .await
+        .ok_or(HeadlessError::NoAdapter)?;
+
+    adapter
+        .request_device(&wgpu::DeviceDescriptor::default(), None)
+        .await
+        .map_err(HeadlessError::RequestDevice)
+}
+
+/// A texture to render into instead of a surface, that can be read back.
+#[derive(Debug)]
+pub struct HeadlessTarget {
+    texture: Texture,
+    view: TextureView,
+    readback_buffer: Buffer,
+    /// Rows in the readback buffer are padded to `COPY_BYTES_PER_ROW_ALIGNMENT`
+    padded_bytes_per_row: u32,
+}
+
+impl HeadlessTarget {
+    /// `format` must have four octets per pixel, e.g. [`TextureFormat::Rgba8UnormSrgb`].
+    pub fn new(
+        device: &wgpu::Device,
+        label: &str,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+    ) -> Self {
+        assert_eq!(
+            format.block_copy_size(None),
+            Some(4),
+            "headless target format {format:?} must have four octets per pixel"
+        );
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let padded_bytes_per_row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{label} readback buffer")),
+            size: u64::from(padded_bytes_per_row) * u64::from(height),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            texture,
+            view,
+            readback_buffer,
+            padded_bytes_per_row,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.texture.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.texture.height()
+    }
+
+    pub fn format(&self) -> TextureFormat {
+        self.texture.format()
+    }
+
+    pub fn view(&self) -> &TextureView {
+        &self.view
+    }
+
+    /// For recording a [`crate::render_graph::RenderGraph`] into the target.
+    pub fn surface(&self) -> SurfaceTarget<'_> {
+        SurfaceTarget {
+            view: &self.view,
+            format: self.format(),
+            width: self.width(),
+            height: self.height(),
+        }
+    }
+
+    /// Copies the pixels for [`HeadlessTarget::read_pixels`], after the passes that render
+    /// into the target.
+    pub fn copy_to_readback(&self, encoder: &mut CommandEncoder) {
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row),
+                    rows_per_image: Some(self.height()),
+                },
+            },
+            self.texture.size(),
+        );
+    }
+
+    /// Waits for the GPU and returns tightly packed rows, from the top row down.
+    /// Call after the encoder from [`HeadlessTarget::copy_to_readback`] is submitted.
+    pub fn read_pixels(&self, device: &wgpu::Device) -> Result<Vec<u8>, wgpu::BufferAsyncError> {
+        let slice = self.readback_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .expect("map_async calls back when the device is polled with Wait")?;
+
+        let bytes_per_row = (self.width() * 4) as usize;
+        let pixels = slice
+            .get_mapped_range()
+            .chunks_exact(self.padded_bytes_per_row as usize)
+            .flat_map(|row| &row[..bytes_per_row])
+            .copied()
+            .collect();
+        self.readback_buffer.unmap();
+
+        Ok(pixels)
+    }
+}
@@ -3,7 +3,14 @@
  * Licensed under the MIT License. See LICENSE in the project root for license information.
  */
 
+pub mod gpu_timer;
+pub mod headless;
+pub mod mipmap;
+pub mod render_graph;
+pub mod shader_validation;
+
 use log::info;
+use std::fmt::{Display, Formatter};
 use wgpu::util::DeviceExt;
 use wgpu::{BindGroup, BindGroupLayout, Buffer, PipelineLayout, Sampler, ShaderModule, Texture};
 
@@ -24,24 +31,82 @@ pub fn create_pipeline_layout(
     device: &wgpu::Device,
     label: &str,
     bind_group_layout: &BindGroupLayout,
+) -> PipelineLayout {
+    create_pipeline_layout_with_groups(device, label, &[bind_group_layout])
+}
+
+pub fn create_pipeline_layout_with_groups(
+    device: &wgpu::Device,
+    label: &str,
+    bind_group_layouts: &[&BindGroupLayout],
 ) -> PipelineLayout {
     info!("creating pipeline layout");
     device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some(label),
-        bind_group_layouts: &[bind_group_layout],
+        bind_group_layouts,
         push_constant_ranges: &[],
     })
 }
 
-pub fn create_nearest_sampler(device: &wgpu::Device, label: &str) -> Sampler {
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SamplerFilter {
+    /// Sharp pixels, for pixel art
+    #[default]
+    Nearest,
+    Linear,
+    /// Linear, also between mip levels. Needs a texture with mip levels.
+    LinearMipmapped,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SamplerAddressMode {
+    #[default]
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+impl From<SamplerAddressMode> for wgpu::AddressMode {
+    fn from(mode: SamplerAddressMode) -> Self {
+        match mode {
+            SamplerAddressMode::Clamp => Self::ClampToEdge,
+            SamplerAddressMode::Repeat => Self::Repeat,
+            SamplerAddressMode::Mirror => Self::MirrorRepeat,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SamplerOptions {
+    pub filter: SamplerFilter,
+    pub address_mode: SamplerAddressMode,
+}
+
+impl SamplerOptions {
+    pub const fn new(filter: SamplerFilter, address_mode: SamplerAddressMode) -> Self {
+        Self {
+            filter,
+            address_mode,
+        }
+    }
+}
+
+pub fn create_sampler(device: &wgpu::Device, label: &str, options: SamplerOptions) -> Sampler {
+    let (filter, mipmap_filter) = match options.filter {
+        SamplerFilter::Nearest => (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest),
+        SamplerFilter::Linear => (wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest),
+        SamplerFilter::LinearMipmapped => (wgpu::FilterMode::Linear, wgpu::FilterMode::Linear),
+    };
+    let address_mode = options.address_mode.into();
+
     device.create_sampler(&wgpu::SamplerDescriptor {
         label: Some(label),
-        address_mode_u: wgpu::AddressMode::ClampToEdge,
-        address_mode_v: wgpu::AddressMode::ClampToEdge,
-        address_mode_w: wgpu::AddressMode::ClampToEdge,
-        mag_filter: wgpu::FilterMode::Nearest,
-        min_filter: wgpu::FilterMode::Nearest,
-        mipmap_filter: wgpu::FilterMode::Nearest,
+        address_mode_u: address_mode,
+        address_mode_v: address_mode,
+        address_mode_w: address_mode,
+        mag_filter: filter,
+        min_filter: filter,
+        mipmap_filter,
         compare: None,
         anisotropy_clamp: 1,
         lod_min_clamp: 0.0,
@@ -50,7 +115,35 @@ pub fn create_nearest_sampler(device: &wgpu::Device, label: &str) -> Sampler {
     })
 }
 
+pub fn create_nearest_sampler(device: &wgpu::Device, label: &str) -> Sampler {
+    create_sampler(device, label, SamplerOptions::default())
+}
+
+pub fn create_linear_sampler(device: &wgpu::Device, label: &str) -> Sampler {
+    create_sampler(
+        device,
+        label,
+        SamplerOptions::new(SamplerFilter::Linear, SamplerAddressMode::Clamp),
+    )
+}
+
 pub fn create_texture(device: &wgpu::Device, width: u32, height: u32) -> Texture {
+    create_texture_with_format(
+        device,
+        "My Texture",
+        width,
+        height,
+        wgpu::TextureFormat::Rgba8Unorm,
+    )
+}
+
+pub fn create_texture_with_format(
+    device: &wgpu::Device,
+    label: &str,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> Texture {
     let texture_size = wgpu::Extent3d {
         width,
         height,
@@ -58,31 +151,186 @@ pub fn create_texture(device: &wgpu::Device, width: u32, height: u32) -> Texture
     };
 
     device.create_texture(&wgpu::TextureDescriptor {
-        label: Some("My Texture"),
+        label: Some(label),
         size: texture_size,
         mip_level_count: 1,
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8Unorm,
+        format,
         usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-        view_formats: &[wgpu::TextureFormat::Rgba8Unorm], // Specify the view format(s),
+        view_formats: &[format], // Specify the view format(s),
     })
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextureRegionError {
+    /// Compressed and multi aspect formats can not be written pixel by pixel
+    UnsupportedFormat(wgpu::TextureFormat),
+    WrongLength {
+        expected: usize,
+        actual: usize,
+    },
+    OutsideTexture {
+        origin: (u32, u32),
+        size: (u32, u32),
+        texture_size: (u32, u32),
+    },
+}
+
+impl Display for TextureRegionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnsupportedFormat(format) => {
+                write!(f, "can not write a region of a {format:?} texture")
+            }
+            Self::WrongLength { expected, actual } => {
+                write!(f, "expected {expected} octets for the region, got {actual}")
+            }
+            Self::OutsideTexture {
+                origin,
+                size,
+                texture_size,
+            } => write!(
+                f,
+                "region {}x{} at ({}, {}) is outside of the {}x{} texture",
+                size.0, size.1, origin.0, origin.1, texture_size.0, texture_size.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TextureRegionError {}
+
+/// Checks that `octet_count` octets fill the region, and that the region is inside the texture.
+/// Returns the octets per row.
+fn texture_region_bytes_per_row(
+    format: wgpu::TextureFormat,
+    texture_size: (u32, u32),
+    origin: (u32, u32),
+    size: (u32, u32),
+    octet_count: usize,
+) -> Result<u32, TextureRegionError> {
+    let octets_per_pixel = match (format.block_dimensions(), format.block_copy_size(None)) {
+        ((1, 1), Some(octets_per_pixel)) => octets_per_pixel,
+        _ => return Err(TextureRegionError::UnsupportedFormat(format)),
+    };
+    let (width, height) = size;
+    let expected = width as usize * height as usize * octets_per_pixel as usize;
+    if octet_count != expected {
+        return Err(TextureRegionError::WrongLength {
+            expected,
+            actual: octet_count,
+        });
+    }
+    let fits = |start: u32, length: u32, limit: u32| {
+        start.checked_add(length).is_some_and(|end| end <= limit)
+    };
+    if !fits(origin.0, width, texture_size.0) || !fits(origin.1, height, texture_size.1) {
+        return Err(TextureRegionError::OutsideTexture {
+            origin,
+            size,
+            texture_size,
+        });
+    }
+
+    Ok(width * octets_per_pixel)
+}
+
+/// Writes tightly packed rows of pixels to a region of an uncompressed texture.
+///
+/// `queue.write_texture` stages the data itself, so unlike buffer to texture copies
+/// the rows do not have to be padded to `COPY_BYTES_PER_ROW_ALIGNMENT`.
+pub fn write_texture_region(
+    queue: &wgpu::Queue,
+    texture: &Texture,
+    origin: (u32, u32),
+    size: (u32, u32),
+    octets: &[u8],
+) -> Result<(), TextureRegionError> {
+    let (width, height) = size;
+    let bytes_per_row = texture_region_bytes_per_row(
+        texture.format(),
+        (texture.width(), texture.height()),
+        origin,
+        size,
+        octets.len(),
+    )?;
+
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d {
+                x: origin.0,
+                y: origin.1,
+                z: 0,
+            },
+            aspect: wgpu::TextureAspect::All,
+        },
+        octets,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(bytes_per_row),
+            rows_per_image: Some(height),
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+
+    Ok(())
+}
+
+/// Approximate GPU memory used by the texture, counting all mip levels and layers.
+pub fn texture_octet_size(texture: &Texture) -> u64 {
+    let format = texture.format();
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = u64::from(format.block_copy_size(None).unwrap_or(4));
+    let size = texture.size();
+
+    (0..texture.mip_level_count())
+        .map(|level| {
+            let level_size = size.mip_level_size(level, texture.dimension());
+            u64::from(level_size.width.div_ceil(block_width))
+                * u64::from(level_size.height.div_ceil(block_height))
+                * u64::from(level_size.depth_or_array_layers)
+                * block_size
+        })
+        .sum()
+}
+
 pub fn create_texture_and_sampler_bind_group(
     device: &wgpu::Device,
     bind_group_layout: &BindGroupLayout,
     sampler: &Sampler,
-    texture: Texture,
+    texture: &Texture,
     label: &str,
 ) -> BindGroup {
     let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    create_texture_and_sampler_bind_group_from_view(
+        device,
+        bind_group_layout,
+        sampler,
+        &texture_view,
+        label,
+    )
+}
+
+pub fn create_texture_and_sampler_bind_group_from_view(
+    device: &wgpu::Device,
+    bind_group_layout: &BindGroupLayout,
+    sampler: &Sampler,
+    texture_view: &wgpu::TextureView,
+    label: &str,
+) -> BindGroup {
     device.create_bind_group(&wgpu::BindGroupDescriptor {
         layout: bind_group_layout,
         entries: &[
             wgpu::BindGroupEntry {
                 binding: 0,
-                resource: wgpu::BindingResource::TextureView(&texture_view),
+                resource: wgpu::BindingResource::TextureView(texture_view),
             },
             wgpu::BindGroupEntry {
                 binding: 1,
@@ -116,6 +364,26 @@ pub fn create_uniform_buffer(device: &wgpu::Device, label: &str) -> Buffer {
     })
 }
 
+/// Uniform buffers must be at least 16 octets and a multiple of 16.
+pub fn pad_uniform_octets(octets: &[u8]) -> Vec<u8> {
+    let padded_len = octets.len().div_ceil(16).max(1) * 16;
+    let mut padded = octets.to_vec();
+    padded.resize(padded_len, 0);
+    padded
+}
+
+pub fn create_uniform_buffer_with_octets(
+    device: &wgpu::Device,
+    label: &str,
+    octets: &[u8],
+) -> Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(label),
+        contents: &pad_uniform_octets(octets),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    })
+}
+
 pub fn create_uniform_bind_group_layout(device: &wgpu::Device, label: &str) -> BindGroupLayout {
     device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         label: Some(label),
@@ -151,3 +419,52 @@ pub fn create_uniform_bind_group(
         }],
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::TextureFormat;
+
+    #[test]
+    fn region_inside_texture() {
+        let bytes_per_row =
+            texture_region_bytes_per_row(TextureFormat::Rgba8Unorm, (8, 8), (6, 5), (2, 3), 24);
+        assert_eq!(bytes_per_row, Ok(8));
+    }
+
+    #[test]
+    fn length_is_checked_before_bounds() {
+        assert_eq!(
+            texture_region_bytes_per_row(TextureFormat::Rgba8Unorm, (8, 8), (7, 0), (2, 2), 15),
+            Err(TextureRegionError::WrongLength {
+                expected: 16,
+                actual: 15
+            })
+        );
+    }
+
+    #[test]
+    fn region_outside_texture() {
+        for (origin, size) in [((7, 0), (2, 1)), ((0, 8), (1, 1)), ((u32::MAX, 0), (1, 1))] {
+            let octets = (size.0 * size.1) as usize;
+            assert_eq!(
+                texture_region_bytes_per_row(TextureFormat::R8Uint, (8, 8), origin, size, octets),
+                Err(TextureRegionError::OutsideTexture {
+                    origin,
+                    size,
+                    texture_size: (8, 8)
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn compressed_format_is_unsupported() {
+        assert_eq!(
+            texture_region_bytes_per_row(TextureFormat::Bc1RgbaUnorm, (8, 8), (0, 0), (4, 4), 8),
+            Err(TextureRegionError::UnsupportedFormat(
+                TextureFormat::Bc1RgbaUnorm
+            ))
+        );
+    }
+}
@@ -0,0 +1,258 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use wgpu::{BindGroupLayout, PipelineLayout, RenderPipeline, Sampler, ShaderModule, Texture};
+
+const MIPMAP_SHADER_SOURCE: &str = include_str!("shaders/mipmap.wgsl");
+
+/// Fills mip chains on the GPU by drawing each level into the next with linear filtering.
+pub struct MipmapGenerator {
+    shader: ShaderModule,
+    sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+    pipeline_layout: PipelineLayout,
+    /// One pipeline per target format
+    pipelines: HashMap<wgpu::TextureFormat, RenderPipeline>,
+}
+
+impl Debug for MipmapGenerator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MipmapGenerator")
+            .field("formats", &self.pipelines.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl MipmapGenerator {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let shader = crate::create_shader_module(device, "mipmap", MIPMAP_SHADER_SOURCE);
+        let sampler = crate::create_linear_sampler(device, "mipmap sampler");
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mipmap bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout =
+            crate::create_pipeline_layout(device, "mipmap pipeline layout", &bind_group_layout);
+
+        Self {
+            shader,
+            sampler,
+            bind_group_layout,
+            pipeline_layout,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Block compressed and other non renderable formats can not get generated mip levels.
+    pub fn supports_format(format: wgpu::TextureFormat) -> bool {
+        !format.is_compressed()
+            && format
+                .guaranteed_format_features(wgpu::Features::empty())
+                .allowed_usages
+                .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+    }
+
+    /// The number of levels in a full mip chain, down to 1x1.
+    pub fn full_mip_level_count(width: u32, height: u32) -> u32 {
+        32 - width.max(height).max(1).leading_zeros()
+    }
+
+    /// Whether [`Self::generate`] can draw into the levels of a texture. Textures without
+    /// `RENDER_ATTACHMENT` usage, e.g. loaded with a stored mip chain, need
+    /// [`Self::create_mipmapped_copy`] instead.
+    pub fn can_generate_in_place(mip_level_count: u32, usage: wgpu::TextureUsages) -> bool {
+        mip_level_count > 1 && usage.contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+    }
+
+    /// Creates a texture with a full mip chain, with the first level drawn from `source`.
+    pub fn create_mipmapped_copy(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source: &Texture,
+        label: &str,
+    ) -> Texture {
+        let format = source.format();
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: source.width(),
+                height: source.height(),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: Self::full_mip_level_count(source.width(), source.height()),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[format],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("mipmap encoder"),
+        });
+        let source_view = source.create_view(&wgpu::TextureViewDescriptor {
+            base_mip_level: 0,
+            mip_level_count: Some(1),
+            ..Default::default()
+        });
+        self.blit(device, &mut encoder, &source_view, &texture, 0);
+        self.encode_levels(device, &mut encoder, &texture);
+        queue.submit(Some(encoder.finish()));
+
+        texture
+    }
+
+    /// Regenerates all levels after the first, e.g. after the first level has been written to.
+    /// The texture must have been created with `RENDER_ATTACHMENT` usage.
+    pub fn generate(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, texture: &Texture) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("mipmap encoder"),
+        });
+        self.encode_levels(device, &mut encoder, texture);
+        queue.submit(Some(encoder.finish()));
+    }
+
+    fn encode_levels(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &Texture,
+    ) {
+        for level in 1..texture.mip_level_count() {
+            let source_view = texture.create_view(&wgpu::TextureViewDescriptor {
+                base_mip_level: level - 1,
+                mip_level_count: Some(1),
+                ..Default::default()
+            });
+            self.blit(device, encoder, &source_view, texture, level);
+        }
+    }
+
+    fn blit(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source_view: &wgpu::TextureView,
+        target: &Texture,
+        target_level: u32,
+    ) {
+        let format = target.format();
+        if !self.pipelines.contains_key(&format) {
+            let pipeline = self.create_pipeline(device, format);
+            self.pipelines.insert(format, pipeline);
+        }
+        let pipeline = &self.pipelines[&format];
+
+        let target_view = target.create_view(&wgpu::TextureViewDescriptor {
+            base_mip_level: target_level,
+            mip_level_count: Some(1),
+            ..Default::default()
+        });
+        let bind_group = crate::create_texture_and_sampler_bind_group_from_view(
+            device,
+            &self.bind_group_layout,
+            &self.sampler,
+            source_view,
+            "mipmap bind group",
+        );
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("mipmap level"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &target_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+    ) -> RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("mipmap pipeline"),
+            layout: Some(&self.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::TextureUsages;
+
+    #[test]
+    fn only_render_attachments_are_generated_in_place() {
+        let loaded = TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST;
+        let renderable = loaded | TextureUsages::RENDER_ATTACHMENT;
+
+        assert!(MipmapGenerator::can_generate_in_place(5, renderable));
+        assert!(!MipmapGenerator::can_generate_in_place(5, loaded));
+        assert!(!MipmapGenerator::can_generate_in_place(1, renderable));
+        assert!(!MipmapGenerator::can_generate_in_place(1, loaded));
+    }
+
+    #[test]
+    fn full_mip_chain_goes_down_to_one_texel() {
+        assert_eq!(MipmapGenerator::full_mip_level_count(1, 1), 1);
+        assert_eq!(MipmapGenerator::full_mip_level_count(0, 0), 1);
+        assert_eq!(MipmapGenerator::full_mip_level_count(32, 16), 6);
+        assert_eq!(MipmapGenerator::full_mip_level_count(33, 1), 6);
+    }
+}
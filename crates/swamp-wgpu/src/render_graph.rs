@@ -0,0 +1,758 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+use crate::gpu_timer::GpuTimer;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use wgpu::{CommandEncoder, ComputePass, RenderPass, Texture, TextureFormat, TextureView};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TextureId(usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PassId(usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TextureSize {
+    /// Same size as the surface
+    Surface,
+    /// Surface size divided by the factor, e.g. for downsampled blur passes
+    SurfaceDivided(u32),
+    Fixed(u32, u32),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TransientTextureDesc {
+    pub size: TextureSize,
+    /// `None` uses the surface format
+    pub format: Option<TextureFormat>,
+}
+
+impl TransientTextureDesc {
+    pub fn surface_sized() -> Self {
+        Self {
+            size: TextureSize::Surface,
+            format: None,
+        }
+    }
+
+    pub fn depth() -> Self {
+        Self {
+            size: TextureSize::Surface,
+            format: Some(TextureFormat::Depth32Float),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorTarget {
+    Surface,
+    Texture(TextureId),
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderPassDesc {
+    pub label: String,
+    pub color: ColorTarget,
+    /// `None` loads the previous contents of the color target
+    pub clear: Option<wgpu::Color>,
+    pub depth: Option<TextureId>,
+    /// `None` loads the depth written by an earlier pass
+    pub depth_clear: Option<f32>,
+    /// Transient textures sampled by this pass. The pass is scheduled after the last pass
+    /// added before it that writes to them, see [`RenderGraph::execution_order`].
+    pub reads: Vec<TextureId>,
+    pub after: Vec<PassId>,
+}
+
+impl RenderPassDesc {
+    pub fn new(label: &str, color: ColorTarget) -> Self {
+        Self {
+            label: label.to_string(),
+            color,
+            clear: None,
+            depth: None,
+            depth_clear: Some(1.0),
+            reads: Vec::new(),
+            after: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ComputePassDesc {
+    pub label: String,
+    pub reads: Vec<TextureId>,
+    pub after: Vec<PassId>,
+}
+
+impl ComputePassDesc {
+    pub fn new(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            reads: Vec::new(),
+            after: Vec::new(),
+        }
+    }
+}
+
+/// Identifies a texture of a [`TransientTexturePool`]. The pool hands out the same texture
+/// and view again as long as the id is the same, e.g. for keeping bind groups between frames.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TransientViewId(u64);
+
+/// Everything a pass needs while recording, besides the pass itself.
+pub struct PassContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub surface_format: TextureFormat,
+    pub surface_size: (u32, u32),
+    textures: &'a [PooledTexture],
+}
+
+impl PassContext<'_> {
+    pub fn texture_view(&self, id: TextureId) -> &TextureView {
+        &self.textures[id.0].view
+    }
+
+    pub fn texture_view_id(&self, id: TextureId) -> TransientViewId {
+        self.textures[id.0].id
+    }
+
+    pub fn texture_format(&self, id: TextureId) -> TextureFormat {
+        self.textures[id.0].texture.format()
+    }
+}
+
+/// What passes write to, the surface is ordered like a texture.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Resource {
+    Surface,
+    Texture(TextureId),
+}
+
+type RenderFn<'a> = Box<dyn for<'p> FnOnce(&mut RenderPass<'p>, &PassContext) + 'a>;
+type ComputeFn<'a> = Box<dyn for<'p> FnOnce(&mut ComputePass<'p>, &PassContext) + 'a>;
+
+enum PassKind<'a> {
+    Render(RenderPassDesc, RenderFn<'a>),
+    Compute(ComputePassDesc, ComputeFn<'a>),
+}
+
+impl PassKind<'_> {
+    fn label(&self) -> &str {
+        match self {
+            PassKind::Render(desc, _) => &desc.label,
+            PassKind::Compute(desc, _) => &desc.label,
+        }
+    }
+
+    fn reads(&self) -> &[TextureId] {
+        match self {
+            PassKind::Render(desc, _) => &desc.reads,
+            PassKind::Compute(desc, _) => &desc.reads,
+        }
+    }
+
+    fn after(&self) -> &[PassId] {
+        match self {
+            PassKind::Render(desc, _) => &desc.after,
+            PassKind::Compute(desc, _) => &desc.after,
+        }
+    }
+
+    fn writes(&self) -> Vec<Resource> {
+        match self {
+            PassKind::Render(desc, _) => {
+                let color = match desc.color {
+                    ColorTarget::Surface => Resource::Surface,
+                    ColorTarget::Texture(id) => Resource::Texture(id),
+                };
+                std::iter::once(color)
+                    .chain(desc.depth.map(Resource::Texture))
+                    .collect()
+            }
+            PassKind::Compute(..) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenderGraphError {
+    TextureNeverWritten { pass: String, texture: TextureId },
+    UnknownTexture { pass: String, texture: TextureId },
+    UnknownPass { pass: String, after: PassId },
+    Cycle { passes: Vec<String> },
+}
+
+impl Display for RenderGraphError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TextureNeverWritten { pass, texture } => {
+                write!(f, "pass '{pass}' reads {texture:?} which no pass writes")
+            }
+            Self::UnknownTexture { pass, texture } => {
+                write!(f, "pass '{pass}' refers to unknown {texture:?}")
+            }
+            Self::UnknownPass { pass, after } => {
+                write!(f, "pass '{pass}' depends on unknown {after:?}")
+            }
+            Self::Cycle { passes } => write!(f, "cycle between passes {passes:?}"),
+        }
+    }
+}
+
+impl std::error::Error for RenderGraphError {}
+
+/// The surface texture that the final pass(es) render into.
+pub struct SurfaceTarget<'a> {
+    pub view: &'a TextureView,
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A list of passes for a single frame. Built every frame, the transient textures
+/// are kept in a [`TransientTexturePool`] so they are reused across frames.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    textures: Vec<TransientTextureDesc>,
+    passes: Vec<PassKind<'a>>,
+}
+
+impl Debug for RenderGraph<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RenderGraph")
+            .field("textures", &self.textures)
+            .field(
+                "passes",
+                &self.passes.iter().map(PassKind::label).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create_texture(&mut self, desc: TransientTextureDesc) -> TextureId {
+        self.textures.push(desc);
+        TextureId(self.textures.len() - 1)
+    }
+
+    pub fn add_render_pass(
+        &mut self,
+        desc: RenderPassDesc,
+        run: impl for<'p> FnOnce(&mut RenderPass<'p>, &PassContext) + 'a,
+    ) -> PassId {
+        self.passes.push(PassKind::Render(desc, Box::new(run)));
+        PassId(self.passes.len() - 1)
+    }
+
+    pub fn add_compute_pass(
+        &mut self,
+        desc: ComputePassDesc,
+        run: impl for<'p> FnOnce(&mut ComputePass<'p>, &PassContext) + 'a,
+    ) -> PassId {
+        self.passes.push(PassKind::Compute(desc, Box::new(run)));
+        PassId(self.passes.len() - 1)
+    }
+
+    pub fn pass_count(&self) -> usize {
+        self.passes.len()
+    }
+
+    /// Returns the pass indices in execution order.
+    ///
+    /// A pass runs after the passes it explicitly depends on and after the last pass added
+    /// before it that writes to a texture it reads. Passes added later that write to that
+    /// texture run after the reader. A texture that is only written by passes added later is
+    /// read after all of them. Passes writing to the same target, including the surface,
+    /// keep the order they were added in, and other ties are broken by insertion order.
+    pub fn execution_order(&self) -> Result<Vec<usize>, RenderGraphError> {
+        let pass_count = self.passes.len();
+        let mut dependencies: Vec<Vec<usize>> = vec![Vec::new(); pass_count];
+        let mut writers: HashMap<Resource, Vec<usize>> = HashMap::new();
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            for resource in pass.writes() {
+                if let Resource::Texture(texture) = resource {
+                    if texture.0 >= self.textures.len() {
+                        return Err(RenderGraphError::UnknownTexture {
+                            pass: pass.label().to_string(),
+                            texture,
+                        });
+                    }
+                }
+                let previous_writers = writers.entry(resource).or_default();
+                dependencies[index].extend(previous_writers.iter().copied());
+                previous_writers.push(index);
+            }
+        }
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            for &texture in pass.reads() {
+                if texture.0 >= self.textures.len() {
+                    return Err(RenderGraphError::UnknownTexture {
+                        pass: pass.label().to_string(),
+                        texture,
+                    });
+                }
+                let texture_writers: Vec<usize> = writers
+                    .get(&Resource::Texture(texture))
+                    .into_iter()
+                    .flatten()
+                    .copied()
+                    .filter(|&writer| writer != index)
+                    .collect();
+                if texture_writers.is_empty() {
+                    return Err(RenderGraphError::TextureNeverWritten {
+                        pass: pass.label().to_string(),
+                        texture,
+                    });
+                }
+                match texture_writers.iter().rposition(|&writer| writer < index) {
+                    Some(position) => {
+                        dependencies[index].push(texture_writers[position]);
+                        for &later_writer in &texture_writers[position + 1..] {
+                            dependencies[later_writer].push(index);
+                        }
+                    }
+                    None => dependencies[index].extend(texture_writers),
+                }
+            }
+            for &after in pass.after() {
+                if after.0 >= pass_count {
+                    return Err(RenderGraphError::UnknownPass {
+                        pass: pass.label().to_string(),
+                        after,
+                    });
+                }
+                dependencies[index].push(after.0);
+            }
+        }
+
+        let mut order = Vec::with_capacity(pass_count);
+        let mut done = vec![false; pass_count];
+        while order.len() < pass_count {
+            let next = (0..pass_count)
+                .find(|&index| !done[index] && dependencies[index].iter().all(|&dep| done[dep]));
+            match next {
+                Some(index) => {
+                    done[index] = true;
+                    order.push(index);
+                }
+                None => {
+                    return Err(RenderGraphError::Cycle {
+                        passes: (0..pass_count)
+                            .filter(|&index| !done[index])
+                            .map(|index| self.passes[index].label().to_string())
+                            .collect(),
+                    })
+                }
+            }
+        }
+
+        Ok(order)
+    }
+
+    pub fn record(
+        self,
+        encoder: &mut CommandEncoder,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface: &SurfaceTarget,
+        pool: &mut TransientTexturePool,
+    ) -> Result<(), RenderGraphError> {
+        self.record_timed(encoder, device, queue, surface, pool, None)
+    }
+
+    /// Like [`RenderGraph::record`], with timestamps around each pass when there is a timer.
+    pub fn record_timed(
+        self,
+        encoder: &mut CommandEncoder,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface: &SurfaceTarget,
+        pool: &mut TransientTexturePool,
+        mut timer: Option<&mut GpuTimer>,
+    ) -> Result<(), RenderGraphError> {
+        let order = self.execution_order()?;
+
+        let mut available = std::mem::take(&mut pool.textures);
+        let mut keys = Vec::with_capacity(self.textures.len());
+        let mut allocated = Vec::with_capacity(self.textures.len());
+        for desc in &self.textures {
+            let key = PoolKey::new(desc, surface);
+            let texture = available
+                .get_mut(&key)
+                .and_then(Vec::pop)
+                .unwrap_or_else(|| pool.create_texture(device, &key));
+            keys.push(key);
+            allocated.push(texture);
+        }
+
+        let context = PassContext {
+            device,
+            queue,
+            surface_format: surface.format,
+            surface_size: (surface.width, surface.height),
+            textures: &allocated,
+        };
+
+        let mut passes: Vec<Option<PassKind>> = self.passes.into_iter().map(Some).collect();
+        for index in order {
+            match passes[index].take().expect("each pass is executed once") {
+                PassKind::Render(desc, run) => {
+                    let color_view = match desc.color {
+                        ColorTarget::Surface => surface.view,
+                        ColorTarget::Texture(id) => &allocated[id.0].view,
+                    };
+                    let load = desc.clear.map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear);
+                    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some(&desc.label),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: color_view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load,
+                                store: wgpu::StoreOp::Store,
+                            },
+                        })],
+                        depth_stencil_attachment: desc.depth.map(|id| {
+                            wgpu::RenderPassDepthStencilAttachment {
+                                view: &allocated[id.0].view,
+                                depth_ops: Some(wgpu::Operations {
+                                    load: desc
+                                        .depth_clear
+                                        .map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear),
+                                    store: wgpu::StoreOp::Store,
+                                }),
+                                stencil_ops: None,
+                            }
+                        }),
+                        timestamp_writes: timer
+                            .as_deref_mut()
+                            .and_then(|timer| timer.render_pass_writes(&desc.label)),
+                        occlusion_query_set: None,
+                    });
+                    run(&mut render_pass, &context);
+                }
+                PassKind::Compute(desc, run) => {
+                    let mut compute_pass =
+                        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                            label: Some(&desc.label),
+                            timestamp_writes: timer
+                                .as_deref_mut()
+                                .and_then(|timer| timer.compute_pass_writes(&desc.label)),
+                        });
+                    run(&mut compute_pass, &context);
+                }
+            }
+        }
+
+        // Only keep the textures used this frame, the rest are released. Pushed in reverse,
+        // so the same graph gets the same textures next frame.
+        for (key, texture) in keys.into_iter().zip(allocated).rev() {
+            pool.textures.entry(key).or_default().push(texture);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct PoolKey {
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+}
+
+impl PoolKey {
+    fn new(desc: &TransientTextureDesc, surface: &SurfaceTarget) -> Self {
+        let (width, height) = match desc.size {
+            TextureSize::Surface => (surface.width, surface.height),
+            TextureSize::SurfaceDivided(factor) => (
+                (surface.width / factor.max(1)).max(1),
+                (surface.height / factor.max(1)).max(1),
+            ),
+            TextureSize::Fixed(width, height) => (width, height),
+        };
+        Self {
+            width,
+            height,
+            format: desc.format.unwrap_or(surface.format),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PooledTexture {
+    id: TransientViewId,
+    texture: Texture,
+    view: TextureView,
+}
+
+/// Keeps the transient render targets alive between frames.
+#[derive(Debug, Default)]
+pub struct TransientTexturePool {
+    textures: HashMap<PoolKey, Vec<PooledTexture>>,
+    next_id: u64,
+}
+
+impl TransientTexturePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn texture_count(&self) -> usize {
+        self.textures.values().map(Vec::len).sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.textures.clear();
+    }
+
+    fn create_texture(&mut self, device: &wgpu::Device, key: &PoolKey) -> PooledTexture {
+        let texture = create_transient_texture(device, key);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.next_id += 1;
+
+        PooledTexture {
+            id: TransientViewId(self.next_id),
+            texture,
+            view,
+        }
+    }
+}
+
+fn create_transient_texture(device: &wgpu::Device, key: &PoolKey) -> Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("transient render target"),
+        size: wgpu::Extent3d {
+            width: key.width,
+            height: key.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: key.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_pass(graph: &mut RenderGraph, desc: RenderPassDesc) -> PassId {
+        graph.add_render_pass(desc, |_, _| {})
+    }
+
+    fn reading(label: &str, color: ColorTarget, reads: &[TextureId]) -> RenderPassDesc {
+        let mut desc = RenderPassDesc::new(label, color);
+        desc.reads = reads.to_vec();
+        desc
+    }
+
+    #[test]
+    fn independent_passes_keep_insertion_order() {
+        let mut graph = RenderGraph::new();
+        let target = graph.create_texture(TransientTextureDesc::surface_sized());
+        render_pass(
+            &mut graph,
+            RenderPassDesc::new("a", ColorTarget::Texture(target)),
+        );
+        render_pass(
+            &mut graph,
+            RenderPassDesc::new("b", ColorTarget::Texture(target)),
+        );
+        render_pass(&mut graph, reading("c", ColorTarget::Surface, &[target]));
+
+        assert_eq!(graph.execution_order().unwrap(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn readers_run_after_writers_added_later() {
+        let mut graph = RenderGraph::new();
+        let scene = graph.create_texture(TransientTextureDesc::surface_sized());
+        let bloom = graph.create_texture(TransientTextureDesc::surface_sized());
+        render_pass(
+            &mut graph,
+            reading("present", ColorTarget::Surface, &[bloom]),
+        );
+        render_pass(
+            &mut graph,
+            reading("bloom", ColorTarget::Texture(bloom), &[scene]),
+        );
+        render_pass(
+            &mut graph,
+            RenderPassDesc::new("scene", ColorTarget::Texture(scene)),
+        );
+
+        assert_eq!(graph.execution_order().unwrap(), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn surface_writers_keep_insertion_order() {
+        let mut graph = RenderGraph::new();
+        let scene = graph.create_texture(TransientTextureDesc::surface_sized());
+        render_pass(
+            &mut graph,
+            reading("composite", ColorTarget::Surface, &[scene]),
+        );
+        render_pass(&mut graph, RenderPassDesc::new("ui", ColorTarget::Surface));
+        render_pass(
+            &mut graph,
+            RenderPassDesc::new("scene", ColorTarget::Texture(scene)),
+        );
+
+        assert_eq!(graph.execution_order().unwrap(), vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn reused_texture_is_read_before_it_is_written_again() {
+        let mut graph = RenderGraph::new();
+        let scratch = graph.create_texture(TransientTextureDesc::surface_sized());
+        let lookup = graph.create_texture(TransientTextureDesc::surface_sized());
+        let first = graph.create_texture(TransientTextureDesc::surface_sized());
+        let second = graph.create_texture(TransientTextureDesc::surface_sized());
+        render_pass(
+            &mut graph,
+            reading("present", ColorTarget::Surface, &[first, second]),
+        );
+        render_pass(
+            &mut graph,
+            RenderPassDesc::new("stage 1", ColorTarget::Texture(scratch)),
+        );
+        // Waits for a pass added last, stage 2 must not overwrite scratch before it
+        render_pass(
+            &mut graph,
+            reading("copy 1", ColorTarget::Texture(first), &[scratch, lookup]),
+        );
+        render_pass(
+            &mut graph,
+            RenderPassDesc::new("stage 2", ColorTarget::Texture(scratch)),
+        );
+        render_pass(
+            &mut graph,
+            reading("copy 2", ColorTarget::Texture(second), &[scratch]),
+        );
+        render_pass(
+            &mut graph,
+            RenderPassDesc::new("lookup", ColorTarget::Texture(lookup)),
+        );
+
+        assert_eq!(graph.execution_order().unwrap(), vec![1, 5, 2, 3, 4, 0]);
+    }
+
+    #[test]
+    fn explicit_dependencies_are_respected() {
+        let mut graph = RenderGraph::new();
+        render_pass(
+            &mut graph,
+            RenderPassDesc::new("first", ColorTarget::Surface),
+        );
+        let mut desc = RenderPassDesc::new("second", ColorTarget::Surface);
+        desc.after = vec![PassId(2)];
+        render_pass(&mut graph, desc);
+        graph.add_compute_pass(ComputePassDesc::new("compute"), |_, _| {});
+
+        assert_eq!(graph.execution_order().unwrap(), vec![0, 2, 1]);
+    }
+
+    #[test]
+    fn depth_writers_are_ordered() {
+        let mut graph = RenderGraph::new();
+        let depth = graph.create_texture(TransientTextureDesc::depth());
+        let mut first = RenderPassDesc::new("opaque", ColorTarget::Surface);
+        first.depth = Some(depth);
+        let mut second = RenderPassDesc::new("decals", ColorTarget::Surface);
+        second.depth = Some(depth);
+        second.depth_clear = None;
+        render_pass(&mut graph, first);
+        render_pass(&mut graph, second);
+
+        assert_eq!(graph.execution_order().unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn cycle_is_an_error() {
+        let mut graph = RenderGraph::new();
+        let a = graph.create_texture(TransientTextureDesc::surface_sized());
+        let b = graph.create_texture(TransientTextureDesc::surface_sized());
+        render_pass(
+            &mut graph,
+            RenderPassDesc::new("outside", ColorTarget::Surface),
+        );
+        render_pass(&mut graph, reading("a", ColorTarget::Texture(a), &[b]));
+        render_pass(&mut graph, reading("b", ColorTarget::Texture(b), &[a]));
+
+        assert_eq!(
+            graph.execution_order(),
+            Err(RenderGraphError::Cycle {
+                passes: vec!["a".to_string(), "b".to_string()]
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_texture_is_an_error() {
+        let mut graph = RenderGraph::new();
+        render_pass(
+            &mut graph,
+            RenderPassDesc::new("pass", ColorTarget::Texture(TextureId(3))),
+        );
+
+        assert_eq!(
+            graph.execution_order(),
+            Err(RenderGraphError::UnknownTexture {
+                pass: "pass".to_string(),
+                texture: TextureId(3)
+            })
+        );
+    }
+
+    #[test]
+    fn reading_an_unwritten_texture_is_an_error() {
+        let mut graph = RenderGraph::new();
+        let texture = graph.create_texture(TransientTextureDesc::surface_sized());
+        // Reading its own target does not count as written
+        render_pass(
+            &mut graph,
+            reading("pass", ColorTarget::Texture(texture), &[texture]),
+        );
+
+        assert_eq!(
+            graph.execution_order(),
+            Err(RenderGraphError::TextureNeverWritten {
+                pass: "pass".to_string(),
+                texture
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_pass_is_an_error() {
+        let mut graph = RenderGraph::new();
+        let mut desc = RenderPassDesc::new("pass", ColorTarget::Surface);
+        desc.after = vec![PassId(7)];
+        render_pass(&mut graph, desc);
+
+        assert_eq!(
+            graph.execution_order(),
+            Err(RenderGraphError::UnknownPass {
+                pass: "pass".to_string(),
+                after: PassId(7)
+            })
+        );
+    }
+}
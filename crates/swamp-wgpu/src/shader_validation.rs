@@ -0,0 +1,549 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/swamp-render
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+
+use std::fmt::{Display, Formatter};
+use wgpu::ShaderModule;
+
+/// Where in the WGSL source a problem was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderDiagnostic {
+    pub label: String,
+    pub message: String,
+    /// 1-based line number
+    pub line: Option<u32>,
+    /// 1-based column in octets
+    pub column: Option<u32>,
+    /// Human readable report, with the offending source line
+    pub rendered: String,
+}
+
+impl ShaderDiagnostic {
+    /// Moves the location to be relative to `source`, which followed `prelude_lines` lines
+    /// in the validated source, and renders the report again against `source`.
+    /// Problems inside the prelude lose their location.
+    #[must_use]
+    pub fn without_prelude(mut self, prelude_lines: u32, source: &str) -> Self {
+        self.line = self
+            .line
+            .and_then(|line| line.checked_sub(prelude_lines))
+            .filter(|line| *line > 0);
+        if self.line.is_none() {
+            self.column = None;
+        }
+        self.rendered = self.render(source);
+        self
+    }
+
+    fn render(&self, source: &str) -> String {
+        let mut rendered = format!("error: {}\n", self.message);
+        let Some(line) = self.line else {
+            rendered.push_str(&format!("  --> {}\n", self.label));
+            return rendered;
+        };
+        let column = self.column.unwrap_or(1);
+        let gutter = " ".repeat(line.to_string().len());
+        let text = source.lines().nth(line as usize - 1).unwrap_or_default();
+        rendered.push_str(&format!("{gutter}--> {}:{line}:{column}\n", self.label));
+        rendered.push_str(&format!("{gutter} |\n{line} | {text}\n"));
+        rendered.push_str(&format!(
+            "{gutter} | {}^\n",
+            " ".repeat(column.saturating_sub(1) as usize)
+        ));
+        rendered
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+    Compute,
+}
+
+impl From<ShaderStage> for naga::ShaderStage {
+    fn from(stage: ShaderStage) -> Self {
+        match stage {
+            ShaderStage::Vertex => Self::Vertex,
+            ShaderStage::Fragment => Self::Fragment,
+            ShaderStage::Compute => Self::Compute,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BindingKind {
+    UniformBuffer,
+    Texture2d,
+    Sampler,
+    Other,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ExpectedBinding {
+    pub group: u32,
+    pub binding: u32,
+    pub kind: BindingKind,
+}
+
+impl ExpectedBinding {
+    pub const fn new(group: u32, binding: u32, kind: BindingKind) -> Self {
+        Self {
+            group,
+            binding,
+            kind,
+        }
+    }
+}
+
+/// What a pipeline expects from a shader module.
+#[derive(Debug, Clone)]
+pub struct ShaderInterface<'a> {
+    pub entry_point: &'a str,
+    pub stage: ShaderStage,
+    /// Every resource binding the shader declares must be one of these
+    pub bindings: &'a [ExpectedBinding],
+    /// Vertex input locations provided by the vertex buffer layouts
+    pub vertex_locations: Option<&'a [u32]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShaderError {
+    Parse(ShaderDiagnostic),
+    Validation(ShaderDiagnostic),
+    MissingEntryPoint {
+        label: String,
+        name: String,
+        stage: ShaderStage,
+    },
+    UnexpectedBinding {
+        label: String,
+        group: u32,
+        binding: u32,
+        expected: Option<BindingKind>,
+        found: BindingKind,
+    },
+    UnknownVertexInput {
+        label: String,
+        location: u32,
+    },
+}
+
+impl Display for ShaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Parse(diagnostic) | Self::Validation(diagnostic) => {
+                write!(f, "{}", diagnostic.rendered)
+            }
+            Self::MissingEntryPoint { label, name, stage } => {
+                write!(f, "{label}: missing {stage:?} entry point '{name}'")
+            }
+            Self::UnexpectedBinding {
+                label,
+                group,
+                binding,
+                expected,
+                found,
+            } => match expected {
+                Some(expected) => write!(
+                    f,
+                    "{label}: @group({group}) @binding({binding}) is {found:?}, expected {expected:?}"
+                ),
+                None => write!(
+                    f,
+                    "{label}: @group({group}) @binding({binding}) ({found:?}) is not provided by the pipeline"
+                ),
+            },
+            Self::UnknownVertexInput { label, location } => {
+                write!(
+                    f,
+                    "{label}: vertex input @location({location}) is not provided by the vertex layout"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+/// Parses and validates WGSL on the CPU, without involving the device.
+pub fn validate_wgsl(label: &str, source: &str) -> Result<naga::Module, ShaderError> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|err| {
+        let location = err.location(source);
+        ShaderError::Parse(ShaderDiagnostic {
+            label: label.to_string(),
+            message: err.message().to_string(),
+            line: location.map(|location| location.line_number),
+            column: location.map(|location| location.line_position),
+            rendered: err.emit_to_string_with_path(source, label),
+        })
+    })?;
+
+    let mut validator = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::default(),
+    );
+    validator.validate(&module).map_err(|err| {
+        let location = err.location(source);
+        ShaderError::Validation(ShaderDiagnostic {
+            label: label.to_string(),
+            message: err.as_inner().to_string(),
+            line: location.map(|location| location.line_number),
+            column: location.map(|location| location.line_position),
+            rendered: err.emit_to_string_with_path(source, label),
+        })
+    })?;
+
+    Ok(module)
+}
+
+/// Checks that the module has the entry point and only uses the bindings in the interface.
+pub fn check_interface(
+    label: &str,
+    module: &naga::Module,
+    interface: &ShaderInterface,
+) -> Result<(), ShaderError> {
+    let stage: naga::ShaderStage = interface.stage.into();
+    let entry_point = module
+        .entry_points
+        .iter()
+        .find(|entry_point| entry_point.name == interface.entry_point && entry_point.stage == stage)
+        .ok_or_else(|| ShaderError::MissingEntryPoint {
+            label: label.to_string(),
+            name: interface.entry_point.to_string(),
+            stage: interface.stage,
+        })?;
+
+    for (_, variable) in module.global_variables.iter() {
+        let Some(resource_binding) = &variable.binding else {
+            continue;
+        };
+        let found = binding_kind(module, variable);
+        let expected = interface
+            .bindings
+            .iter()
+            .find(|expected| {
+                expected.group == resource_binding.group
+                    && expected.binding == resource_binding.binding
+            })
+            .map(|expected| expected.kind);
+        if expected != Some(found) {
+            return Err(ShaderError::UnexpectedBinding {
+                label: label.to_string(),
+                group: resource_binding.group,
+                binding: resource_binding.binding,
+                expected,
+                found,
+            });
+        }
+    }
+
+    if let Some(vertex_locations) = interface.vertex_locations {
+        for location in input_locations(module, &entry_point.function) {
+            if !vertex_locations.contains(&location) {
+                return Err(ShaderError::UnknownVertexInput {
+                    label: label.to_string(),
+                    location,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Validates the source and checks it against the interface before handing it to the device,
+/// so errors are reported as a [`ShaderError`] instead of a device panic.
+pub fn create_shader_module_checked(
+    device: &wgpu::Device,
+    label: &str,
+    source: &str,
+    interface: &ShaderInterface,
+) -> Result<ShaderModule, ShaderError> {
+    let module = validate_wgsl(label, source)?;
+    check_interface(label, &module, interface)?;
+
+    Ok(crate::create_shader_module(device, label, source))
+}
+
+fn binding_kind(module: &naga::Module, variable: &naga::GlobalVariable) -> BindingKind {
+    if variable.space == naga::AddressSpace::Uniform {
+        return BindingKind::UniformBuffer;
+    }
+
+    match module.types[variable.ty].inner {
+        naga::TypeInner::Image {
+            dim: naga::ImageDimension::D2,
+            arrayed: false,
+            class:
+                naga::ImageClass::Sampled {
+                    kind: naga::ScalarKind::Float,
+                    multi: false,
+                },
+        } => BindingKind::Texture2d,
+        naga::TypeInner::Sampler { comparison: false } => BindingKind::Sampler,
+        _ => BindingKind::Other,
+    }
+}
+
+fn input_locations(module: &naga::Module, function: &naga::Function) -> Vec<u32> {
+    let mut locations = Vec::new();
+    for argument in &function.arguments {
+        match &argument.binding {
+            Some(naga::Binding::Location { location, .. }) => locations.push(*location),
+            Some(naga::Binding::BuiltIn(_)) => {}
+            None => {
+                if let naga::TypeInner::Struct { members, .. } = &module.types[argument.ty].inner {
+                    locations.extend(members.iter().filter_map(|member| match member.binding {
+                        Some(naga::Binding::Location { location, .. }) => Some(location),
+                        _ => None,
+                    }));
+                }
+            }
+        }
+    }
+    locations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = "
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+};
+
+@group(0) @binding(0) var<uniform> scale: vec4<f32>;
+@group(1) @binding(0) var diffuse: texture_2d<f32>;
+@group(1) @binding(1) var diffuse_sampler: sampler;
+
+@vertex
+fn vs_main(input: VertexInput) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(input.position * scale.xy, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return textureSample(diffuse, diffuse_sampler, vec2<f32>(0.5, 0.5));
+}
+";
+
+    const BINDINGS: &[ExpectedBinding] = &[
+        ExpectedBinding::new(0, 0, BindingKind::UniformBuffer),
+        ExpectedBinding::new(1, 0, BindingKind::Texture2d),
+        ExpectedBinding::new(1, 1, BindingKind::Sampler),
+    ];
+
+    fn interface(
+        entry_point: &'static str,
+        stage: ShaderStage,
+        bindings: &'static [ExpectedBinding],
+    ) -> ShaderInterface<'static> {
+        ShaderInterface {
+            entry_point,
+            stage,
+            bindings,
+            vertex_locations: (stage == ShaderStage::Vertex).then_some(&[0, 1]),
+        }
+    }
+
+    fn check(source: &str, interface: &ShaderInterface) -> Result<(), ShaderError> {
+        let module = validate_wgsl("test", source).expect("valid wgsl");
+        check_interface("test", &module, interface)
+    }
+
+    #[test]
+    fn accepts_matching_interface() {
+        check(SHADER, &interface("vs_main", ShaderStage::Vertex, BINDINGS)).unwrap();
+        check(
+            SHADER,
+            &interface("fs_main", ShaderStage::Fragment, BINDINGS),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn parse_error_has_line_and_column() {
+        let source = "fn main() {\n    let x = 1.0\n    let y = 2.0;\n}\n";
+        let Err(ShaderError::Parse(diagnostic)) = validate_wgsl("broken", source) else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(diagnostic.label, "broken");
+        assert_eq!(diagnostic.line, Some(3));
+        assert_eq!(diagnostic.column, Some(5));
+        assert!(diagnostic.rendered.contains("broken"));
+    }
+
+    #[test]
+    fn validation_error_is_reported() {
+        let source = "fn main() -> f32 {\n    return vec2<f32>(1.0, 2.0);\n}\n";
+        let Err(ShaderError::Validation(diagnostic)) = validate_wgsl("invalid", source) else {
+            panic!("expected a validation error");
+        };
+        assert_eq!(diagnostic.label, "invalid");
+        assert!(diagnostic.line.is_some());
+    }
+
+    #[test]
+    fn missing_entry_points() {
+        let without_vertex = SHADER.replace("fn vs_main", "fn other_vs_main");
+        assert_eq!(
+            check(
+                &without_vertex,
+                &interface("vs_main", ShaderStage::Vertex, BINDINGS)
+            ),
+            Err(ShaderError::MissingEntryPoint {
+                label: "test".to_string(),
+                name: "vs_main".to_string(),
+                stage: ShaderStage::Vertex,
+            })
+        );
+
+        let without_fragment = SHADER.replace("fn fs_main", "fn other_fs_main");
+        assert_eq!(
+            check(
+                &without_fragment,
+                &interface("fs_main", ShaderStage::Fragment, BINDINGS)
+            ),
+            Err(ShaderError::MissingEntryPoint {
+                label: "test".to_string(),
+                name: "fs_main".to_string(),
+                stage: ShaderStage::Fragment,
+            })
+        );
+    }
+
+    #[test]
+    fn entry_point_must_have_the_stage() {
+        assert!(matches!(
+            check(
+                SHADER,
+                &interface("vs_main", ShaderStage::Fragment, BINDINGS)
+            ),
+            Err(ShaderError::MissingEntryPoint { .. })
+        ));
+    }
+
+    #[test]
+    fn wrong_binding_kind() {
+        const SWAPPED: &[ExpectedBinding] = &[
+            ExpectedBinding::new(0, 0, BindingKind::UniformBuffer),
+            ExpectedBinding::new(1, 0, BindingKind::Sampler),
+            ExpectedBinding::new(1, 1, BindingKind::Texture2d),
+        ];
+        assert_eq!(
+            check(
+                SHADER,
+                &interface("fs_main", ShaderStage::Fragment, SWAPPED)
+            ),
+            Err(ShaderError::UnexpectedBinding {
+                label: "test".to_string(),
+                group: 1,
+                binding: 0,
+                expected: Some(BindingKind::Sampler),
+                found: BindingKind::Texture2d,
+            })
+        );
+    }
+
+    #[test]
+    fn wrong_group() {
+        let source = SHADER.replace("@group(0) @binding(0)", "@group(2) @binding(0)");
+        assert_eq!(
+            check(
+                &source,
+                &interface("fs_main", ShaderStage::Fragment, BINDINGS)
+            ),
+            Err(ShaderError::UnexpectedBinding {
+                label: "test".to_string(),
+                group: 2,
+                binding: 0,
+                expected: None,
+                found: BindingKind::UniformBuffer,
+            })
+        );
+    }
+
+    #[test]
+    fn wrong_binding() {
+        let source = SHADER.replace(
+            "@group(1) @binding(1) var diffuse_sampler",
+            "@group(1) @binding(3) var diffuse_sampler",
+        );
+        assert_eq!(
+            check(
+                &source,
+                &interface("fs_main", ShaderStage::Fragment, BINDINGS)
+            ),
+            Err(ShaderError::UnexpectedBinding {
+                label: "test".to_string(),
+                group: 1,
+                binding: 3,
+                expected: None,
+                found: BindingKind::Sampler,
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_vertex_location() {
+        let source = SHADER.replace("@location(1) uv", "@location(4) uv");
+        assert_eq!(
+            check(
+                &source,
+                &interface("vs_main", ShaderStage::Vertex, BINDINGS)
+            ),
+            Err(ShaderError::UnknownVertexInput {
+                label: "test".to_string(),
+                location: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn vertex_locations_are_not_checked_for_fragments() {
+        let source = SHADER.replace("@location(1) uv", "@location(4) uv");
+        check(
+            &source,
+            &interface("fs_main", ShaderStage::Fragment, BINDINGS),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn without_prelude_renders_against_the_source() {
+        let prelude = "const ONE: f32 = 1.0;\n";
+        let source = "fn main() {\n    let x = ONE\n    let y = 2.0;\n}\n";
+        let Err(ShaderError::Parse(diagnostic)) =
+            validate_wgsl("effect", &format!("{prelude}\n{source}"))
+        else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(diagnostic.line, Some(5));
+
+        let diagnostic = diagnostic.without_prelude(2, source);
+        assert_eq!(diagnostic.line, Some(3));
+        assert_eq!(diagnostic.column, Some(5));
+        assert!(diagnostic.rendered.contains("effect:3:5"));
+        assert!(diagnostic.rendered.contains("3 |     let y = 2.0;"));
+        assert!(!diagnostic.rendered.contains(":5:"));
+    }
+
+    #[test]
+    fn without_prelude_drops_locations_inside_the_prelude() {
+        let diagnostic = ShaderDiagnostic {
+            label: "effect".to_string(),
+            message: "bad".to_string(),
+            line: Some(2),
+            column: Some(4),
+            rendered: String::new(),
+        }
+        .without_prelude(2, "");
+        assert_eq!(diagnostic.line, None);
+        assert_eq!(diagnostic.column, None);
+        assert_eq!(diagnostic.rendered, "error: bad\n  --> effect\n");
+    }
+}